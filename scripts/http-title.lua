@@ -0,0 +1,21 @@
+-- Fetches the index page of web services and reports the page title
+description = "Reports the HTTP status, server header and page title of web services"
+categories = {"default", "safe", "discovery"}
+
+local tls_ports = { [443] = true, [8443] = true }
+
+if PORT then
+    local scheme = tls_ports[PORT] and "https" or "http"
+    local response = http.get(scheme .. "://" .. HOST .. ":" .. PORT .. "/", { timeout = 3000 })
+
+    if response then
+        local title = response.body:match("<[Tt][Ii][Tt][Ll][Ee][^>]*>%s*(.-)%s*</[Tt][Ii][Tt][Ll][Ee]>")
+        result = {
+            url = response.url,
+            status = response.status,
+            server = response.headers["server"],
+            title = title,
+        }
+        output = title and ("Title: " .. title) or ("HTTP " .. response.status)
+    end
+end
@@ -0,0 +1,20 @@
+-- Retrieves the certificate and supported protocol versions of TLS services
+description = "Reports the TLS certificate subject, issuer, validity and supported TLS versions"
+categories = {"default", "safe", "discovery"}
+
+if PORT then
+    local cert = tls.certificate(HOST, PORT, 3000)
+
+    if cert then
+        result = {
+            subject = cert.subject,
+            issuer = cert.issuer,
+            not_before = cert.not_before,
+            not_after = cert.not_after,
+            san = cert.san,
+            sha256 = cert.sha256,
+            versions = tls.versions(HOST, PORT, 3000),
+        }
+        output = "Certificate: " .. cert.subject
+    end
+end
@@ -1,4 +1,7 @@
 -- Simple test script for rmap Lua integration
+description = "Reports a guessed service for each open port and basic host information"
+categories = {"default", "safe"}
+
 log("Starting test script for host: " .. HOST)
 
 if PORT then
@@ -1,4 +1,6 @@
-use clap::Parser;
+use crate::core::TimingTemplate;
+use crate::dns::ReverseDns;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use num_cpus;
 
 #[derive(Parser, Debug)]
@@ -6,10 +8,22 @@ use num_cpus;
 #[command(about = "A fast network port scanner")]
 #[command(version)]
 pub struct Args {
-    /// Configuration file path. Note that CLI arguments override configuration file settings.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file path. Environment variables and CLI arguments override its settings.
     #[arg(long = "config")]
     pub config: Option<String>,
 
+    /// Named profile to start from: quick, full-tcp, udp-top, web-audit or one defined in the
+    /// config file or profiles directory. Other options override it
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Directory of profile files, one profile per file named after it (default: profiles)
+    #[arg(long = "profile-dir", value_name = "DIR")]
+    pub profiles_dir: Option<String>,
+
     /// Target IP address, domain, or CIDR range
     #[arg(short = 't', long = "target")]
     pub target: Vec<String>,
@@ -18,18 +32,64 @@ pub struct Args {
     #[arg(short = 'p', long = "ports")]
     pub ports: Option<String>,
 
+    /// Only show open ports, even for ports given explicitly with --ports
+    #[arg(long = "open", default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
+    pub open: bool,
+
     /// Enable TCP scanning
-    #[arg(long = "tcp", default_value = "true")]
+    #[arg(long = "tcp", default_value_t = true, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub tcp: bool,
 
     /// Enable UDP scanning
-    #[arg(long = "udp")]
+    #[arg(long = "udp", default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub udp: bool,
 
-    /// Timeout per probe in milliseconds
+    /// Hosts to look up names for with reverse DNS: all, live (hosts that answered) or never
+    #[arg(long = "reverse-dns", value_enum, value_name = "WHEN")]
+    pub reverse_dns: Option<ReverseDns>,
+
+    /// Timing template: 0-5 or paranoid, sneaky, polite, normal, aggressive, insane.
+    /// Sets parallelism, timeouts and retries, the options below override it
+    #[arg(short = 'T', long = "timing", value_name = "TEMPLATE")]
+    pub timing: Option<TimingTemplate>,
+
+    /// Timeout per probe in milliseconds until round trip times have been measured
     #[arg(long = "timeout", default_value = "2000")]
     pub timeout: u64,
 
+    /// Lower bound of the adaptive probe timeout in milliseconds
+    #[arg(long = "min-rtt-timeout", value_name = "MS")]
+    pub min_rtt_timeout: Option<u64>,
+
+    /// Upper bound of the adaptive probe timeout in milliseconds
+    #[arg(long = "max-rtt-timeout", value_name = "MS")]
+    pub max_rtt_timeout: Option<u64>,
+
+    /// How often a probe without an answer is sent again, each retry waiting twice as long
+    #[arg(long = "max-retries", value_name = "COUNT")]
+    pub max_retries: Option<u32>,
+
+    /// Probes per second the scan sends at least, starting probes beyond --threads to keep up,
+    /// and does not slow down below when probes get dropped
+    #[arg(long = "min-rate", value_name = "RATE")]
+    pub min_rate: Option<f64>,
+
+    /// Probes per second across every target and protocol
+    #[arg(long = "max-rate", value_name = "RATE")]
+    pub max_rate: Option<f64>,
+
+    /// Probes per second sent to a single target
+    #[arg(long = "max-host-rate", value_name = "RATE")]
+    pub max_host_rate: Option<f64>,
+
+    /// Probe ports in the order given and targets one after another instead of in random order
+    #[arg(short = 'r', long = "ordered", default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
+    pub ordered: bool,
+
+    /// Seed of the random probe order, the same seed scans in the same order
+    #[arg(long = "seed", value_name = "SEED", conflicts_with = "ordered")]
+    pub seed: Option<u64>,
+
     /// Number of concurrent tasks/threads
     #[arg(long = "threads", default_value_t = num_cpus::get())]
     pub threads: usize,
@@ -38,11 +98,139 @@ pub struct Args {
     #[arg(long = "json")]
     pub json: Option<String>,
 
-    /// Path to Lua script for service detection
-    #[arg(long = "lua-script")]
+    /// Write nmap-compatible XML output to a file (also accepted as -oX)
+    #[arg(long = "xml")]
+    pub xml: Option<String>,
+
+    /// Write grepable output, one line per host, to a file (also accepted as -oG)
+    #[arg(long = "grepable")]
+    pub grepable: Option<String>,
+
+    /// Write one comma-separated row per host and port to a file
+    #[arg(long = "csv")]
+    pub csv: Option<String>,
+
+    /// Write one tab-separated row per host and port to a file
+    #[arg(long = "tsv")]
+    pub tsv: Option<String>,
+
+    /// Write a self-contained HTML report to a file
+    #[arg(long = "html", value_name = "FILE")]
+    pub html: Option<String>,
+
+    /// Stream results as newline-delimited JSON events to a file, or `-` for stdout
+    #[arg(long = "ndjson", value_name = "FILE")]
+    pub ndjson: Option<String>,
+
+    /// Write JSON, XML and grepable output to <BASENAME>.json, .xml and .gnmap
+    /// (also accepted as -oA)
+    #[arg(long = "all-formats", value_name = "BASENAME")]
+    pub all_formats: Option<String>,
+
+    /// Probe open ports to detect the product and version of their services
+    #[arg(long = "service-version", default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
+    pub service_version: bool,
+
+    /// nmap-service-probes file used by --service-version instead of the built-in probes
+    #[arg(long = "service-probes", value_name = "FILE")]
+    pub service_probes: Option<String>,
+
+    /// Lua scripts to run: comma-separated names, globs, paths, directories, categories
+    /// or expressions such as "default and not intrusive"
+    #[arg(long = "lua-script", visible_alias = "script")]
     pub lua_script: Option<String>,
 
+    /// Directory containing Lua scripts
+    #[arg(long = "script-dir")]
+    pub script_dir: Option<String>,
+
+    /// Arguments passed to Lua scripts (e.g., user=admin,http-enum.path=/admin)
+    #[arg(long = "script-args")]
+    pub script_args: Option<String>,
+
+    /// File containing key=value script arguments, one per line
+    #[arg(long = "script-args-file")]
+    pub script_args_file: Option<String>,
+
+    /// Print the effective configuration and where each value came from, then exit
+    #[arg(long = "print-config")]
+    pub print_config: bool,
+
+    /// List available Lua scripts with their descriptions and exit
+    #[arg(long = "list-scripts")]
+    pub list_scripts: bool,
+
+    /// Brute-force directories and files on every open HTTP(S) port
+    #[arg(long = "web-enum", default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
+    pub web_enum: bool,
+
+    /// Wordlist for web content discovery (repeatable, default: scripts/wordlist.txt)
+    #[arg(long = "wordlist", value_delimiter = ',')]
+    pub wordlists: Vec<String>,
+
+    /// File extensions to try for every word (e.g., php,bak,txt)
+    #[arg(long = "extensions", value_delimiter = ',')]
+    pub web_extensions: Option<Vec<String>>,
+
+    /// HTTP status codes reported by web content discovery
+    #[arg(long = "web-status", value_delimiter = ',')]
+    pub web_status_codes: Option<Vec<u16>>,
+
+    /// Response sizes in bytes hidden from web content discovery results
+    #[arg(long = "web-exclude-size", value_delimiter = ',')]
+    pub web_exclude_sizes: Option<Vec<usize>>,
+
+    /// Concurrent requests per web service
+    #[arg(long = "web-threads")]
+    pub web_threads: Option<u64>,
+
     /// Enable verbose logging
-    #[arg(short = 'v', long = "verbose")]
+    #[arg(short = 'v', long = "verbose", default_value_t = false, num_args = 0..=1, default_missing_value = "true", action = ArgAction::Set)]
     pub verbose: bool,
+
+    /// Periodically save scan progress to a file, removed once the scan completes
+    #[arg(long = "checkpoint", value_name = "FILE")]
+    pub checkpoint: Option<String>,
+
+    /// Seconds between checkpoint writes
+    #[arg(long = "checkpoint-interval", value_name = "SECONDS")]
+    pub checkpoint_interval: Option<u64>,
+
+    /// Continue an interrupted scan from a checkpoint file, other scan options are taken
+    /// from the checkpoint except script arguments, which are not saved
+    #[arg(long = "resume", value_name = "FILE", conflicts_with_all = ["target", "config"])]
+    pub resume: Option<String>,
+}
+
+/// Commands that work on saved results instead of running a scan
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Render a saved JSON report as a self-contained HTML page
+    Report {
+        /// JSON report written by --json
+        input: String,
+
+        /// HTML file to write (default: the input file with an .html extension)
+        #[arg(short = 'o', long = "output")]
+        output: Option<String>,
+    },
+    /// Compare two saved JSON reports, exits with status 1 when they differ
+    Diff {
+        /// Earlier JSON report
+        old: String,
+
+        /// Later JSON report
+        new: String,
+
+        /// Output format
+        #[arg(long = "format", value_enum, default_value_t = DiffFormat::Table)]
+        format: DiffFormat,
+    },
+}
+
+/// How `rmap diff` prints its result
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Table,
+    Json,
 }
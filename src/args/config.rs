@@ -1,107 +1,756 @@
+use super::Command;
+use super::profile::ProfileDefinition;
+use crate::core::TimingTemplate;
+use crate::dns::ReverseDns;
+use crate::utils::check_ports;
 use num_cpus;
+use serde::de::{self, DeserializeOwned, Deserializer, IgnoredAny, Visitor};
+use serde::{Deserialize, Serialize};
 use serde_yaml;
-use std::fs;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+use std::{fmt, fs};
 
-#[derive(Debug, Clone, serde::Deserialize)]
+/// Every setting of a scan
+///
+/// Config files use the field names as keys, see `docs/configuration.md`. Some keys also
+/// accept the name of their command line option.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub target: Vec<String>,
+    /// Named profile the settings start from
+    pub profile: Option<String>,
+    /// Directory of profile files, one profile per file named after it
+    #[serde(alias = "profile_dir")]
+    pub profiles_dir: String,
+    /// Profiles defined in the config file
+    #[serde(skip_serializing)]
+    pub profiles: BTreeMap<String, ProfileDefinition>,
+    #[serde(deserialize_with = "port_list")]
     pub ports: String,
     #[serde(skip)]
     pub ports_explicitly_specified: bool,
+    /// Only list open ports in the result tables
+    #[serde(alias = "open")]
+    pub open_only: bool,
     pub tcp: bool,
     pub udp: bool,
+    /// Hosts whose names are looked up with reverse DNS
+    pub reverse_dns: ReverseDns,
+    /// Timing template the timing settings below started from
+    pub timing: Option<TimingTemplate>,
+    /// Timeout per probe in milliseconds until round trip times have been measured
     pub timeout: u64,
+    /// Lower bound of the adaptive probe timeout in milliseconds
+    pub min_rtt_timeout: u64,
+    /// Upper bound of the adaptive probe timeout in milliseconds
+    pub max_rtt_timeout: u64,
+    /// How often a probe without an answer is sent again
+    pub max_retries: u32,
+    /// Probes per second the scan sends at least, starting probes beyond `threads` to keep up,
+    /// and does not slow down below when probes get dropped
+    pub min_rate: Option<f64>,
+    /// Probes per second across every host and protocol
+    pub max_rate: Option<f64>,
+    /// Probes per second sent to a single host
+    pub max_host_rate: Option<f64>,
+    /// Probe ports in the order given and hosts one after another instead of randomizing
+    pub ordered: bool,
+    /// Seed of the randomized probe order, a random one is picked when the scan starts
+    pub seed: Option<u64>,
     pub threads: u64,
     pub json: Option<String>,
+    pub xml: Option<String>,
+    pub grepable: Option<String>,
+    pub csv: Option<String>,
+    pub tsv: Option<String>,
+    pub html: Option<String>,
+    /// Stream events as NDJSON to this file, `-` for stdout
+    pub ndjson: Option<String>,
+    /// Base name for JSON, XML and grepable output not given a file of their own
+    pub all_formats: Option<String>,
+    /// Detect the product and version of the services on open ports
+    pub service_version: bool,
+    /// Probes file in the format of nmap's `nmap-service-probes`, built-in probes otherwise
+    pub service_probes: Option<String>,
+    #[serde(alias = "script")]
     pub lua_script: Option<String>,
+    #[serde(alias = "script_dir")]
+    pub scripts_dir: String,
+    #[serde(deserialize_with = "script_arg_map")]
+    pub script_args: HashMap<String, String>,
+    /// File of `key=value` script arguments, loaded over `script_args`
+    pub script_args_file: Option<String>,
+    #[serde(skip)]
+    pub list_scripts: bool,
+    /// Subcommand to run instead of a scan
+    #[serde(skip)]
+    pub command: Option<Command>,
+    pub web_enum: bool,
+    #[serde(alias = "wordlist")]
+    pub wordlists: Vec<String>,
+    #[serde(alias = "extensions")]
+    pub web_extensions: Vec<String>,
+    #[serde(alias = "web_status")]
+    pub web_status_codes: Vec<u16>,
+    #[serde(alias = "web_exclude_size")]
+    pub web_exclude_sizes: Vec<usize>,
+    pub web_threads: u64,
     pub verbose: bool,
+    /// Keep a checkpoint of the scan progress in this file
+    pub checkpoint: Option<String>,
+    /// Seconds between checkpoint writes
+    pub checkpoint_interval: u64,
+    /// Continue the scan saved in this checkpoint file
+    #[serde(skip)]
+    pub resume: Option<String>,
+    /// Print the settings and where they came from instead of scanning
+    #[serde(skip)]
+    pub print_config: bool,
+    /// Where each setting came from, settings not listed have their default
+    #[serde(skip)]
+    pub sources: BTreeMap<String, ConfigSource>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             target: vec![],
+            profile: None,
+            profiles_dir: "profiles".to_string(),
+            profiles: BTreeMap::new(),
             ports: "1-1024".to_string(),
             ports_explicitly_specified: false,
+            open_only: false,
             tcp: true,
             udp: false,
+            reverse_dns: ReverseDns::Live,
+            timing: None,
             timeout: 2000,
+            min_rtt_timeout: 100,
+            max_rtt_timeout: 10000,
+            max_retries: 2,
+            min_rate: None,
+            max_rate: None,
+            max_host_rate: None,
+            ordered: false,
+            seed: None,
             threads: num_cpus::get() as u64,
             json: None,
+            xml: None,
+            grepable: None,
+            csv: None,
+            tsv: None,
+            html: None,
+            ndjson: None,
+            all_formats: None,
+            service_version: false,
+            service_probes: None,
             lua_script: None,
+            scripts_dir: "scripts".to_string(),
+            script_args: HashMap::new(),
+            script_args_file: None,
+            list_scripts: false,
+            command: None,
+            web_enum: false,
+            wordlists: vec!["scripts/wordlist.txt".to_string()],
+            web_extensions: vec![],
+            web_status_codes: vec![200, 204, 301, 302, 307, 308, 401, 403],
+            web_exclude_sizes: vec![],
+            web_threads: 10,
             verbose: false,
+            checkpoint: None,
+            checkpoint_interval: 30,
+            resume: None,
+            print_config: false,
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+/// Keys accepted besides the field names, named after command line options
+const KEY_ALIASES: [(&str, &str); 8] = [
+    ("profile_dir", "profiles_dir"),
+    ("open", "open_only"),
+    ("script", "lua_script"),
+    ("script_dir", "scripts_dir"),
+    ("wordlist", "wordlists"),
+    ("extensions", "web_extensions"),
+    ("web_status", "web_status_codes"),
+    ("web_exclude_size", "web_exclude_sizes"),
+];
+
+/// The field name a key stands for
+pub fn canonical_key(key: &str) -> &str {
+    KEY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key, |(_, field)| field)
+}
+
+/// Where a setting got its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    /// A config file
+    File(String),
+    /// An `RMAP_*` environment variable
+    Env(String),
+    CommandLine,
+    /// A timing template, for the settings it changed
+    Template(TimingTemplate),
+    /// A profile, named after the one that set the value when profiles inherit
+    Profile(String),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => f.write_str("default"),
+            ConfigSource::File(path) => write!(f, "file {}", path),
+            ConfigSource::Env(name) => write!(f, "environment {}", name),
+            ConfigSource::CommandLine => f.write_str("command line"),
+            ConfigSource::Template(template) => write!(f, "timing template {}", template),
+            ConfigSource::Profile(name) => write!(f, "profile {}", name),
+        }
+    }
+}
+
+/// A setting with a value that cannot be used
+#[derive(Debug)]
+pub struct InvalidValue {
+    /// The config file key of the setting
+    pub key: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid value for `{}`: {}", self.key, self.message)
+    }
+}
+
+impl Error for InvalidValue {}
+
+/// A config file that could not be loaded
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: String,
+    /// Line of the offending key or value, when known
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path, line, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
         }
     }
 }
 
+impl Error for ConfigError {}
+
+/// Config file formats, told apart by the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// YAML unless the file ends in `.toml` or `.json`
+    pub fn from_path(path: &str) -> ConfigFormat {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("toml") => ConfigFormat::Toml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+
+    /// Deserialize `content`, errors come with the line they were found on
+    pub fn parse<T: DeserializeOwned + Default>(
+        &self,
+        content: &str,
+    ) -> Result<T, (Option<usize>, String)> {
+        match self {
+            // An empty YAML document, or one with only comments, sets nothing
+            ConfigFormat::Yaml => match serde_yaml::from_str::<Option<T>>(content) {
+                Ok(value) => Ok(value.unwrap_or_default()),
+                Err(e) => Err((
+                    e.location().map(|location| location.line()),
+                    strip_location(&e.to_string()),
+                )),
+            },
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| {
+                let line = e
+                    .span()
+                    .map(|span| content[..span.start].matches('\n').count() + 1);
+                (line, e.message().to_string())
+            }),
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| {
+                let line = (e.line() > 0).then_some(e.line());
+                (line, strip_location(&e.to_string()))
+            }),
+        }
+    }
+
+    /// Line a top-level key is set on, found by looking for it at the start of a line
+    fn key_line(&self, content: &str, key: &str) -> Option<usize> {
+        content
+            .lines()
+            .position(|line| {
+                let rest = match self {
+                    ConfigFormat::Yaml | ConfigFormat::Toml => line.strip_prefix(key),
+                    ConfigFormat::Json => line
+                        .trim_start()
+                        .strip_prefix('"')
+                        .and_then(|rest| rest.strip_prefix(key))
+                        .and_then(|rest| rest.strip_prefix('"')),
+                };
+                let separator = if *self == ConfigFormat::Toml {
+                    '='
+                } else {
+                    ':'
+                };
+                rest.is_some_and(|rest| rest.trim_start().starts_with(separator))
+            })
+            .map(|index| index + 1)
+    }
+}
+
 impl Config {
-    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = fs::read_to_string(path)?;
-
-        // Parse YAML into a partial config that allows missing fields
-        let yaml_value: serde_yaml::Value = serde_yaml::from_str(&content)?;
-
-        // Start with default config
-        let mut config = Config::default();
-
-        // Merge YAML values over defaults
-        if let serde_yaml::Value::Mapping(map) = yaml_value {
-            for (key, value) in map {
-                if let serde_yaml::Value::String(key_str) = key {
-                    match key_str.as_str() {
-                        "target" => {
-                            if let Ok(targets) = serde_yaml::from_value::<Vec<String>>(value) {
-                                config.target = targets;
-                            }
-                        }
-                        "ports" => {
-                            if let Ok(ports) = serde_yaml::from_value::<String>(value) {
-                                config.ports = ports;
-                            }
-                        }
-                        "tcp" => {
-                            if let Ok(tcp) = serde_yaml::from_value::<bool>(value) {
-                                config.tcp = tcp;
-                            }
-                        }
-                        "udp" => {
-                            if let Ok(udp) = serde_yaml::from_value::<bool>(value) {
-                                config.udp = udp;
-                            }
-                        }
-                        "timeout" => {
-                            if let Ok(timeout) = serde_yaml::from_value::<u64>(value) {
-                                config.timeout = timeout;
-                            }
-                        }
-                        "threads" => {
-                            if let Ok(threads) = serde_yaml::from_value::<u64>(value) {
-                                config.threads = threads;
-                            }
-                        }
-                        "json" => {
-                            if let Ok(json) = serde_yaml::from_value::<Option<String>>(value) {
-                                config.json = json;
-                            }
-                        }
-                        "lua_script" => {
-                            if let Ok(lua_script) = serde_yaml::from_value::<Option<String>>(value)
-                            {
-                                config.lua_script = lua_script;
-                            }
-                        }
-                        "verbose" => {
-                            if let Ok(verbose) = serde_yaml::from_value::<bool>(value) {
-                                config.verbose = verbose;
-                            }
-                        }
-
-                        _ => {} // Ignore unknown fields
-                    }
+    /// Whether stdout is reserved for the NDJSON event stream
+    pub fn streams_to_stdout(&self) -> bool {
+        self.ndjson.as_deref() == Some("-")
+    }
+
+    /// Whether result tables list closed and filtered ports as well as open ones
+    ///
+    /// Ports the user asked for explicitly are shown in every state unless `--open` is set.
+    pub fn show_all_states(&self) -> bool {
+        self.ports_explicitly_specified && !self.open_only
+    }
+
+    /// Where the setting `key` got its value
+    pub fn source(&self, key: &str) -> &ConfigSource {
+        self.sources.get(key).unwrap_or(&ConfigSource::Default)
+    }
+
+    /// Record where the setting `key` got its value
+    pub fn set_source(&mut self, key: &str, source: ConfigSource) {
+        self.sources.insert(key.to_string(), source);
+    }
+
+    /// The layer the setting `key` got its value from: 0 for defaults, 1 for the config file,
+    /// 2 for the environment and 3 for the command line
+    ///
+    /// Settings set by a timing template or profile belong to the layer that selected it.
+    pub fn layer(&self, key: &str) -> u8 {
+        match self.source(key) {
+            ConfigSource::Default => 0,
+            ConfigSource::File(_) => 1,
+            ConfigSource::Env(_) => 2,
+            ConfigSource::CommandLine => 3,
+            ConfigSource::Template(_) => self.layer("timing"),
+            ConfigSource::Profile(_) => self.layer("profile"),
+        }
+    }
+
+    /// Keys of every setting that config files, environment variables and `--print-config`
+    /// know
+    pub fn keys() -> Vec<String> {
+        match serde_json::to_value(Config::default()) {
+            Ok(serde_json::Value::Object(map)) => map.into_iter().map(|(key, _)| key).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Set the setting `key` from a JSON value, which must have the setting's type
+    ///
+    /// Only that setting is deserialized, the others are left as they are.
+    pub fn set_value(&mut self, key: &str, value: serde_json::Value) -> Result<(), Box<dyn Error>> {
+        let key = canonical_key(key);
+        let mut values = serde_json::Map::new();
+        values.insert(key.to_string(), value);
+        let parsed: Config = serde_json::from_value(serde_json::Value::Object(values))?;
+
+        // Moves the field named `key` over from the parsed config
+        macro_rules! set_field {
+            ($($field:ident),* $(,)?) => {
+                match key {
+                    $(stringify!($field) => self.$field = parsed.$field,)*
+                    _ => return Err(format!("`{}` cannot be set", key).into()),
                 }
+            };
+        }
+        set_field!(
+            target,
+            profile,
+            profiles_dir,
+            ports,
+            open_only,
+            tcp,
+            udp,
+            reverse_dns,
+            timing,
+            timeout,
+            min_rtt_timeout,
+            max_rtt_timeout,
+            max_retries,
+            min_rate,
+            max_rate,
+            max_host_rate,
+            ordered,
+            seed,
+            threads,
+            json,
+            xml,
+            grepable,
+            csv,
+            tsv,
+            html,
+            ndjson,
+            all_formats,
+            service_version,
+            service_probes,
+            lua_script,
+            scripts_dir,
+            script_args,
+            script_args_file,
+            web_enum,
+            wordlists,
+            web_extensions,
+            web_status_codes,
+            web_exclude_sizes,
+            web_threads,
+            verbose,
+            checkpoint,
+            checkpoint_interval,
+        );
+        Ok(())
+    }
+
+    /// Check that every setting has a usable value
+    ///
+    /// A missing target is not an error here, config files often leave it to the command line.
+    pub fn validate(&self) -> Result<(), InvalidValue> {
+        let invalid = |key, message: String| Err(InvalidValue { key, message });
+
+        if let Err(message) = check_ports(&self.ports) {
+            return invalid("ports", message);
+        }
+        if self.threads == 0 {
+            return invalid("threads", "must be at least 1".to_string());
+        }
+        if self.web_threads == 0 {
+            return invalid("web_threads", "must be at least 1".to_string());
+        }
+        if self.min_rtt_timeout == 0 {
+            return invalid("min_rtt_timeout", "must be at least 1ms".to_string());
+        }
+        if self.min_rtt_timeout > self.max_rtt_timeout {
+            return invalid(
+                "min_rtt_timeout",
+                format!(
+                    "{}ms is above max_rtt_timeout ({}ms)",
+                    self.min_rtt_timeout, self.max_rtt_timeout
+                ),
+            );
+        }
+        for (key, rate) in [
+            ("min_rate", self.min_rate),
+            ("max_rate", self.max_rate),
+            ("max_host_rate", self.max_host_rate),
+        ] {
+            if let Some(rate) = rate
+                && !(rate.is_finite() && rate > 0.0)
+            {
+                return invalid(key, format!("{} is not a positive number", rate));
             }
         }
+        if let (Some(min_rate), Some(max_rate)) = (self.min_rate, self.max_rate)
+            && min_rate > max_rate
+        {
+            return invalid(
+                "min_rate",
+                format!("{}/s is above max_rate ({}/s)", min_rate, max_rate),
+            );
+        }
+        Ok(())
+    }
+
+    /// Load a YAML, TOML or JSON config file, the format is taken from the file extension
+    ///
+    /// Keys left out keep their default. A timing template only sets the timing settings the
+    /// file leaves out. Unknown keys, values of the wrong type and values rejected by
+    /// [`Config::validate`] are reported with the line they are on.
+    ///
+    /// Profiles the file defines are checked for unknown keys but not applied, the profile a
+    /// scan uses is only known once every layer is read.
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let error = |line, message| ConfigError {
+            path: path.to_string(),
+            line,
+            message,
+        };
+        let content = fs::read_to_string(path).map_err(|e| error(None, e.to_string()))?;
+        let format = ConfigFormat::from_path(path);
+
+        let mut config: Config = format
+            .parse(&content)
+            .map_err(|(line, message)| error(line, suggest_key(message)))?;
+        let keys: BTreeMap<String, IgnoredAny> = format
+            .parse(&content)
+            .map_err(|(line, message)| error(line, message))?;
+        let keys: Vec<&str> = keys.keys().map(|key| canonical_key(key)).collect();
+        let is_set = |key: &str| keys.contains(&key);
+
+        for key in &keys {
+            config.set_source(key, ConfigSource::File(path.to_string()));
+        }
+        for (name, definition) in &config.profiles {
+            definition
+                .check(name)
+                .map_err(|message| error(format.key_line(&content, "profiles"), message))?;
+        }
+        if let Some(template) = config.timing {
+            for key in template.apply_unless(&mut config, is_set) {
+                config.set_source(key, ConfigSource::Template(template));
+            }
+        }
+        config.ports_explicitly_specified = is_set("ports");
 
+        config
+            .validate()
+            .map_err(|e| error(format.key_line(&content, e.key), e.to_string()))?;
         Ok(config)
     }
 }
+
+/// Remove the " at line N column M" serde_yaml and serde_json append to their messages
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Replace serde's list of every expected key with the closest one to an unknown key
+fn suggest_key(message: String) -> String {
+    let Some(key) = message
+        .strip_prefix("unknown field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(key, _)| key.to_string())
+    else {
+        return message;
+    };
+
+    match closest_key(&key) {
+        Some(candidate) => format!("unknown key `{}`, did you mean `{}`?", key, candidate),
+        None => format!("unknown key `{}`", key),
+    }
+}
+
+/// The known key closest to a misspelled one, if any is close enough
+pub fn closest_key(key: &str) -> Option<String> {
+    Config::keys()
+        .into_iter()
+        .map(|candidate| (edit_distance(key, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Number of single character insertions, deletions and substitutions turning `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Port lists may be written as a number when they hold a single port
+fn port_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    deserializer.deserialize_any(ScalarVisitor("a port list such as \"22,80,8000-8100\""))
+}
+
+/// Script arguments may be numbers or booleans, scripts receive them as strings
+fn script_arg_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    struct ScriptArg(String);
+
+    impl<'de> Deserialize<'de> for ScriptArg {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer
+                .deserialize_any(ScalarVisitor("a string, number or boolean"))
+                .map(ScriptArg)
+        }
+    }
+
+    let args = HashMap::<String, ScriptArg>::deserialize(deserializer)?;
+    Ok(args.into_iter().map(|(key, arg)| (key, arg.0)).collect())
+}
+
+/// Accepts any scalar as its string form, the field says what it expected otherwise
+struct ScalarVisitor(&'static str);
+
+impl Visitor<'_> for ScalarVisitor {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<String, E> {
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Load `content` as a config file with the extension `extension`
+    fn load(extension: &str, content: &str) -> Result<Config, ConfigError> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "rmap-config-test-{}-{}.{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        fs::write(&path, content).unwrap();
+        let config = Config::from_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn unknown_keys_name_their_line_and_closest_key() {
+        let cases = [
+            ("yml", "ports: \"80\"\nthreds: 4\n", 2),
+            ("toml", "ports = \"80\"\nthreds = 4\n", 2),
+            ("json", "{\n  \"ports\": \"80\",\n  \"threds\": 4\n}\n", 3),
+        ];
+        for (extension, content, line) in cases {
+            let error = load(extension, content).unwrap_err();
+            assert_eq!(error.line, Some(line), "{}", extension);
+            assert_eq!(
+                error.message, "unknown key `threds`, did you mean `threads`?",
+                "{}",
+                extension
+            );
+        }
+    }
+
+    #[test]
+    fn timing_is_a_number_or_a_name() {
+        let cases = [
+            ("yml", "timing: 4\n"),
+            ("yml", "timing: aggressive\n"),
+            ("toml", "timing = 4\n"),
+            ("toml", "timing = \"aggressive\"\n"),
+            ("json", "{\"timing\": 4}"),
+            ("json", "{\"timing\": \"aggressive\"}"),
+        ];
+        for (extension, content) in cases {
+            let config = load(extension, content).unwrap();
+            assert_eq!(
+                config.timing,
+                Some(TimingTemplate::Aggressive),
+                "{}",
+                content
+            );
+            assert_eq!(config.timeout, 500, "{}", content);
+            assert_eq!(
+                config.source("timeout"),
+                &ConfigSource::Template(TimingTemplate::Aggressive)
+            );
+        }
+    }
+
+    #[test]
+    fn every_key_can_be_set_on_its_own() {
+        let defaults = serde_json::to_value(Config::default()).unwrap();
+        let mut config = Config {
+            threads: 3,
+            ..Config::default()
+        };
+        for key in Config::keys() {
+            config.set_value(&key, defaults[&key].clone()).unwrap();
+        }
+        config.set_value("open", true.into()).unwrap();
+        assert!(config.open_only);
+        assert!(config.set_value("threads", "many".into()).is_err());
+        assert!(config.set_value("profiles", serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn rtt_timeouts_are_checked_against_each_other_only() {
+        let cases = [
+            ("yml", "max_rtt_timeout: 500\n", None),
+            ("yml", "min_rtt_timeout: 3000\n", None),
+            ("yml", "timing: 4\ntimeout: 2000\n", None),
+            (
+                "yml",
+                "max_rtt_timeout: 50\nmin_rtt_timeout: 3000\n",
+                Some(2),
+            ),
+            ("toml", "max_rtt_timeout = 500\n", None),
+            (
+                "toml",
+                "max_rtt_timeout = 50\nmin_rtt_timeout = 3000\n",
+                Some(2),
+            ),
+            ("json", "{\"max_rtt_timeout\": 500}", None),
+            (
+                "json",
+                "{\n  \"max_rtt_timeout\": 50,\n  \"min_rtt_timeout\": 3000\n}",
+                Some(3),
+            ),
+        ];
+        for (extension, content, line) in cases {
+            match (load(extension, content), line) {
+                (Ok(_), None) => {}
+                (Err(error), Some(line)) => {
+                    assert_eq!(error.line, Some(line), "{}", content);
+                    assert!(error.message.contains("min_rtt_timeout"), "{}", error);
+                }
+                (result, _) => panic!("{}: unexpected {:?}", content, result.map(|_| ())),
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use super::Config;
+use super::config::{ConfigSource, canonical_key, closest_key};
+use super::script_args::parse_script_args;
+use crate::core::TimingTemplate;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Prefix of the environment variables holding settings
+const PREFIX: &str = "RMAP_";
+
+/// Apply `RMAP_*` environment variables to `config`
+///
+/// Every config file key has a variable named after it in upper case, e.g. `RMAP_MAX_RETRIES`.
+/// Lists are comma-separated and `RMAP_SCRIPT_ARGS` uses the `--script-args` syntax. A timing
+/// template is applied before the other variables so they can override it. Unknown variables
+/// are skipped with a warning.
+pub fn apply_env(
+    config: &mut Config,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), String> {
+    let known = Config::keys();
+    let mut settings = BTreeMap::new();
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(PREFIX) else {
+            continue;
+        };
+        let key = canonical_key(&key.to_lowercase()).to_string();
+        if !known.contains(&key) {
+            match closest_key(&key) {
+                Some(candidate) => eprintln!(
+                    "Warning: ignoring unknown variable {}, did you mean {}{}?",
+                    name,
+                    PREFIX,
+                    candidate.to_uppercase()
+                ),
+                None => eprintln!("Warning: ignoring unknown variable {}", name),
+            }
+            continue;
+        }
+        settings.insert(key, (name, value));
+    }
+
+    if let Some((name, value)) = settings.remove("timing") {
+        let template: TimingTemplate = value
+            .parse()
+            .map_err(|e| format!("invalid value in {}: {}", name, e))?;
+        // Settings of the config file are kept, as they are from a template of its own
+        let kept: Vec<String> = Config::keys()
+            .into_iter()
+            .filter(|key| matches!(config.source(key), ConfigSource::File(_)))
+            .collect();
+        for key in template.apply_unless(config, |key| kept.iter().any(|kept| kept == key)) {
+            config.set_source(key, ConfigSource::Template(template));
+        }
+        config.set_source("timing", ConfigSource::Env(name));
+    }
+
+    for (key, (name, value)) in settings {
+        let mut result = Err(String::new());
+        for candidate in candidates(config, &key, &value)? {
+            result = config.set_value(&key, candidate).map_err(|e| e.to_string());
+            if result.is_ok() {
+                break;
+            }
+        }
+        result.map_err(|e| format!("invalid value in {}: {}", name, e))?;
+
+        if key == "ports" {
+            config.ports_explicitly_specified = true;
+        }
+        config.set_source(&key, ConfigSource::Env(name));
+    }
+    Ok(())
+}
+
+/// Ways to read a variable's text as a value of the setting, tried in order
+///
+/// Values are read like YAML scalars first, so numbers and booleans get their type, and as
+/// plain strings when the setting wants text that happens to look like a number.
+fn candidates(config: &Config, key: &str, value: &str) -> Result<Vec<Value>, String> {
+    let scalar = |text: &str| serde_yaml::from_str::<Value>(text).unwrap_or(Value::Null);
+    let current = serde_json::to_value(config).map_err(|e| e.to_string())?;
+
+    Ok(match &current[key] {
+        Value::Array(_) => {
+            let items: Vec<&str> = value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .collect();
+            vec![
+                Value::Array(items.iter().map(|item| scalar(item)).collect()),
+                Value::Array(items.iter().map(|item| Value::from(*item)).collect()),
+            ]
+        }
+        Value::Object(_) => {
+            let args = parse_script_args(value).map_err(|e| e.to_string())?;
+            vec![serde_json::to_value(args).map_err(|e| e.to_string())?]
+        }
+        _ if value.is_empty() => vec![Value::Null],
+        _ => vec![scalar(value), Value::from(value)],
+    })
+}
@@ -1,19 +1,54 @@
-use clap::Parser;
-use num_cpus;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use profile::Profiles;
 
 mod cli;
 mod config;
+mod env;
+mod profile;
+mod script_args;
 
-pub use config::Config;
+pub use cli::{Command, DiffFormat};
+pub use config::{Config, ConfigSource};
 
+/// Nmap-style output flags accepted on the command line and their long equivalents
+const NMAP_OUTPUT_FLAGS: [(&str, &str); 3] = [
+    ("-oX", "--xml"),
+    ("-oG", "--grepable"),
+    ("-oA", "--all-formats"),
+];
+
+/// Rewrite nmap-style output flags (`-oX file`, `-oXfile`) to their long form
+///
+/// clap only supports single character short flags, so these are translated before parsing.
+fn normalize_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut normalized = Vec::new();
+    for arg in args {
+        match NMAP_OUTPUT_FLAGS
+            .iter()
+            .find(|(flag, _)| arg.starts_with(flag))
+        {
+            Some((flag, long)) if arg.len() == flag.len() => normalized.push(long.to_string()),
+            Some((flag, long)) => normalized.push(format!("{}={}", long, &arg[flag.len()..])),
+            None => normalized.push(arg),
+        }
+    }
+    normalized
+}
+
+/// Build the configuration from its layers: built-in defaults, the config file, `RMAP_*`
+/// environment variables and options given on the command line, each overriding the last
 pub fn get_config() -> Config {
-    let args = cli::Args::parse();
+    let matches = cli::Args::command().get_matches_from(normalize_args(std::env::args()));
+    let args = cli::Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Only options typed on the command line count, not the defaults clap fills in
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
 
     let mut config = if let Some(config_path) = &args.config {
         match Config::from_file(config_path) {
             Ok(config) => config,
             Err(e) => {
-                eprintln!("Error loading config file '{}': {}", config_path, e);
+                eprintln!("Error in config file {}", e);
                 std::process::exit(1);
             }
         }
@@ -21,16 +56,52 @@ pub fn get_config() -> Config {
         Config::default()
     };
 
-    if !args.target.is_empty() {
+    if let Err(e) = env::apply_env(&mut config, std::env::vars()) {
+        eprintln!("Error in environment: {}", e);
+        std::process::exit(1);
+    }
+
+    if given("target") {
         config.target = args.target;
     }
 
-    // Validate that we have at least one target
-    if config.target.is_empty() {
-        eprintln!(
-            "Error: No target specified. Provide target via --target argument or in config file."
+    if let Some(script_dir) = args.script_dir {
+        config.scripts_dir = script_dir;
+    }
+
+    // Subcommands work on saved results and do not need a target
+    if args.command.is_some() {
+        config.command = args.command;
+        return config;
+    }
+
+    // A resumed scan runs with the configuration saved in the checkpoint, apart from the
+    // script arguments, which are not saved
+    if args.resume.is_some() {
+        config.resume = args.resume;
+        if args.script_args_file.is_some() {
+            config.script_args_file = args.script_args_file;
+        }
+        load_script_args(
+            &mut config,
+            args.script_args.as_deref(),
+            given("script_args"),
         );
-        std::process::exit(1);
+        return config;
+    }
+
+    // Listing scripts does not need a target
+    if args.list_scripts {
+        config.list_scripts = true;
+        return config;
+    }
+
+    if args.profile.is_some() {
+        config.profile = args.profile;
+    }
+
+    if let Some(profiles_dir) = args.profiles_dir {
+        config.profiles_dir = profiles_dir;
     }
 
     if let Some(ports) = args.ports {
@@ -38,16 +109,68 @@ pub fn get_config() -> Config {
         config.ports_explicitly_specified = true;
     }
 
-    if args.tcp != true || args.udp {
+    if given("open") {
+        config.open_only = args.open;
+    }
+
+    if given("tcp") {
         config.tcp = args.tcp;
+    }
+
+    if given("udp") {
         config.udp = args.udp;
     }
 
-    if args.timeout != 2000 {
+    if let Some(reverse_dns) = args.reverse_dns {
+        config.reverse_dns = reverse_dns;
+    }
+
+    // The template comes first so the individual timing options can override it. Settings
+    // given in the config file or environment are kept, as a template of their own layer does.
+    if let Some(timing) = args.timing {
+        let kept = explicitly_set(&config);
+        for key in timing.apply_unless(&mut config, |key| kept.iter().any(|kept| kept == key)) {
+            config.set_source(key, ConfigSource::Template(timing));
+        }
+    }
+
+    if given("timeout") {
         config.timeout = args.timeout;
     }
 
-    if args.threads != num_cpus::get() {
+    if let Some(timeout) = args.min_rtt_timeout {
+        config.min_rtt_timeout = timeout;
+    }
+
+    if let Some(timeout) = args.max_rtt_timeout {
+        config.max_rtt_timeout = timeout;
+    }
+
+    if let Some(retries) = args.max_retries {
+        config.max_retries = retries;
+    }
+
+    if args.min_rate.is_some() {
+        config.min_rate = args.min_rate;
+    }
+
+    if args.max_rate.is_some() {
+        config.max_rate = args.max_rate;
+    }
+
+    if args.max_host_rate.is_some() {
+        config.max_host_rate = args.max_host_rate;
+    }
+
+    if given("ordered") {
+        config.ordered = args.ordered;
+    }
+
+    if args.seed.is_some() {
+        config.seed = args.seed;
+    }
+
+    if given("threads") {
         config.threads = args.threads as u64;
     }
 
@@ -55,13 +178,190 @@ pub fn get_config() -> Config {
         config.json = args.json;
     }
 
+    if args.xml.is_some() {
+        config.xml = args.xml;
+    }
+
+    if args.grepable.is_some() {
+        config.grepable = args.grepable;
+    }
+
+    if args.csv.is_some() {
+        config.csv = args.csv;
+    }
+
+    if args.tsv.is_some() {
+        config.tsv = args.tsv;
+    }
+
+    if args.html.is_some() {
+        config.html = args.html;
+    }
+
+    if args.ndjson.is_some() {
+        config.ndjson = args.ndjson;
+    }
+
+    if args.all_formats.is_some() {
+        config.all_formats = args.all_formats;
+    }
+
+    if given("service_version") {
+        config.service_version = args.service_version;
+    }
+
+    if args.service_probes.is_some() {
+        config.service_probes = args.service_probes;
+    }
+
     if args.lua_script.is_some() {
         config.lua_script = args.lua_script;
     }
 
-    if args.verbose {
+    if args.script_args_file.is_some() {
+        config.script_args_file = args.script_args_file;
+    }
+
+    if given("web_enum") {
+        config.web_enum = args.web_enum;
+    }
+
+    if given("wordlists") {
+        config.wordlists = args.wordlists;
+    }
+
+    if let Some(extensions) = args.web_extensions {
+        config.web_extensions = extensions;
+    }
+
+    if let Some(codes) = args.web_status_codes {
+        config.web_status_codes = codes;
+    }
+
+    if let Some(sizes) = args.web_exclude_sizes {
+        config.web_exclude_sizes = sizes;
+    }
+
+    if let Some(web_threads) = args.web_threads {
+        config.web_threads = web_threads;
+    }
+
+    if given("verbose") {
         config.verbose = args.verbose;
     }
 
+    if args.checkpoint.is_some() {
+        config.checkpoint = args.checkpoint;
+    }
+
+    if let Some(interval) = args.checkpoint_interval {
+        config.checkpoint_interval = interval;
+    }
+
+    let keys = Config::keys();
+    for id in matches.ids() {
+        let key = config::canonical_key(id.as_str());
+        if given(id.as_str()) && keys.iter().any(|known| known == key) {
+            config.set_source(key, ConfigSource::CommandLine);
+        }
+    }
+
+    // A profile sits just below the layer that selected it, whose own settings win over it
+    if let Some(name) = config.profile.clone() {
+        let selected = config.layer("profile");
+        let kept: Vec<String> = Config::keys()
+            .into_iter()
+            .filter(|key| config.layer(key) >= selected)
+            .collect();
+        let applied = Profiles::load(&config.profiles_dir, &config).and_then(|profiles| {
+            profiles.apply(&name, &mut config, |key| {
+                kept.iter().any(|kept| kept == key)
+            })
+        });
+        if let Err(e) = applied {
+            eprintln!("Error in profile: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // -oA fills in the formats that were not given a file of their own
+    if let Some(basename) = config.all_formats.clone() {
+        let source = config.source("all_formats").clone();
+        if config.json.is_none() {
+            config.json = Some(format!("{}.json", basename));
+            config.set_source("json", source.clone());
+        }
+        if config.xml.is_none() {
+            config.xml = Some(format!("{}.xml", basename));
+            config.set_source("xml", source.clone());
+        }
+        if config.grepable.is_none() {
+            config.grepable = Some(format!("{}.gnmap", basename));
+            config.set_source("grepable", source);
+        }
+    }
+
+    load_script_args(
+        &mut config,
+        args.script_args.as_deref(),
+        given("script_args"),
+    );
+
+    // Printing the configuration shows it as it is, even without a target
+    if args.print_config {
+        config.print_config = true;
+        return config;
+    }
+
+    // Validate that we have at least one target
+    if config.target.is_empty() {
+        eprintln!(
+            "Error: No target specified. Provide target via --target argument, RMAP_TARGET or in config file."
+        );
+        std::process::exit(1);
+    }
+
     config
 }
+
+/// Keys of the settings given in the config file or environment, not by a template or profile
+fn explicitly_set(config: &Config) -> Vec<String> {
+    Config::keys()
+        .into_iter()
+        .filter(|key| {
+            matches!(
+                config.source(key),
+                ConfigSource::File(_) | ConfigSource::Env(_)
+            )
+        })
+        .collect()
+}
+
+/// Load the script arguments of `script_args_file`, then those given inline on top
+fn load_script_args(config: &mut Config, inline: Option<&str>, inline_given: bool) {
+    if let Some(path) = config.script_args_file.clone() {
+        match script_args::load_script_args_file(&path) {
+            Ok(file_args) => {
+                if !file_args.is_empty() && !inline_given {
+                    let source = config.source("script_args_file").clone();
+                    config.set_source("script_args", source);
+                }
+                config.script_args.extend(file_args)
+            }
+            Err(e) => {
+                eprintln!("Error loading script arguments file '{}': {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(inline_args) = inline {
+        match script_args::parse_script_args(inline_args) {
+            Ok(cli_args) => config.script_args.extend(cli_args),
+            Err(e) => {
+                eprintln!("Error parsing script arguments: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
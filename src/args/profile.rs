@@ -0,0 +1,216 @@
+use super::config::{
+    Config, ConfigFormat, ConfigSource, canonical_key, closest_key, edit_distance,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Profiles shipped with rmap, written as a config file that defines nothing else
+const BUILTIN: &str = include_str!("../../config/profiles.yml");
+
+/// Keys that select profiles, a profile cannot set them
+const RESERVED_KEYS: [&str; 2] = ["profile", "profiles_dir"];
+
+/// Settings of a named profile, as written under `profiles` or in a profile file
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProfileDefinition {
+    /// Profile whose settings this one starts from
+    pub inherits: Option<String>,
+    /// Config file keys and their values
+    #[serde(flatten)]
+    pub settings: Map<String, Value>,
+}
+
+impl ProfileDefinition {
+    /// Check that every key is a setting, `name` is the profile's name for the error message
+    pub fn check(&self, name: &str) -> Result<(), String> {
+        let known = Config::keys();
+        for key in self.settings.keys() {
+            let key = canonical_key(key);
+            if RESERVED_KEYS.contains(&key) {
+                return Err(format!(
+                    "profile `{}`: `{}` cannot be set in a profile",
+                    name, key
+                ));
+            }
+            if !known.iter().any(|known| known == key) {
+                return Err(match closest_key(key) {
+                    Some(candidate) => format!(
+                        "profile `{}`: unknown key `{}`, did you mean `{}`?",
+                        name, key, candidate
+                    ),
+                    None => format!("profile `{}`: unknown key `{}`", name, key),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every profile that can be selected, by name
+pub struct Profiles {
+    definitions: BTreeMap<String, ProfileDefinition>,
+}
+
+impl Profiles {
+    /// The built-in profiles, then those in `dir`, then those `config` file defined
+    ///
+    /// A profile replaces one of the same name loaded before it. A missing directory holds no
+    /// profiles.
+    pub fn load(dir: &str, config: &Config) -> Result<Profiles, String> {
+        let builtin: Config = ConfigFormat::Yaml
+            .parse(BUILTIN)
+            .map_err(|(_, message)| format!("built-in profiles: {}", message))?;
+        let mut definitions = builtin.profiles;
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, _>>(),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| format!("{}: {}", dir, e))?;
+        let mut paths: Vec<_> = entries.into_iter().map(|entry| entry.path()).collect();
+        paths.sort();
+        for path in paths {
+            if let Some((name, definition)) = load_file(&path)? {
+                definitions.insert(name, definition);
+            }
+        }
+
+        definitions.extend(config.profiles.clone());
+        Ok(Profiles { definitions })
+    }
+
+    /// Settings of the profile `name` and the profiles it inherits from, each with the name of
+    /// the profile that set it
+    fn settings(&self, name: &str) -> Result<BTreeMap<String, (Value, String)>, String> {
+        let mut chain: Vec<&str> = Vec::new();
+        let mut next = Some(name);
+        while let Some(current) = next {
+            if chain.contains(&current) {
+                chain.push(current);
+                return Err(format!(
+                    "profiles inherit in a loop: {}",
+                    chain.join(" -> ")
+                ));
+            }
+            let definition = self
+                .definitions
+                .get(current)
+                .ok_or_else(|| self.unknown(current))?;
+            next = definition.inherits.as_deref();
+            chain.push(current);
+        }
+
+        // Profiles inherited from come first so the ones inheriting override them
+        let mut settings = BTreeMap::new();
+        for profile in chain.iter().rev() {
+            for (key, value) in &self.definitions[*profile].settings {
+                settings.insert(
+                    canonical_key(key).to_string(),
+                    (value.clone(), profile.to_string()),
+                );
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Set the settings of the profile `name` on `config`, except those `keep` returns true for
+    ///
+    /// A timing template in the profile sets the timing settings the profile leaves out.
+    pub fn apply(
+        &self,
+        name: &str,
+        config: &mut Config,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<(), String> {
+        let settings = self.settings(name)?;
+        let set = |config: &mut Config, key: &str, value: &Value, profile: &str| {
+            config.set_value(key, value.clone()).map_err(|e| {
+                format!("profile `{}`: invalid value for `{}`: {}", profile, key, e)
+            })?;
+            config.set_source(key, ConfigSource::Profile(profile.to_string()));
+            Ok::<(), String>(())
+        };
+
+        if let Some((value, profile)) = settings.get("timing")
+            && !keep("timing")
+        {
+            set(config, "timing", value, profile)?;
+            if let Some(template) = config.timing {
+                let changed =
+                    template.apply_unless(config, |key| keep(key) || settings.contains_key(key));
+                for key in changed {
+                    config.set_source(key, ConfigSource::Template(template));
+                }
+            }
+        }
+
+        for (key, (value, profile)) in &settings {
+            if key == "timing" || keep(key) {
+                continue;
+            }
+            set(config, key, value, profile)?;
+            if key == "ports" {
+                config.ports_explicitly_specified = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Error for a profile that is not defined, with the closest name or the available ones
+    fn unknown(&self, name: &str) -> String {
+        let closest = self
+            .definitions
+            .keys()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance);
+        match closest {
+            Some((_, candidate)) => {
+                format!("unknown profile `{}`, did you mean `{}`?", name, candidate)
+            }
+            None => format!(
+                "unknown profile `{}`, available profiles: {}",
+                name,
+                self.definitions
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Load a profile file, named after the file without its extension
+///
+/// Files that are not YAML, TOML or JSON are skipped.
+fn load_file(path: &Path) -> Result<Option<(String, ProfileDefinition)>, String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    if !matches!(extension.as_deref(), Some("yml" | "yaml" | "toml" | "json")) {
+        return Ok(None);
+    }
+    let (Some(name), Some(display)) = (path.file_stem(), path.to_str()) else {
+        return Ok(None);
+    };
+    let name = name.to_string_lossy().into_owned();
+
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", display, e))?;
+    let definition: ProfileDefinition =
+        ConfigFormat::from_path(display)
+            .parse(&content)
+            .map_err(|(line, message)| match line {
+                Some(line) => format!("{}:{}: {}", display, line, message),
+                None => format!("{}: {}", display, message),
+            })?;
+    definition
+        .check(&name)
+        .map_err(|message| format!("{}: {}", display, message))?;
+    Ok(Some((name, definition)))
+}
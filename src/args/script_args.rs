@@ -0,0 +1,73 @@
+use std::{collections::HashMap, error::Error, fs};
+
+/// Parse a `key=value,key2=value2` list of script arguments
+///
+/// Values may be wrapped in double quotes to include commas or `=` characters,
+/// e.g. `http-enum.paths="/admin,/login"`.
+pub fn parse_script_args(input: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let mut args = HashMap::new();
+
+    for pair in split_pairs(input) {
+        let pair = pair.trim();
+        if pair.is_empty() {
+            continue;
+        }
+
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid script argument '{}', expected key=value", pair))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("Invalid script argument '{}', key is empty", pair).into());
+        }
+
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        args.insert(key.to_string(), value.to_string());
+    }
+
+    Ok(args)
+}
+
+/// Load script arguments from a file
+///
+/// The file holds `key=value` pairs separated by newlines or commas. Lines starting
+/// with `#` are ignored.
+pub fn load_script_args_file(path: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    let mut args = HashMap::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        args.extend(parse_script_args(line)?);
+    }
+
+    Ok(args)
+}
+
+/// Split on commas that are not inside double quotes
+fn split_pairs(input: &str) -> Vec<&str> {
+    let mut pairs = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    for (i, ch) in input.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                pairs.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    pairs.push(&input[start..]);
+
+    pairs
+}
@@ -0,0 +1,660 @@
+//! Checkpoint files for resuming interrupted scans
+//!
+//! While a scan runs, [`CheckpointWriter`] records every host that is done and the port results
+//! of hosts still being scanned, and writes them to disk periodically. A scan started with
+//! `--resume` reloads the configuration and those results and only probes what is left.
+
+use crate::args::Config;
+use crate::core::web::WebEnumResult;
+use crate::core::{CancellationToken, PortResult, ScriptResult, ServiceInfo};
+use crate::events::{ScanEvent, ScanObserver};
+use crate::report::{HostReport, PortReport, Protocol, ScanMetadata};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Version of the checkpoint file layout
+pub const CHECKPOINT_VERSION: &str = "1";
+
+/// Progress of a scan, enough to continue it later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub checkpoint_version: String,
+    pub config: Config,
+    /// Not part of the serialized config, but decides how result tables look
+    pub ports_explicitly_specified: bool,
+    /// The command line of the original scan
+    pub command_line: Vec<String>,
+    pub start_time: DateTime<Utc>,
+    /// Hosts whose probes, scripts and web enumeration are done, one entry per protocol
+    pub finished: Vec<FinishedHost>,
+    /// Ports already probed on hosts that were still being scanned
+    pub pending: Vec<PendingHost>,
+}
+
+/// The complete result of one host and protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FinishedHost {
+    pub protocol: Protocol,
+    pub host: HostReport,
+}
+
+/// Port results of a host that was still being scanned
+///
+/// Scripts and web enumeration run once every port is probed, so their results are only kept
+/// in memory until the host is done and are run again when the scan is resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingHost {
+    pub target: String,
+    pub address: String,
+    pub protocol: Protocol,
+    pub ports: Vec<PortReport>,
+    #[serde(skip)]
+    scripts: Vec<ScriptResult>,
+    #[serde(skip)]
+    web: Vec<WebEnumResult>,
+}
+
+impl PendingHost {
+    fn new(target: &str, address: &str, protocol: Protocol) -> PendingHost {
+        PendingHost {
+            target: target.to_string(),
+            address: address.to_string(),
+            protocol,
+            ports: Vec::new(),
+            scripts: Vec::new(),
+            web: Vec::new(),
+        }
+    }
+
+    fn is(&self, target: &str, address: &str, protocol: Protocol) -> bool {
+        self.target == target && self.address == address && self.protocol == protocol
+    }
+
+    /// Build the host report the way the scanners do, port scripts are kept with their port
+    fn into_host(self) -> HostReport {
+        let mut host = HostReport::new(&self.target, &self.address);
+        let mut ports = self.ports;
+        for script_result in self.scripts {
+            match ports
+                .iter_mut()
+                .find(|port| Some(port.port) == script_result.port)
+            {
+                Some(port) => port.scripts.push(script_result),
+                None => host.scripts.push(script_result),
+            }
+        }
+        host.web = self.web;
+        host.add_ports(ports);
+        host
+    }
+}
+
+impl Checkpoint {
+    /// Start an empty checkpoint for a scan described by `metadata`
+    ///
+    /// Script arguments are left out, they may hold credentials. They are given again when
+    /// the scan is resumed.
+    pub fn new(config: &Config, metadata: &ScanMetadata) -> Checkpoint {
+        let mut config = config.clone();
+        config.script_args.clear();
+        Checkpoint {
+            checkpoint_version: CHECKPOINT_VERSION.to_string(),
+            ports_explicitly_specified: config.ports_explicitly_specified,
+            config,
+            command_line: metadata.command_line.clone(),
+            start_time: metadata.start_time,
+            finished: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Read a checkpoint written by [`Checkpoint::write`]
+    pub fn load(path: &str) -> Result<Checkpoint, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let checkpoint: Checkpoint = serde_json::from_str(&content)?;
+        if checkpoint.checkpoint_version != CHECKPOINT_VERSION {
+            return Err(format!(
+                "unsupported checkpoint version {}",
+                checkpoint.checkpoint_version
+            )
+            .into());
+        }
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint, replacing the previous file only once the new one is complete
+    pub fn write(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, serde_json::to_string(self)? + "\n")?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// The report of a host that was done when the checkpoint was written
+    pub fn finished_host(
+        &self,
+        target: &str,
+        address: &str,
+        protocol: Protocol,
+    ) -> Option<&HostReport> {
+        self.finished
+            .iter()
+            .find(|finished| {
+                finished.protocol == protocol
+                    && finished.host.target == target
+                    && finished.host.address() == address
+            })
+            .map(|finished| &finished.host)
+    }
+
+    /// Port results already gathered for a host that was still being scanned
+    pub fn done_ports(&self, target: &str, address: &str, protocol: Protocol) -> Vec<PortResult> {
+        self.pending
+            .iter()
+            .filter(|pending| pending.is(target, address, protocol))
+            .flat_map(|pending| &pending.ports)
+            .map(|port| PortResult {
+                port: port.port.to_string(),
+                state: port.state,
+                reason: port.reason,
+                attempts: port.attempts,
+            })
+            .collect()
+    }
+
+    fn pending_mut(&mut self, target: &str, address: &str, protocol: Protocol) -> &mut PendingHost {
+        match self
+            .pending
+            .iter()
+            .position(|pending| pending.is(target, address, protocol))
+        {
+            Some(index) => &mut self.pending[index],
+            None => {
+                self.pending
+                    .push(PendingHost::new(target, address, protocol));
+                self.pending.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Keeps a checkpoint file up to date from the events of a running scan
+///
+/// The file is written when the writer is created, then at most once per interval while
+/// results come in. It is rewritten when an interrupted scan stops and removed once the scan
+/// completes.
+pub struct CheckpointWriter {
+    path: String,
+    interval: Duration,
+    cancel: CancellationToken,
+    state: Mutex<WriterState>,
+}
+
+struct WriterState {
+    checkpoint: Checkpoint,
+    last_write: Instant,
+}
+
+impl CheckpointWriter {
+    /// Write `checkpoint` to `path` and keep it updated
+    ///
+    /// Hosts that finish after `cancel` is cancelled are kept as pending, since their scripts
+    /// and web enumeration may have been cut short.
+    pub fn create(
+        path: &str,
+        interval: Duration,
+        cancel: CancellationToken,
+        checkpoint: Checkpoint,
+    ) -> Result<CheckpointWriter, Box<dyn Error>> {
+        checkpoint.write(path)?;
+        Ok(CheckpointWriter {
+            path: path.to_string(),
+            interval,
+            cancel,
+            state: Mutex::new(WriterState {
+                checkpoint,
+                last_write: Instant::now(),
+            }),
+        })
+    }
+
+    fn write(&self, state: &mut WriterState) {
+        if let Err(e) = state.checkpoint.write(&self.path) {
+            eprintln!("Error writing checkpoint '{}': {}", self.path, e);
+        }
+        state.last_write = Instant::now();
+    }
+}
+
+impl ScanObserver for CheckpointWriter {
+    fn on_event(&self, event: &ScanEvent) {
+        if matches!(event, ScanEvent::Progress { .. }) {
+            return;
+        }
+
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        match event {
+            ScanEvent::PortResult {
+                target,
+                address,
+                protocol,
+                port,
+                state: port_state,
+                reason,
+                attempts,
+            } => {
+                state
+                    .checkpoint
+                    .pending_mut(target, address, *protocol)
+                    .ports
+                    .push(PortReport {
+                        protocol: *protocol,
+                        port: *port,
+                        state: *port_state,
+                        reason: *reason,
+                        attempts: *attempts,
+                        service: Some(ServiceInfo::from_port_table(&port.to_string())),
+                        scripts: Vec::new(),
+                    });
+            }
+            // Services found by version detection replace the ones named from the port table
+            ScanEvent::Service {
+                target,
+                address,
+                protocol,
+                port,
+                service,
+            } => {
+                if let Some(report) = state
+                    .checkpoint
+                    .pending_mut(target, address, *protocol)
+                    .ports
+                    .iter_mut()
+                    .find(|report| report.port == *port)
+                {
+                    report.service = Some(service.clone());
+                }
+            }
+            // Scripts and web enumeration only run after TCP scans
+            ScanEvent::ScriptResult {
+                target,
+                address,
+                result,
+            } => {
+                state
+                    .checkpoint
+                    .pending_mut(target, address, Protocol::Tcp)
+                    .scripts
+                    .push(result.clone());
+            }
+            ScanEvent::WebResult {
+                target,
+                address,
+                result,
+            } => {
+                state
+                    .checkpoint
+                    .pending_mut(target, address, Protocol::Tcp)
+                    .web
+                    .push(result.clone());
+            }
+            ScanEvent::HostFinished {
+                target,
+                address,
+                protocol,
+                ..
+            } => {
+                let checkpoint = &mut state.checkpoint;
+                if let Some(index) = checkpoint
+                    .pending
+                    .iter()
+                    .position(|pending| pending.is(target, address, *protocol))
+                {
+                    if self.cancel.is_cancelled() {
+                        let pending = &mut checkpoint.pending[index];
+                        pending.scripts.clear();
+                        pending.web.clear();
+                    } else {
+                        let pending = checkpoint.pending.remove(index);
+                        checkpoint.finished.push(FinishedHost {
+                            protocol: *protocol,
+                            host: pending.into_host(),
+                        });
+                    }
+                }
+            }
+            ScanEvent::ScanFinished { partial, .. } => {
+                if *partial {
+                    self.write(&mut state);
+                } else if let Err(e) = fs::remove_file(&self.path) {
+                    eprintln!("Error removing checkpoint '{}': {}", self.path, e);
+                }
+                return;
+            }
+            _ => return,
+        }
+
+        if state.last_write.elapsed() >= self.interval {
+            self.write(&mut state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{PortReason, PortState, ScannerBuilder};
+    use crate::report::ScanReport;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rmap-{}-{}.checkpoint", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn port(port: u16, state: PortState, reason: PortReason) -> PortReport {
+        PortReport {
+            protocol: Protocol::Tcp,
+            port,
+            state,
+            reason,
+            attempts: 2,
+            service: Some(ServiceInfo::from_port_table(&port.to_string())),
+            scripts: Vec::new(),
+        }
+    }
+
+    fn script(port: Option<u16>) -> ScriptResult {
+        ScriptResult {
+            script_name: format!("script-{:?}", port),
+            host: "10.0.0.1".to_string(),
+            port,
+            success: true,
+            output: String::new(),
+            error: None,
+            data: serde_json::Value::Null,
+        }
+    }
+
+    fn checkpoint(config: &Config) -> Checkpoint {
+        Checkpoint::new(config, &ScanReport::new(config).metadata)
+    }
+
+    #[test]
+    fn write_and_load_round_trip() {
+        let mut config = Config {
+            target: vec!["example.com".to_string()],
+            ports: "22,80".to_string(),
+            ports_explicitly_specified: true,
+            ..Config::default()
+        };
+        config
+            .script_args
+            .insert("password".to_string(), "secret".to_string());
+        let mut written = checkpoint(&config);
+        let mut host = HostReport::new("example.com", "10.0.0.1");
+        host.add_ports(vec![port(22, PortState::Open, PortReason::SynAck)]);
+        written.finished.push(FinishedHost {
+            protocol: Protocol::Tcp,
+            host,
+        });
+        written
+            .pending_mut("example.com", "10.0.0.2", Protocol::Udp)
+            .ports
+            .push(port(53, PortState::OpenFiltered, PortReason::NoResponse));
+
+        let path = temp_path("round-trip");
+        written.write(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.config.target, config.target);
+        assert_eq!(loaded.config.ports, config.ports);
+        assert!(loaded.ports_explicitly_specified);
+        // Script arguments may hold credentials and are not written
+        assert!(loaded.config.script_args.is_empty());
+        assert_eq!(
+            serde_json::to_value(&loaded.finished).unwrap(),
+            serde_json::to_value(&written.finished).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&loaded.pending).unwrap(),
+            serde_json::to_value(&written.pending).unwrap()
+        );
+        assert!(
+            loaded
+                .finished_host("example.com", "10.0.0.1", Protocol::Tcp)
+                .is_some()
+        );
+        assert!(
+            loaded
+                .finished_host("example.com", "10.0.0.1", Protocol::Udp)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut checkpoint = checkpoint(&Config::default());
+        checkpoint.checkpoint_version = "0".to_string();
+        let path = temp_path("version");
+        checkpoint.write(&path).unwrap();
+        let error = Checkpoint::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(error.to_string().contains("version 0"), "{}", error);
+    }
+
+    #[test]
+    fn done_ports_of_the_matching_host_and_protocol() {
+        let mut checkpoint = checkpoint(&Config::default());
+        let pending = checkpoint.pending_mut("example.com", "10.0.0.1", Protocol::Tcp);
+        pending
+            .ports
+            .push(port(22, PortState::Open, PortReason::SynAck));
+        pending
+            .ports
+            .push(port(23, PortState::Closed, PortReason::ConnRefused));
+        checkpoint
+            .pending_mut("example.com", "10.0.0.2", Protocol::Tcp)
+            .ports
+            .push(port(80, PortState::Filtered, PortReason::NoResponse));
+
+        let done = checkpoint.done_ports("example.com", "10.0.0.1", Protocol::Tcp);
+        let done: Vec<_> = done
+            .iter()
+            .map(|result| (result.port.as_str(), result.state, result.attempts))
+            .collect();
+        assert_eq!(
+            done,
+            [("22", PortState::Open, 2), ("23", PortState::Closed, 2)]
+        );
+        assert!(
+            checkpoint
+                .done_ports("example.com", "10.0.0.1", Protocol::Udp)
+                .is_empty()
+        );
+        assert!(
+            checkpoint
+                .done_ports("other.com", "10.0.0.1", Protocol::Tcp)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn port_scripts_stay_with_their_port() {
+        let mut pending = PendingHost::new("10.0.0.1", "10.0.0.1", Protocol::Tcp);
+        pending.ports = vec![
+            port(443, PortState::Open, PortReason::SynAck),
+            port(80, PortState::Open, PortReason::SynAck),
+        ];
+        pending.scripts = vec![script(Some(80)), script(None), script(Some(8080))];
+
+        let host = pending.into_host();
+        let ports: Vec<_> = host
+            .ports
+            .iter()
+            .map(|port| (port.port, port.scripts.len()))
+            .collect();
+        assert_eq!(ports, [(80, 1), (443, 0)]);
+        // Scripts of ports that are not in the report belong to the host
+        let host_scripts: Vec<_> = host.scripts.iter().map(|script| script.port).collect();
+        assert_eq!(host_scripts, [None, Some(8080)]);
+    }
+
+    #[test]
+    fn detected_services_replace_the_port_table() {
+        let path = temp_path("service");
+        let writer = CheckpointWriter::create(
+            &path,
+            Duration::ZERO,
+            CancellationToken::new(),
+            checkpoint(&Config::default()),
+        )
+        .unwrap();
+        let detected = ServiceInfo {
+            product: Some("OpenSSH".to_string()),
+            version: Some("9.6p1".to_string()),
+            cpe: vec!["cpe:/a:openbsd:openssh:9.6p1".to_string()],
+            confidence: Some(10),
+            ..ServiceInfo::from_port_table("2222")
+        };
+        let service_event = |service: ServiceInfo| ScanEvent::Service {
+            target: "10.0.0.1".to_string(),
+            address: "10.0.0.1".to_string(),
+            protocol: Protocol::Tcp,
+            port: 2222,
+            service,
+        };
+        writer.on_event(&ScanEvent::PortResult {
+            target: "10.0.0.1".to_string(),
+            address: "10.0.0.1".to_string(),
+            protocol: Protocol::Tcp,
+            port: 2222,
+            state: PortState::Open,
+            reason: PortReason::SynAck,
+            attempts: 1,
+        });
+        writer.on_event(&service_event(ServiceInfo::from_port_table("2222")));
+        writer.on_event(&service_event(detected.clone()));
+        writer.on_event(&ScanEvent::HostFinished {
+            target: "10.0.0.1".to_string(),
+            address: "10.0.0.1".to_string(),
+            protocol: Protocol::Tcp,
+            open_ports: 1,
+        });
+
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let host = loaded
+            .finished_host("10.0.0.1", "10.0.0.1", Protocol::Tcp)
+            .unwrap();
+        let service = host.ports[0].service.as_ref().unwrap();
+        assert_eq!(service.product, detected.product);
+        assert_eq!(service.cpe, detected.cpe);
+        assert_eq!(service.confidence, Some(10));
+    }
+
+    /// Run the scan of `config`, recording its events
+    async fn scan(config: Config, events: Arc<Mutex<Vec<ScanEvent>>>) -> ScanReport {
+        let observer = move |event: &ScanEvent| events.lock().unwrap().push(event.clone());
+        ScannerBuilder::from_config(config)
+            .observer(Arc::new(observer))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resumed_scan_reports_the_same_as_a_full_scan() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+        let config = Config {
+            target: vec!["127.0.0.1".to_string(), "127.0.0.2".to_string()],
+            ports: format!("{},1-9", open_port),
+            ordered: true,
+            ..Config::default()
+        };
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let full = scan(config.clone(), events.clone()).await;
+
+        // Stop part-way: the first host is done, the second has half of its ports probed
+        let path = temp_path("resume");
+        let writer = CheckpointWriter::create(
+            &path,
+            Duration::ZERO,
+            CancellationToken::new(),
+            Checkpoint::new(&config, &full.metadata),
+        )
+        .unwrap();
+        let mut probed_second = 0;
+        for event in events.lock().unwrap().iter() {
+            match event {
+                ScanEvent::PortResult { address, .. } if address == "127.0.0.2" => {
+                    if probed_second == 5 {
+                        continue;
+                    }
+                    probed_second += 1;
+                }
+                ScanEvent::HostFinished { address, .. } if address == "127.0.0.2" => continue,
+                // A completed scan removes its checkpoint
+                ScanEvent::ScanFinished { .. } => continue,
+                _ => {}
+            }
+            writer.on_event(event);
+        }
+        let saved = Checkpoint::load(&path).unwrap();
+        assert_eq!(saved.finished.len(), 1);
+        assert_eq!(
+            saved
+                .done_ports("127.0.0.2", "127.0.0.2", Protocol::Tcp)
+                .len(),
+            5
+        );
+
+        let resumed_events = Arc::new(Mutex::new(Vec::new()));
+        let resumed = ScannerBuilder::resume(&path)
+            .unwrap()
+            .observer(Arc::new({
+                let events = resumed_events.clone();
+                move |event: &ScanEvent| events.lock().unwrap().push(event.clone())
+            }))
+            .build()
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        drop(listener);
+
+        // Only the ports that were left are probed again
+        let probed: Vec<String> = resumed_events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                ScanEvent::PortResult { address, .. } => Some(address.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(probed, vec!["127.0.0.2".to_string(); 5]);
+        // The checkpoint of a completed scan is removed
+        assert!(fs::metadata(&path).is_err());
+
+        let hosts = |report: &ScanReport| serde_json::to_value(&report.hosts).unwrap();
+        assert_eq!(hosts(&resumed), hosts(&full));
+        assert_eq!(resumed.metadata.start_time, full.metadata.start_time);
+    }
+}
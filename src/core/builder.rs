@@ -0,0 +1,297 @@
+use super::{CancellationToken, Scanner, TimingTemplate};
+use crate::args::Config;
+use crate::checkpoint::Checkpoint;
+use crate::events::{Observers, ScanObserver};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builds a [`Scanner`] from explicit settings
+///
+/// Every setting starts from the defaults of [`Config::default`], the process arguments are
+/// never read. The command line tool builds its [`Config`] from arguments and a config file
+/// and hands it to [`ScannerBuilder::from_config`].
+///
+/// ```no_run
+/// use rmap::core::Scanner;
+/// use std::time::Duration;
+///
+/// # async fn scan() -> Result<(), Box<dyn std::error::Error>> {
+/// let report = Scanner::builder()
+///     .target("192.168.1.10")
+///     .ports("22,80,443")
+///     .timeout(Duration::from_millis(500))
+///     .concurrency(256)
+///     .build()?
+///     .run()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScannerBuilder {
+    config: Config,
+    observers: Observers,
+    cancel: Option<CancellationToken>,
+    resume: Option<Checkpoint>,
+}
+
+impl ScannerBuilder {
+    pub fn new() -> ScannerBuilder {
+        ScannerBuilder::default()
+    }
+
+    /// Start from an existing configuration
+    pub fn from_config(config: Config) -> ScannerBuilder {
+        ScannerBuilder {
+            config,
+            observers: Observers::default(),
+            cancel: None,
+            resume: None,
+        }
+    }
+
+    /// Continue the scan saved in a checkpoint file
+    ///
+    /// The configuration is the one saved in the checkpoint. Hosts that were done are not
+    /// scanned again and ports already probed are skipped, the final report holds the results
+    /// of both runs. Progress keeps being saved to the same file unless the checkpoint names
+    /// another one.
+    pub fn resume(path: &str) -> Result<ScannerBuilder, Box<dyn Error>> {
+        let checkpoint = Checkpoint::load(path)?;
+        let mut config = checkpoint.config.clone();
+        config.ports_explicitly_specified = checkpoint.ports_explicitly_specified;
+        config.checkpoint.get_or_insert_with(|| path.to_string());
+        let mut builder = ScannerBuilder::from_config(config);
+        builder.resume = Some(checkpoint);
+        Ok(builder)
+    }
+
+    /// Add a target IP address or domain
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.config.target.push(target.into());
+        self
+    }
+
+    /// Add several targets
+    pub fn targets<I, S>(mut self, targets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config
+            .target
+            .extend(targets.into_iter().map(Into::into));
+        self
+    }
+
+    /// Ports or port ranges to scan (e.g., 80,443,1-1024)
+    ///
+    /// Ports set here count as explicitly requested, so tables list them in every state.
+    pub fn ports(mut self, ports: impl Into<String>) -> Self {
+        self.config.ports = ports.into();
+        self.config.ports_explicitly_specified = true;
+        self
+    }
+
+    /// Enable or disable TCP scanning
+    pub fn tcp(mut self, enabled: bool) -> Self {
+        self.config.tcp = enabled;
+        self
+    }
+
+    /// Enable or disable UDP scanning
+    pub fn udp(mut self, enabled: bool) -> Self {
+        self.config.udp = enabled;
+        self
+    }
+
+    /// Set parallelism, timeouts and retries from a timing template
+    ///
+    /// Settings made after this call override the template's values.
+    pub fn timing(mut self, template: TimingTemplate) -> Self {
+        template.apply(&mut self.config);
+        self
+    }
+
+    /// Timeout per probe until round trip times have been measured
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout.as_millis() as u64;
+        self
+    }
+
+    /// Lower bound of the adaptive probe timeout
+    pub fn min_rtt_timeout(mut self, timeout: Duration) -> Self {
+        self.config.min_rtt_timeout = timeout.as_millis() as u64;
+        self
+    }
+
+    /// Upper bound of the adaptive probe timeout
+    pub fn max_rtt_timeout(mut self, timeout: Duration) -> Self {
+        self.config.max_rtt_timeout = timeout.as_millis() as u64;
+        self
+    }
+
+    /// How often a probe without an answer is sent again
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.config.max_retries = retries;
+        self
+    }
+
+    /// Number of probes in flight at the same time
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.config.threads = concurrency as u64;
+        self
+    }
+
+    /// Send at least this many probes per second, beyond the concurrency when needed
+    pub fn min_rate(mut self, rate: f64) -> Self {
+        self.config.min_rate = Some(rate);
+        self
+    }
+
+    /// Send at most this many probes per second across every host and protocol
+    pub fn max_rate(mut self, rate: f64) -> Self {
+        self.config.max_rate = Some(rate);
+        self
+    }
+
+    /// Send at most this many probes per second to a single host
+    pub fn max_host_rate(mut self, rate: f64) -> Self {
+        self.config.max_host_rate = Some(rate);
+        self
+    }
+
+    /// Probe ports in the order given and hosts one after another instead of randomizing
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.config.ordered = ordered;
+        self
+    }
+
+    /// Seed of the randomized probe order, scans with the same seed probe in the same order
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    /// Lua scripts to run: names, globs, paths, directories, categories or expressions
+    pub fn scripts(mut self, selection: impl Into<String>) -> Self {
+        self.config.lua_script = Some(selection.into());
+        self
+    }
+
+    /// Directory containing Lua scripts
+    pub fn scripts_dir(mut self, dir: impl Into<String>) -> Self {
+        self.config.scripts_dir = dir.into();
+        self
+    }
+
+    /// Arguments passed to Lua scripts
+    pub fn script_args(mut self, script_args: HashMap<String, String>) -> Self {
+        self.config.script_args.extend(script_args);
+        self
+    }
+
+    /// Detect the product and version of the services on open ports
+    pub fn service_version(mut self, enabled: bool) -> Self {
+        self.config.service_version = enabled;
+        self
+    }
+
+    /// Brute-force directories and files on every open HTTP(S) port
+    pub fn web_enum(mut self, enabled: bool) -> Self {
+        self.config.web_enum = enabled;
+        self
+    }
+
+    /// Write the JSON report to a file when run with [`Scanner::exec`]
+    pub fn json_output(mut self, path: impl Into<String>) -> Self {
+        self.config.json = Some(path.into());
+        self
+    }
+
+    /// Write nmap-compatible XML to a file when run with [`Scanner::exec`]
+    pub fn xml_output(mut self, path: impl Into<String>) -> Self {
+        self.config.xml = Some(path.into());
+        self
+    }
+
+    /// Write grepable output to a file when run with [`Scanner::exec`]
+    pub fn grepable_output(mut self, path: impl Into<String>) -> Self {
+        self.config.grepable = Some(path.into());
+        self
+    }
+
+    /// Write CSV rows to a file when run with [`Scanner::exec`]
+    pub fn csv_output(mut self, path: impl Into<String>) -> Self {
+        self.config.csv = Some(path.into());
+        self
+    }
+
+    /// Write TSV rows to a file when run with [`Scanner::exec`]
+    pub fn tsv_output(mut self, path: impl Into<String>) -> Self {
+        self.config.tsv = Some(path.into());
+        self
+    }
+
+    /// Write an HTML report to a file when run with [`Scanner::exec`]
+    pub fn html_output(mut self, path: impl Into<String>) -> Self {
+        self.config.html = Some(path.into());
+        self
+    }
+
+    /// Stream events as NDJSON to a file while the scan runs, `-` for stdout
+    pub fn ndjson_output(mut self, path: impl Into<String>) -> Self {
+        self.config.ndjson = Some(path.into());
+        self
+    }
+
+    /// Periodically save scan progress to a file so the scan can be resumed
+    pub fn checkpoint(mut self, path: impl Into<String>) -> Self {
+        self.config.checkpoint = Some(path.into());
+        self
+    }
+
+    /// Time between checkpoint writes
+    pub fn checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.config.checkpoint_interval = interval.as_secs();
+        self
+    }
+
+    /// Receive progress and result events while the scan runs
+    pub fn observer(mut self, observer: Arc<dyn ScanObserver>) -> Self {
+        self.observers.add(observer);
+        self
+    }
+
+    /// Stop the scanner with an existing token, e.g. one shared by several scanners
+    pub fn cancellation_token(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Check the settings and create the scanner
+    pub fn build(self) -> Result<Scanner, Box<dyn Error>> {
+        let ScannerBuilder {
+            config,
+            observers,
+            cancel,
+            resume,
+        } = self;
+        if config.target.is_empty() {
+            return Err("No target specified".into());
+        }
+        config.validate()?;
+        if !config.tcp && !config.udp {
+            return Err("Neither TCP nor UDP scanning is enabled".into());
+        }
+        let mut scanner = Scanner::new(config);
+        scanner.observers = observers;
+        if let Some(cancel) = cancel {
+            scanner.cancel = cancel;
+        }
+        scanner.resume = resume.map(Arc::new);
+        Ok(scanner)
+    }
+}
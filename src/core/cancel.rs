@@ -0,0 +1,55 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long probes already in flight may keep waiting for an answer after cancellation
+pub const DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Stops a running scan
+///
+/// Clones share their state, cancelling one cancels all of them. Scanners stop sending new
+/// probes once the token is cancelled and give probes in flight [`DRAIN_TIMEOUT`] to finish.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled
+    pub async fn cancelled(&self) {
+        let notified = self.inner.notify.notified();
+        tokio::pin!(notified);
+        // Register before checking the flag so a concurrent cancel cannot be missed
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+
+    /// Wait until the token is cancelled and the drain period has passed
+    pub async fn drained(&self) {
+        self.cancelled().await;
+        tokio::time::sleep(DRAIN_TIMEOUT).await;
+    }
+}
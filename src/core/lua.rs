@@ -1,14 +1,35 @@
-use mlua::{Lua, Result as LuaResult, Table, Value};
-use std::{collections::HashMap, error::Error, fs, path::Path};
+use super::cancel::CancellationToken;
+use super::lualib::{self, json::lua_to_json};
+use super::scripts::{self, ScriptInfo};
+use super::timing::HostTiming;
+use mlua::{HookTriggers, Lua, Result as LuaResult, Table, Value, VmState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+/// VM instructions between checks for cancellation
+const CANCEL_CHECK_INSTRUCTIONS: u32 = 10_000;
 
 /// Lua script execution context for host-based scripts
 pub struct LuaScriptRunner {
     lua: Lua,
     scripts_dir: String,
+    /// Names of the scripts in `scripts_dir`, the namespaces of script arguments
+    script_names: HashSet<String>,
+    script_args: HashMap<String, String>,
+    log_to_stderr: bool,
+    quiet: bool,
+    cancel: Option<CancellationToken>,
 }
 
 /// Result of script execution
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScriptResult {
     pub script_name: String,
     pub host: String,
@@ -16,34 +37,162 @@ pub struct ScriptResult {
     pub success: bool,
     pub output: String,
     pub error: Option<String>,
-    pub data: HashMap<String, String>,
+    /// The script's `result` value as a JSON-compatible tree
+    pub data: JsonValue,
+}
+
+impl ScriptResult {
+    /// Result for a script that could not be run at all
+    pub fn failed(script_name: &str, host: &str, port: Option<u16>, error: impl ToString) -> Self {
+        ScriptResult {
+            script_name: script_name.to_string(),
+            host: host.to_string(),
+            port,
+            success: false,
+            output: String::new(),
+            error: Some(error.to_string()),
+            data: JsonValue::Null,
+        }
+    }
+
+    /// Whether the script returned any structured data
+    pub fn has_data(&self) -> bool {
+        match &self.data {
+            JsonValue::Null => false,
+            JsonValue::Object(map) => !map.is_empty(),
+            JsonValue::Array(items) => !items.is_empty(),
+            _ => true,
+        }
+    }
 }
 
 impl LuaScriptRunner {
-    /// Create a new Lua script runner
+    /// Create a new Lua script runner with the bundled standard library loaded
     pub fn new() -> Result<Self, Box<dyn Error>> {
         let lua = Lua::new();
+        lualib::register(&lua)?;
 
+        let scripts_dir = "scripts".to_string();
         Ok(LuaScriptRunner {
             lua,
-            scripts_dir: "scripts".to_string(),
+            script_names: script_names(&scripts_dir),
+            scripts_dir,
+            script_args: HashMap::new(),
+            log_to_stderr: false,
+            quiet: false,
+            cancel: None,
         })
     }
 
     /// Set the scripts directory
     pub fn set_scripts_dir(&mut self, dir: &str) {
         self.scripts_dir = dir.to_string();
+        self.script_names = script_names(dir);
+    }
+
+    /// Set the arguments exposed to scripts through the read-only `args` table
+    ///
+    /// Keys of the form `<script>.<key>` are only visible as `args.<key>` to the script
+    /// with that name, where they take precedence over an un-namespaced `<key>`. Keys with a
+    /// prefix that names no script, such as `http.useragent`, are visible to every script.
+    pub fn set_script_args(&mut self, script_args: HashMap<String, String>) {
+        self.script_args = script_args;
+    }
+
+    /// Send messages from the `log` function to stderr instead of stdout
+    pub fn set_log_to_stderr(&mut self, log_to_stderr: bool) {
+        self.log_to_stderr = log_to_stderr;
+    }
+
+    /// Discard messages from the `log` and `debug` functions
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Make network helpers wait for the rate limits of `timing` before each request
+    pub fn set_timing(&mut self, timing: Arc<HostTiming>) {
+        lualib::set_timing(&self.lua, timing);
+    }
+
+    /// Abort running scripts with an error once `cancel` is cancelled
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) {
+        let token = cancel.clone();
+        // Checked every few thousand VM instructions, a script blocked in a network call
+        // stops once that call returns
+        let hook = self.lua.set_hook(
+            HookTriggers::new().every_nth_instruction(CANCEL_CHECK_INSTRUCTIONS),
+            move |_, _| {
+                if token.is_cancelled() {
+                    Err(mlua::Error::runtime("scan cancelled"))
+                } else {
+                    Ok(VmState::Continue)
+                }
+            },
+        );
+        if hook.is_ok() {
+            self.cancel = Some(cancel);
+        }
+    }
+
+    /// Build the read-only `args` table for a given script
+    fn create_args_table(&self, script_name: &str) -> LuaResult<Table> {
+        let values = self.lua.create_table()?;
+        let prefix = format!("{}.", script_name);
+        let namespaced = |key: &str| {
+            key.split_once('.').is_some_and(|(namespace, _)| {
+                namespace == script_name || self.script_names.contains(namespace)
+            })
+        };
+
+        for (key, value) in &self.script_args {
+            if !namespaced(key) {
+                values.set(key.as_str(), value.as_str())?;
+            }
+        }
+        for (key, value) in &self.script_args {
+            if let Some(local_key) = key.strip_prefix(&prefix) {
+                values.set(local_key, value.as_str())?;
+            }
+        }
+
+        // Expose the values through an empty proxy so scripts cannot modify them
+        let proxy = self.lua.create_table()?;
+        let metatable = self.lua.create_table()?;
+        metatable.set("__index", values.clone())?;
+        metatable.set(
+            "__newindex",
+            self.lua
+                .create_function(|_, (_, key): (Table, Value)| -> LuaResult<()> {
+                    Err(mlua::Error::runtime(format!(
+                        "script arguments are read-only (attempt to set '{}')",
+                        key.to_string().unwrap_or_default()
+                    )))
+                })?,
+        )?;
+        let next: mlua::Function = self.lua.globals().get("next")?;
+        metatable.set(
+            "__pairs",
+            self.lua.create_function(move |_, _: Table| {
+                Ok((next.clone(), values.clone(), Value::Nil))
+            })?,
+        )?;
+        metatable.set("__metatable", false)?;
+        proxy.set_metatable(Some(metatable))?;
+
+        Ok(proxy)
     }
 
     /// Initialize Lua environment with host scanning functions
     pub fn init_environment(&self, host: &str, port: Option<u16>) -> LuaResult<()> {
         let globals = self.lua.globals();
 
+        // Clear results left behind by a previously executed script
+        globals.set("result", Value::Nil)?;
+        globals.set("output", Value::Nil)?;
+
         // Set host information
         globals.set("HOST", host)?;
-        if let Some(p) = port {
-            globals.set("PORT", p)?;
-        }
+        globals.set("PORT", port)?;
 
         // Create host table with utility functions
         let host_table = self.lua.create_table()?;
@@ -77,14 +226,25 @@ impl LuaScriptRunner {
         globals.set("host", host_table)?;
 
         // Add logging functions
-        let log_fn = self.lua.create_function(|_, msg: String| {
-            println!("[SCRIPT] {}", msg);
+        let log_to_stderr = self.log_to_stderr;
+        let quiet = self.quiet;
+        let log_fn = self.lua.create_function(move |_, msg: String| {
+            if quiet {
+                return Ok(());
+            }
+            if log_to_stderr {
+                eprintln!("[SCRIPT] {}", msg);
+            } else {
+                println!("[SCRIPT] {}", msg);
+            }
             Ok(())
         })?;
         globals.set("log", log_fn)?;
 
-        let debug_fn = self.lua.create_function(|_, msg: String| {
-            eprintln!("[DEBUG] {}", msg);
+        let debug_fn = self.lua.create_function(move |_, msg: String| {
+            if !quiet {
+                eprintln!("[DEBUG] {}", msg);
+            }
             Ok(())
         })?;
         globals.set("debug", debug_fn)?;
@@ -92,7 +252,7 @@ impl LuaScriptRunner {
         Ok(())
     }
 
-    /// Execute a Lua script against a host
+    /// Execute a Lua script from the scripts directory against a host
     pub async fn run_script(
         &self,
         script_name: &str,
@@ -100,6 +260,33 @@ impl LuaScriptRunner {
         port: Option<u16>,
     ) -> Result<ScriptResult, Box<dyn Error>> {
         let script_path = Path::new(&self.scripts_dir).join(format!("{}.lua", script_name));
+        self.run_script_file(&script_path, host, port).await
+    }
+
+    /// Execute the Lua script at `script_path` against a host
+    pub async fn run_script_file(
+        &self,
+        script_path: &Path,
+        host: &str,
+        port: Option<u16>,
+    ) -> Result<ScriptResult, Box<dyn Error>> {
+        let script_name = script_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.is_cancelled())
+        {
+            return Ok(ScriptResult::failed(
+                script_name,
+                host,
+                port,
+                "scan cancelled",
+            ));
+        }
 
         if !script_path.exists() {
             return Ok(ScriptResult {
@@ -109,47 +296,27 @@ impl LuaScriptRunner {
                 success: false,
                 output: String::new(),
                 error: Some(format!("Script file not found: {}", script_path.display())),
-                data: HashMap::new(),
+                data: JsonValue::Null,
             });
         }
 
-        let script_content = fs::read_to_string(&script_path)?;
+        let script_content = fs::read_to_string(script_path)?;
 
         // Initialize environment for this execution
         self.init_environment(host, port)?;
+        self.lua
+            .globals()
+            .set("args", self.create_args_table(script_name)?)?;
 
         // Execute the script
         match self.lua.load(&script_content).exec() {
             Ok(_) => {
                 // Try to get results from the script
                 let globals = self.lua.globals();
-                let mut data = HashMap::new();
                 let mut output = String::new();
 
                 // Check if script set any result variables
-                if let Ok(result_table) = globals.get::<Table>("result") {
-                    for pair in result_table.pairs::<String, Value>() {
-                        if let Ok((key, value)) = pair {
-                            match value {
-                                Value::String(s) => {
-                                    data.insert(key, s.to_str()?.to_string());
-                                }
-                                Value::Integer(i) => {
-                                    data.insert(key, i.to_string());
-                                }
-                                Value::Number(n) => {
-                                    data.insert(key, n.to_string());
-                                }
-                                Value::Boolean(b) => {
-                                    data.insert(key, b.to_string());
-                                }
-                                _ => {
-                                    data.insert(key, "<complex_value>".to_string());
-                                }
-                            }
-                        }
-                    }
-                }
+                let data = lua_to_json(&globals.get::<Value>("result")?);
 
                 // Check if script set output
                 if let Ok(output_str) = globals.get::<String>("output") {
@@ -173,36 +340,29 @@ impl LuaScriptRunner {
                 success: false,
                 output: String::new(),
                 error: Some(e.to_string()),
-                data: HashMap::new(),
+                data: JsonValue::Null,
             }),
         }
     }
 
     /// List available scripts in the scripts directory
     pub fn list_scripts(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        let scripts_path = Path::new(&self.scripts_dir);
-
-        if !scripts_path.exists() {
-            return Ok(Vec::new());
-        }
-
-        let mut scripts = Vec::new();
-
-        for entry in fs::read_dir(scripts_path)? {
-            let entry = entry?;
-            let path = entry.path();
+        Ok(self
+            .describe_scripts()?
+            .into_iter()
+            .map(|script| script.name)
+            .collect())
+    }
 
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "lua") {
-                if let Some(stem) = path.file_stem() {
-                    if let Some(name) = stem.to_str() {
-                        scripts.push(name.to_string());
-                    }
-                }
-            }
-        }
+    /// List available scripts in the scripts directory along with their metadata
+    pub fn describe_scripts(&self) -> Result<Vec<ScriptInfo>, Box<dyn Error>> {
+        scripts::discover_scripts(Path::new(&self.scripts_dir))
+    }
 
-        scripts.sort();
-        Ok(scripts)
+    /// Resolve a script selection (names, globs, paths, categories or expressions)
+    /// against the scripts directory
+    pub fn select_scripts(&self, selection: &str) -> Result<Vec<ScriptInfo>, Box<dyn Error>> {
+        scripts::select_scripts(selection, Path::new(&self.scripts_dir))
     }
 
     /// Execute multiple scripts against a host
@@ -225,7 +385,7 @@ impl LuaScriptRunner {
                         success: false,
                         output: String::new(),
                         error: Some(e.to_string()),
-                        data: HashMap::new(),
+                        data: JsonValue::Null,
                     });
                 }
             }
@@ -249,3 +409,16 @@ impl LuaScriptRunner {
 pub fn new_script_runner() -> Result<LuaScriptRunner, Box<dyn Error>> {
     LuaScriptRunner::new()
 }
+
+/// Names of the `.lua` scripts in `dir`, empty when it cannot be read
+fn script_names(dir: &str) -> HashSet<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashSet::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lua"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect()
+}
@@ -0,0 +1,374 @@
+//! Binary packing for protocol scripts
+//!
+//! Format characters:
+//!
+//! * `>` / `<` - switch to big-endian (the default) / little-endian
+//! * `B` `b` `H` `h` `I` `i` `L` `l` - unsigned/signed 8, 16, 32 and 64 bit integers,
+//!   an optional count repeats the item (`H3`)
+//! * `c<n>` - exactly `n` raw bytes
+//! * `s<n>` - string prefixed by an `n`-byte length (default 2), longer strings are an error
+//! * `z` - zero-terminated string
+//! * `x` - a zero padding byte, an optional count repeats it
+//!
+//! Counts are at most 65536 and a format expands to at most 65536 items.
+
+use mlua::{Lua, MultiValue, Result as LuaResult, Table, Value};
+
+/// Largest count after a format character, and most items a format may expand to
+const MAX_COUNT: usize = 65_536;
+
+enum Item {
+    BigEndian,
+    LittleEndian,
+    Integer { size: usize, signed: bool },
+    Fixed(usize),
+    Prefixed(usize),
+    ZeroTerminated,
+    Padding,
+}
+
+pub fn create(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    // bin.pack(format, ...) -> string
+    module.set(
+        "pack",
+        lua.create_function(|lua, (format, values): (String, MultiValue)| {
+            let bytes = pack(&format, values.into_iter().collect())?;
+            lua.create_string(bytes)
+        })?,
+    )?;
+
+    // bin.unpack(format, data [, pos]) -> values..., next_pos
+    module.set(
+        "unpack",
+        lua.create_function(
+            |lua, (format, data, pos): (String, mlua::String, Option<usize>)| {
+                unpack(lua, &format, &data.as_bytes(), pos.unwrap_or(1))
+            },
+        )?,
+    )?;
+
+    Ok(module)
+}
+
+fn parse_format(format: &str) -> LuaResult<Vec<Item>> {
+    let chars: Vec<char> = format.chars().collect();
+    let mut items = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let code = chars[i];
+        i += 1;
+
+        let digits_start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        let count: Option<usize> = if i > digits_start {
+            let digits: String = chars[digits_start..i].iter().collect();
+            match digits.parse() {
+                Ok(count) if count <= MAX_COUNT => Some(count),
+                _ => {
+                    return Err(mlua::Error::runtime(format!(
+                        "bin: count {} is above the limit of {}",
+                        digits, MAX_COUNT
+                    )));
+                }
+            }
+        } else {
+            None
+        };
+
+        let integer = |size, signed| {
+            (0..count.unwrap_or(1))
+                .map(|_| Item::Integer { size, signed })
+                .collect::<Vec<_>>()
+        };
+
+        match code {
+            '>' => items.push(Item::BigEndian),
+            '<' => items.push(Item::LittleEndian),
+            'B' => items.extend(integer(1, false)),
+            'b' => items.extend(integer(1, true)),
+            'H' => items.extend(integer(2, false)),
+            'h' => items.extend(integer(2, true)),
+            'I' => items.extend(integer(4, false)),
+            'i' => items.extend(integer(4, true)),
+            'L' => items.extend(integer(8, false)),
+            'l' => items.extend(integer(8, true)),
+            'c' => {
+                items.push(Item::Fixed(count.ok_or_else(|| {
+                    mlua::Error::runtime("bin: 'c' requires a byte count")
+                })?))
+            }
+            's' => {
+                let size = count.unwrap_or(2);
+                if !matches!(size, 1 | 2 | 4 | 8) {
+                    return Err(mlua::Error::runtime(format!(
+                        "bin: invalid length prefix size {}",
+                        size
+                    )));
+                }
+                items.push(Item::Prefixed(size));
+            }
+            'z' => items.push(Item::ZeroTerminated),
+            'x' => items.extend((0..count.unwrap_or(1)).map(|_| Item::Padding)),
+            ' ' => {}
+            other => {
+                return Err(mlua::Error::runtime(format!(
+                    "bin: invalid format character '{}'",
+                    other
+                )));
+            }
+        }
+        if items.len() > MAX_COUNT {
+            return Err(mlua::Error::runtime(format!(
+                "bin: format expands to more than {} items",
+                MAX_COUNT
+            )));
+        }
+    }
+
+    Ok(items)
+}
+
+fn write_uint(out: &mut Vec<u8>, value: u64, size: usize, little_endian: bool) {
+    let bytes = value.to_le_bytes();
+    if little_endian {
+        out.extend_from_slice(&bytes[..size]);
+    } else {
+        out.extend(bytes[..size].iter().rev());
+    }
+}
+
+fn read_uint(data: &[u8], size: usize, little_endian: bool) -> u64 {
+    let mut value = 0u64;
+    for i in 0..size {
+        let byte = if little_endian {
+            data[size - 1 - i]
+        } else {
+            data[i]
+        };
+        value = (value << 8) | byte as u64;
+    }
+    value
+}
+
+fn pack(format: &str, values: Vec<Value>) -> LuaResult<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut little_endian = false;
+    let mut values = values.into_iter();
+    let mut next_value = |what: &str| {
+        values
+            .next()
+            .ok_or_else(|| mlua::Error::runtime(format!("bin.pack: missing value for {}", what)))
+    };
+
+    for item in parse_format(format)? {
+        match item {
+            Item::BigEndian => little_endian = false,
+            Item::LittleEndian => little_endian = true,
+            Item::Integer { size, .. } => {
+                let value = match next_value("integer")? {
+                    Value::Integer(i) => i,
+                    Value::Number(n) => n as i64,
+                    other => {
+                        return Err(mlua::Error::runtime(format!(
+                            "bin.pack: expected integer, got {}",
+                            other.type_name()
+                        )));
+                    }
+                };
+                write_uint(&mut out, value as u64, size, little_endian);
+            }
+            Item::Fixed(len) => {
+                let bytes = value_bytes(next_value("c")?)?;
+                let mut bytes = bytes[..bytes.len().min(len)].to_vec();
+                bytes.resize(len, 0);
+                out.extend(bytes);
+            }
+            Item::Prefixed(size) => {
+                let bytes = value_bytes(next_value("s")?)?;
+                if size < 8 && bytes.len() as u64 >= 1 << (size * 8) {
+                    return Err(mlua::Error::runtime(format!(
+                        "bin.pack: string of {} bytes does not fit a {}-byte length",
+                        bytes.len(),
+                        size
+                    )));
+                }
+                write_uint(&mut out, bytes.len() as u64, size, little_endian);
+                out.extend(bytes);
+            }
+            Item::ZeroTerminated => {
+                out.extend(value_bytes(next_value("z")?)?);
+                out.push(0);
+            }
+            Item::Padding => out.push(0),
+        }
+    }
+
+    Ok(out)
+}
+
+fn value_bytes(value: Value) -> LuaResult<Vec<u8>> {
+    match value {
+        Value::String(s) => Ok(s.as_bytes().to_vec()),
+        Value::Integer(i) => Ok(i.to_string().into_bytes()),
+        Value::Number(n) => Ok(n.to_string().into_bytes()),
+        other => Err(mlua::Error::runtime(format!(
+            "bin.pack: expected string, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn unpack(lua: &Lua, format: &str, data: &[u8], pos: usize) -> LuaResult<MultiValue> {
+    let mut offset = pos.saturating_sub(1);
+    let mut little_endian = false;
+    let mut results = Vec::new();
+
+    let take = |offset: &mut usize, len: usize| -> LuaResult<&[u8]> {
+        // Lengths come from the data itself, which may be hostile
+        let end = offset
+            .checked_add(len)
+            .filter(|end| *end <= data.len())
+            .ok_or_else(|| mlua::Error::runtime("bin.unpack: data string too short"))?;
+        let slice = &data[*offset..end];
+        *offset = end;
+        Ok(slice)
+    };
+
+    for item in parse_format(format)? {
+        match item {
+            Item::BigEndian => little_endian = false,
+            Item::LittleEndian => little_endian = true,
+            Item::Integer { size, signed } => {
+                let raw = read_uint(take(&mut offset, size)?, size, little_endian);
+                let value = if signed && size < 8 {
+                    let shift = 64 - size * 8;
+                    ((raw << shift) as i64) >> shift
+                } else {
+                    raw as i64
+                };
+                results.push(Value::Integer(value));
+            }
+            Item::Fixed(len) => {
+                results.push(Value::String(lua.create_string(take(&mut offset, len)?)?));
+            }
+            Item::Prefixed(size) => {
+                let len = read_uint(take(&mut offset, size)?, size, little_endian);
+                let len = usize::try_from(len).unwrap_or(usize::MAX);
+                results.push(Value::String(lua.create_string(take(&mut offset, len)?)?));
+            }
+            Item::ZeroTerminated => {
+                let end = data[offset.min(data.len())..]
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| mlua::Error::runtime("bin.unpack: unterminated string"))?;
+                results.push(Value::String(lua.create_string(take(&mut offset, end)?)?));
+                offset += 1;
+            }
+            Item::Padding => {
+                take(&mut offset, 1)?;
+            }
+        }
+    }
+
+    results.push(Value::Integer(offset as i64 + 1));
+    Ok(MultiValue::from_iter(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        lua.globals().set("bin", create(&lua).unwrap()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn pack_and_unpack_round_trip() {
+        let lua = lua();
+        let (a, b, c, d, e, f, g, next): (i64, i64, i64, i64, String, String, String, i64) = lua
+            .load(
+                r#"
+                local data = bin.pack(">BhI<l c4 s1 z x2", 255, -2, 70000, -5, "ab", "hello", "end")
+                return bin.unpack(">BhI<l c4 s1 z x2", data)
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert_eq!((a, b, c, d), (255, -2, 70000, -5));
+        assert_eq!(e, "ab\0\0");
+        assert_eq!(f, "hello");
+        assert_eq!(g, "end");
+        assert_eq!(next, 1 + 1 + 2 + 4 + 8 + 4 + 6 + 4 + 2);
+    }
+
+    #[test]
+    fn byte_order_is_applied() {
+        assert_eq!(pack(">H", vec![Value::Integer(0x0102)]).unwrap(), [1, 2]);
+        assert_eq!(pack("<H", vec![Value::Integer(0x0102)]).unwrap(), [2, 1]);
+        assert_eq!(pack("<s2", vec![Value::Integer(7)]).unwrap(), [1, 0, b'7']);
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let lua = lua();
+        for (format, data) in [
+            ("I", "abc"),
+            ("c5", "abcd"),
+            ("s1", "\x05abc"),
+            ("s8", "\u{7f}\u{7f}\u{7f}\u{7f}\u{7f}\u{7f}\u{7f}\u{7f}"),
+            ("z", "no terminator"),
+            ("x", ""),
+        ] {
+            let result = lua
+                .load(format!("return bin.unpack({:?}, {:?})", format, data))
+                .eval::<MultiValue>();
+            assert!(result.is_err(), "{} accepted {:?}", format, data);
+        }
+    }
+
+    #[test]
+    fn unpack_starts_at_the_given_position() {
+        let lua = lua();
+        let (value, next): (i64, i64) = lua
+            .load(r#"return bin.unpack("B", "\1\2\3", 3)"#)
+            .eval()
+            .unwrap();
+        assert_eq!((value, next), (3, 4));
+    }
+
+    #[test]
+    fn oversized_prefix_is_an_error() {
+        let lua = lua();
+        let error = lua
+            .load(r#"return bin.pack("s1", ("a"):rep(300))"#)
+            .eval::<mlua::String>()
+            .unwrap_err();
+        assert!(error.to_string().contains("does not fit"), "{}", error);
+
+        let packed: mlua::String = lua
+            .load(r#"return bin.pack("s1", ("a"):rep(255))"#)
+            .eval()
+            .unwrap();
+        assert_eq!(packed.as_bytes()[0], 255);
+        let packed: mlua::String = lua
+            .load(r#"return bin.pack("s2", ("a"):rep(300))"#)
+            .eval()
+            .unwrap();
+        assert_eq!(packed.as_bytes()[..2], [1, 44]);
+    }
+
+    #[test]
+    fn invalid_formats_are_errors() {
+        for format in ["q", "c", "s3", "B65537", "x65536B"] {
+            assert!(parse_format(format).is_err(), "{}", format);
+        }
+        assert_eq!(parse_format("H3 x2").unwrap().len(), 5);
+    }
+}
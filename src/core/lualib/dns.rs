@@ -0,0 +1,29 @@
+use super::block_on;
+use crate::dns::DNSResolver;
+use mlua::{Lua, Result as LuaResult, Table, Value};
+
+pub fn create(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    // dns.query(name [, type]) -> { record, ... } | nil, err
+    module.set(
+        "query",
+        lua.create_function(|lua, (name, record_type): (String, Option<String>)| {
+            let record_type = record_type.unwrap_or_else(|| "A".to_string());
+            let answers = block_on(async {
+                DNSResolver::new()
+                    .query(&name, &record_type)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .and_then(|answers| answers);
+
+            match answers {
+                Ok(answers) => Ok((Value::Table(lua.create_sequence_from(answers)?), None)),
+                Err(e) => Ok((Value::Nil, Some(e))),
+            }
+        })?,
+    )?;
+
+    Ok(module)
+}
@@ -0,0 +1,45 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use mlua::{Lua, Result as LuaResult, Table, Value};
+
+pub fn create_base64(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    module.set(
+        "encode",
+        lua.create_function(|_, data: mlua::String| Ok(STANDARD.encode(data.as_bytes())))?,
+    )?;
+
+    module.set(
+        "decode",
+        lua.create_function(|lua, text: mlua::String| {
+            match STANDARD.decode(text.as_bytes().trim_ascii()) {
+                Ok(bytes) => Ok((Value::String(lua.create_string(bytes)?), None)),
+                Err(e) => Ok((Value::Nil, Some(e.to_string()))),
+            }
+        })?,
+    )?;
+
+    Ok(module)
+}
+
+pub fn create_hex(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    module.set(
+        "encode",
+        lua.create_function(|_, data: mlua::String| Ok(hex::encode(data.as_bytes())))?,
+    )?;
+
+    module.set(
+        "decode",
+        lua.create_function(|lua, text: mlua::String| {
+            match hex::decode(text.as_bytes().trim_ascii()) {
+                Ok(bytes) => Ok((Value::String(lua.create_string(bytes)?), None)),
+                Err(e) => Ok((Value::Nil, Some(e.to_string()))),
+            }
+        })?,
+    )?;
+
+    Ok(module)
+}
@@ -0,0 +1,30 @@
+use md5::Md5;
+use mlua::{Lua, Result as LuaResult, Table};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Hash functions returning lowercase hex digests
+pub fn create(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    module.set(
+        "md5",
+        lua.create_function(|_, data: mlua::String| Ok(hex::encode(Md5::digest(data.as_bytes()))))?,
+    )?;
+
+    module.set(
+        "sha1",
+        lua.create_function(|_, data: mlua::String| {
+            Ok(hex::encode(Sha1::digest(data.as_bytes())))
+        })?,
+    )?;
+
+    module.set(
+        "sha256",
+        lua.create_function(|_, data: mlua::String| {
+            Ok(hex::encode(Sha256::digest(data.as_bytes())))
+        })?,
+    )?;
+
+    Ok(module)
+}
@@ -0,0 +1,206 @@
+use super::{DEFAULT_TIMEOUT_MS, blocking, wait_turn};
+use crate::net::http::{self, HttpRequest, HttpResponse};
+use mlua::{Lua, Result as LuaResult, Table, Value};
+use std::time::Duration;
+
+/// Redirects followed unless the script sets `redirects` in the options
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+
+pub fn create(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    // http.get(url [, options]) -> response | nil, err
+    module.set(
+        "get",
+        lua.create_function(|lua, (url, options): (String, Option<Table>)| {
+            perform(lua, "GET", &url, None, options)
+        })?,
+    )?;
+
+    // http.post(url, body [, options]) -> response | nil, err
+    module.set(
+        "post",
+        lua.create_function(
+            |lua, (url, body, options): (String, Option<mlua::String>, Option<Table>)| {
+                perform(
+                    lua,
+                    "POST",
+                    &url,
+                    body.map(|b| b.as_bytes().to_vec()),
+                    options,
+                )
+            },
+        )?,
+    )?;
+
+    // http.request(method, url [, options]) -> response | nil, err
+    module.set(
+        "request",
+        lua.create_function(
+            |lua, (method, url, options): (String, String, Option<Table>)| {
+                perform(lua, &method, &url, None, options)
+            },
+        )?,
+    )?;
+
+    Ok(module)
+}
+
+/// Build and send a request from Lua arguments
+///
+/// Supported options: `headers` (table), `body` (string), `redirects` (number or
+/// `false`) and `timeout` (milliseconds).
+fn perform(
+    lua: &Lua,
+    method: &str,
+    url: &str,
+    body: Option<Vec<u8>>,
+    options: Option<Table>,
+) -> LuaResult<(Value, Option<String>)> {
+    let mut request = HttpRequest::new(method, url);
+    request.body = body;
+    request.max_redirects = DEFAULT_MAX_REDIRECTS;
+    request.timeout = Duration::from_millis(DEFAULT_TIMEOUT_MS);
+
+    if let Some(options) = options {
+        if let Some(headers) = options.get::<Option<Table>>("headers")? {
+            for (name, value) in headers.pairs::<String, String>().flatten() {
+                request.headers.push((name, value));
+            }
+        }
+        if let Some(body) = options.get::<Option<mlua::String>>("body")? {
+            request.body = Some(body.as_bytes().to_vec());
+        }
+        match options.get::<Value>("redirects")? {
+            Value::Boolean(false) => request.max_redirects = 0,
+            Value::Integer(n) => request.max_redirects = n.max(0) as usize,
+            _ => {}
+        }
+        if let Some(timeout) = options.get::<Option<u64>>("timeout")? {
+            request.timeout = Duration::from_millis(timeout);
+        }
+    }
+
+    wait_turn(lua);
+    match blocking(|| http::send(&request)) {
+        Ok(response) => Ok((Value::Table(response_to_table(lua, &response)?), None)),
+        Err(e) => Ok((Value::Nil, Some(e.to_string()))),
+    }
+}
+
+fn response_to_table(lua: &Lua, response: &HttpResponse) -> LuaResult<Table> {
+    let table = lua.create_table()?;
+    table.set("status", response.status)?;
+    table.set("reason", response.reason.as_str())?;
+    table.set("version", response.version.as_str())?;
+    table.set("url", response.url.as_str())?;
+    table.set("body", lua.create_string(&response.body)?)?;
+
+    let headers = lua.create_table()?;
+    for (name, value) in &response.headers {
+        // Repeated headers are joined the same way HTTP allows them to be folded
+        let merged = match headers.get::<Option<String>>(name.as_str())? {
+            Some(existing) => format!("{}, {}", existing, value),
+            None => value.clone(),
+        };
+        headers.set(name.as_str(), merged)?;
+    }
+    table.set("headers", headers)?;
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Answer one connection with `response` and return the URL to request
+    fn serve(response: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(response);
+        });
+        format!("http://127.0.0.1:{}/path", port)
+    }
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        lua.globals().set("http", create(&lua).unwrap()).unwrap();
+        lua
+    }
+
+    fn get(lua: &Lua, url: &str) -> (Value, Option<String>) {
+        lua.load(format!("return http.get({:?})", url))
+            .eval()
+            .unwrap()
+    }
+
+    #[test]
+    fn response_fields_and_folded_headers() {
+        let lua = lua();
+        let url = serve(
+            b"HTTP/1.1 200 OK\r\nServer: test\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\
+              Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+        );
+        let (response, error) = get(&lua, &url);
+        assert_eq!(error, None);
+        let Value::Table(response) = response else {
+            panic!("no response table");
+        };
+        assert_eq!(response.get::<u16>("status").unwrap(), 200);
+        assert_eq!(response.get::<String>("reason").unwrap(), "OK");
+        assert_eq!(response.get::<String>("url").unwrap(), url);
+        assert_eq!(response.get::<String>("body").unwrap(), "hello world");
+        let headers: Table = response.get("headers").unwrap();
+        assert_eq!(headers.get::<String>("server").unwrap(), "test");
+        assert_eq!(headers.get::<String>("set-cookie").unwrap(), "a=1, b=2");
+    }
+
+    #[test]
+    fn malformed_responses_return_nil_and_an_error() {
+        let lua = lua();
+        for response in [
+            &b"SSH-2.0-OpenSSH_9.6\r\n\r\n"[..],
+            b"HTTP/1.1 abc OK\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+            b"",
+        ] {
+            let (value, error) = get(&lua, &serve(response));
+            assert!(value.is_nil(), "{:?}", String::from_utf8_lossy(response));
+            assert!(error.is_some(), "{:?}", String::from_utf8_lossy(response));
+        }
+    }
+
+    #[test]
+    fn invalid_urls_return_nil_and_an_error() {
+        let lua = lua();
+        for url in [
+            "ftp://example.com/",
+            "example.com",
+            "http://:80/",
+            "http://[::1/",
+        ] {
+            let (value, error) = get(&lua, url);
+            assert!(value.is_nil(), "{}", url);
+            assert!(error.is_some(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn content_length_bounds_the_body() {
+        let lua = lua();
+        let url = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabcdef");
+        let (response, _) = get(&lua, &url);
+        let Value::Table(response) = response else {
+            panic!("no response table");
+        };
+        assert_eq!(response.get::<String>("body").unwrap(), "abc");
+    }
+}
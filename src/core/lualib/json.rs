@@ -0,0 +1,206 @@
+use mlua::{Lua, Result as LuaResult, Table, Value};
+use serde_json::{Map, Value as JsonValue};
+
+/// Maximum nesting depth converted from a Lua table, guards against cycles
+const MAX_DEPTH: usize = 32;
+
+pub fn create(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    module.set(
+        "encode",
+        lua.create_function(|_, (value, pretty): (Value, Option<bool>)| {
+            let json = lua_to_json(&value);
+            let encoded = if pretty.unwrap_or(false) {
+                serde_json::to_string_pretty(&json)
+            } else {
+                serde_json::to_string(&json)
+            };
+            encoded.map_err(mlua::Error::external)
+        })?,
+    )?;
+
+    module.set(
+        "decode",
+        lua.create_function(|lua, text: mlua::String| {
+            match serde_json::from_slice::<JsonValue>(&text.as_bytes()) {
+                Ok(json) => Ok((json_to_lua(lua, &json)?, None)),
+                Err(e) => Ok((Value::Nil, Some(e.to_string()))),
+            }
+        })?,
+    )?;
+
+    Ok(module)
+}
+
+/// Convert a Lua value into a JSON value
+///
+/// Tables whose keys are exactly `1..n` become arrays, every other table becomes an
+/// object with stringified keys. Functions, userdata and threads are dropped.
+pub fn lua_to_json(value: &Value) -> JsonValue {
+    lua_to_json_depth(value, 0)
+}
+
+fn lua_to_json_depth(value: &Value, depth: usize) -> JsonValue {
+    match value {
+        Value::Boolean(b) => JsonValue::Bool(*b),
+        Value::Integer(i) => JsonValue::from(*i),
+        Value::Number(n) => serde_json::Number::from_f64(*n)
+            .map(JsonValue::Number)
+            .unwrap_or(JsonValue::Null),
+        Value::String(s) => JsonValue::String(s.to_string_lossy()),
+        Value::Table(table) if depth < MAX_DEPTH => table_to_json(table, depth),
+        _ => JsonValue::Null,
+    }
+}
+
+fn table_to_json(table: &Table, depth: usize) -> JsonValue {
+    let entries: Vec<(Value, Value)> = table.pairs::<Value, Value>().flatten().collect();
+    let len = table.raw_len();
+
+    let is_sequence = len > 0
+        && entries.len() == len
+        && entries
+            .iter()
+            .all(|(key, _)| matches!(key, Value::Integer(i) if *i >= 1 && *i as usize <= len));
+
+    if is_sequence {
+        let items: Vec<Value> = (1..=len).filter_map(|i| table.raw_get(i).ok()).collect();
+        return JsonValue::Array(
+            items
+                .iter()
+                .map(|item| lua_to_json_depth(item, depth + 1))
+                .collect(),
+        );
+    }
+
+    let mut map = Map::new();
+    for (key, value) in &entries {
+        if matches!(
+            value,
+            Value::Function(_) | Value::UserData(_) | Value::LightUserData(_) | Value::Thread(_)
+        ) {
+            continue;
+        }
+        let key = match key {
+            Value::String(s) => s.to_string_lossy(),
+            Value::Integer(i) => i.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            _ => continue,
+        };
+        map.insert(key, lua_to_json_depth(value, depth + 1));
+    }
+
+    JsonValue::Object(map)
+}
+
+/// Convert a JSON value into a Lua value, `null` becomes `nil`
+pub fn json_to_lua(lua: &Lua, value: &JsonValue) -> LuaResult<Value> {
+    Ok(match value {
+        JsonValue::Null => Value::Nil,
+        JsonValue::Bool(b) => Value::Boolean(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Number(n.as_f64().unwrap_or_default()),
+        },
+        JsonValue::String(s) => Value::String(lua.create_string(s)?),
+        JsonValue::Array(items) => {
+            let table = lua.create_table()?;
+            for (i, item) in items.iter().enumerate() {
+                table.raw_set(i + 1, json_to_lua(lua, item)?)?;
+            }
+            Value::Table(table)
+        }
+        JsonValue::Object(map) => {
+            let table = lua.create_table()?;
+            for (key, item) in map {
+                table.raw_set(key.as_str(), json_to_lua(lua, item)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        lua.globals().set("json", create(&lua).unwrap()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn encode_and_decode_round_trip() {
+        let lua = lua();
+        let (text, name, second, nested): (String, String, i64, bool) = lua
+            .load(
+                r#"
+                local text = json.encode({ name = "rmap", ports = { 22, 80 }, tls = { enabled = true } })
+                local value = json.decode(text)
+                return text, value.name, value.ports[2], value.tls.enabled
+                "#,
+            )
+            .eval()
+            .unwrap();
+        let json: JsonValue = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["ports"], serde_json::json!([22, 80]));
+        assert_eq!((name.as_str(), second, nested), ("rmap", 80, true));
+    }
+
+    #[test]
+    fn malformed_json_returns_nil_and_an_error() {
+        let lua = lua();
+        for text in ["{", "{\"a\": }", "[1, 2", "nul", "\"unterminated", ""] {
+            let (value, error): (Value, Option<String>) = lua
+                .load(format!("return json.decode({:?})", text))
+                .eval()
+                .unwrap();
+            assert!(value.is_nil(), "{}", text);
+            assert!(error.is_some(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn tables_become_arrays_only_for_sequences() {
+        let lua = lua();
+        let convert = |code: &str| lua_to_json(&lua.load(code).eval::<Value>().unwrap());
+        assert_eq!(convert("return {1, 2, 3}"), serde_json::json!([1, 2, 3]));
+        assert_eq!(
+            convert("return {[1] = 'a', [3] = 'c'}"),
+            serde_json::json!({"1": "a", "3": "c"})
+        );
+        assert_eq!(convert("return {}"), serde_json::json!({}));
+        assert_eq!(
+            convert("return {f = function() end, n = 1}"),
+            serde_json::json!({"n": 1})
+        );
+    }
+
+    #[test]
+    fn cycles_stop_at_the_depth_limit() {
+        let lua = lua();
+        let value: Value = lua.load("local t = {} t.t = t return t").eval().unwrap();
+        let mut json = &lua_to_json(&value);
+        let mut depth = 0;
+        while let Some(inner) = json.get("t") {
+            json = inner;
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_DEPTH);
+        assert!(json.is_null());
+    }
+
+    #[test]
+    fn null_decodes_to_nil() {
+        let lua = lua();
+        let (a, b): (Value, i64) = lua
+            .load(r#"local v = json.decode('{"a": null, "b": 2}') return v.a, v.b"#)
+            .eval()
+            .unwrap();
+        assert!(a.is_nil());
+        assert_eq!(b, 2);
+    }
+}
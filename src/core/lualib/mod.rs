@@ -0,0 +1,97 @@
+//! Standard library of Lua modules implemented in Rust
+//!
+//! Every module is registered both as a global and in `package.loaded`, so scripts can use
+//! either `http.get(...)` or `local http = require("http")`.
+//!
+//! Functions that talk to the network return `nil, error` on failure instead of raising,
+//! following the usual Lua convention.
+
+use crate::core::timing::HostTiming;
+use mlua::{Lua, Result as LuaResult, Table};
+use std::future::Future;
+use std::sync::Arc;
+
+mod bin;
+mod dns;
+mod encoding;
+mod hash;
+mod http;
+pub mod json;
+mod tls;
+
+/// Default timeout for network helpers when the script does not pass one
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Register all bundled modules into a Lua state
+pub fn register(lua: &Lua) -> LuaResult<()> {
+    install(lua, "http", http::create(lua)?)?;
+    install(lua, "tls", tls::create(lua)?)?;
+    install(lua, "dns", dns::create(lua)?)?;
+    install(lua, "json", json::create(lua)?)?;
+    install(lua, "base64", encoding::create_base64(lua)?)?;
+    install(lua, "hex", encoding::create_hex(lua)?)?;
+    install(lua, "hash", hash::create(lua)?)?;
+    install(lua, "bin", bin::create(lua)?)?;
+    Ok(())
+}
+
+fn install(lua: &Lua, name: &str, module: Table) -> LuaResult<()> {
+    let loaded: Table = lua.globals().get::<Table>("package")?.get("loaded")?;
+    loaded.set(name, module.clone())?;
+    lua.globals().set(name, module)
+}
+
+/// Run blocking network I/O from synchronous Lua code
+///
+/// Scripts execute on the scanner's Tokio workers, so on a multi-threaded runtime the worker
+/// hands its other tasks off for the duration of the call instead of stalling them.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Wait for the rate limits of the host the script runs against, if the runner set one with
+/// [`set_timing`]
+fn wait_turn(lua: &Lua) {
+    let Some(timing) = lua
+        .app_data_ref::<Arc<HostTiming>>()
+        .map(|timing| timing.clone())
+    else {
+        return;
+    };
+    let _ = block_on(async move { timing.wait_turn().await });
+}
+
+/// Pace the network helpers of `lua` like the probes of the host the script runs against
+pub fn set_timing(lua: &Lua, timing: Arc<HostTiming>) {
+    lua.set_app_data(timing);
+}
+
+/// Run an async operation to completion from synchronous Lua code
+///
+/// The scanner's runtime cannot be blocked on from inside one of its tasks, so the future
+/// runs on a dedicated thread with its own runtime, through [`blocking`] like all other I/O.
+fn block_on<F>(future: F) -> Result<F::Output, String>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    blocking(|| {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map(|runtime| runtime.block_on(future))
+                        .map_err(|e| e.to_string())
+                })
+                .join()
+                .map_err(|_| "async helper thread panicked".to_string())?
+        })
+    })
+}
@@ -0,0 +1,48 @@
+use super::{DEFAULT_TIMEOUT_MS, blocking, wait_turn};
+use crate::net::tls;
+use mlua::{Lua, Result as LuaResult, Table, Value};
+use std::time::Duration;
+
+pub fn create(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+
+    // tls.certificate(host, port [, timeout]) -> cert | nil, err
+    module.set(
+        "certificate",
+        lua.create_function(|lua, (host, port, timeout): (String, u16, Option<u64>)| {
+            let timeout = Duration::from_millis(timeout.unwrap_or(DEFAULT_TIMEOUT_MS));
+            wait_turn(lua);
+            let info = match blocking(|| tls::certificate_info(&host, port, timeout)) {
+                Ok(info) => info,
+                Err(e) => return Ok((Value::Nil, Some(e.to_string()))),
+            };
+
+            let cert = lua.create_table()?;
+            cert.set("subject", info.subject)?;
+            cert.set("issuer", info.issuer)?;
+            cert.set("serial", info.serial)?;
+            cert.set("not_before", info.not_before)?;
+            cert.set("not_after", info.not_after)?;
+            cert.set("san", lua.create_sequence_from(info.subject_alt_names)?)?;
+            cert.set("signature_algorithm", info.signature_algorithm)?;
+            cert.set("sha256", info.sha256_fingerprint)?;
+            cert.set("protocol", info.protocol)?;
+            cert.set("cipher", info.cipher_suite)?;
+
+            Ok((Value::Table(cert), None))
+        })?,
+    )?;
+
+    // tls.versions(host, port [, timeout]) -> { "TLSv1.2", "TLSv1.3" }
+    module.set(
+        "versions",
+        lua.create_function(|lua, (host, port, timeout): (String, u16, Option<u64>)| {
+            let timeout = Duration::from_millis(timeout.unwrap_or(DEFAULT_TIMEOUT_MS));
+            wait_turn(lua);
+            let versions = blocking(|| tls::supported_versions(&host, port, timeout));
+            lua.create_sequence_from(versions)
+        })?,
+    )?;
+
+    Ok(module)
+}
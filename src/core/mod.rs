@@ -1,30 +1,293 @@
 use crate::args::Config;
+use crate::checkpoint::{Checkpoint, CheckpointWriter};
+use crate::dns::{DNSResolver, ReverseDns};
+use crate::events::{Observers, ScanEvent, ScanObserver};
+use crate::output::{NdjsonWriter, OutputHandler, ProgressReporter};
+use crate::report::{HostStatus, Protocol, ScanReport};
 use std::error::Error;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
+mod builder;
+mod cancel;
 mod lua;
+mod lualib;
+mod order;
+pub mod probe;
+mod rate;
+mod scripts;
 mod tcp;
+mod timing;
 mod udp;
-pub mod probe;
+pub mod web;
 
+use probe::Prober;
+use rate::RateLimiter;
+
+pub use builder::ScannerBuilder;
+pub use cancel::{CancellationToken, DRAIN_TIMEOUT};
 pub use lua::{LuaScriptRunner, ScriptResult, new_script_runner};
-pub use tcp::{PortState, PortResult, ServiceInfo};
-pub use udp::UdpPortState;
+pub use scripts::ScriptInfo;
+pub use tcp::{PortReason, PortResult, PortState, ServiceInfo};
+pub use timing::TimingTemplate;
 
 pub struct Scanner {
     pub config: Config,
+    observers: Observers,
+    cancel: CancellationToken,
+    /// Results of an earlier run of this scan, see [`ScannerBuilder::resume`]
+    resume: Option<Arc<Checkpoint>>,
+    /// Caches reverse lookups across the scans of this scanner
+    dns: DNSResolver,
 }
 
 impl Scanner {
-    pub fn new(config: Config) -> Scanner {
-        Scanner { config }
+    /// Create a scanner, randomized scans without a seed get one so the report records it
+    pub fn new(mut config: Config) -> Scanner {
+        if !config.ordered {
+            config.seed.get_or_insert_with(order::random_seed);
+        }
+        Scanner {
+            config,
+            observers: Observers::default(),
+            cancel: CancellationToken::new(),
+            resume: None,
+            dns: DNSResolver::new(),
+        }
     }
 
-    pub async fn exec(&self) -> Result<(), Box<dyn Error>> {
-        if self.config.udp {
-            udp::UDPScanner::new().exec().await?;
-        } else if self.config.tcp {
-            tcp::TCPScanner::new().exec().await?;
+    /// Token that stops this scanner
+    ///
+    /// Once cancelled, no new probes are sent, probes in flight get [`DRAIN_TIMEOUT`] to
+    /// finish, and the results gathered so far are returned in a report marked as partial.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Receive progress and result events while the scan runs
+    pub fn subscribe(&mut self, observer: Arc<dyn ScanObserver>) {
+        self.observers.add(observer);
+    }
+
+    /// Configure a scanner without going through command line arguments
+    pub fn builder() -> ScannerBuilder {
+        ScannerBuilder::new()
+    }
+
+    /// Run every enabled protocol scan and return the report
+    ///
+    /// Nothing is written to the terminal or to report files. Progress and results are only
+    /// passed to subscribed observers and the NDJSON event stream, if one is configured.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the report of every scanned host, or an error if the scan fails
+    pub async fn run(&self) -> Result<ScanReport, Box<dyn Error>> {
+        self.scan(false).await
+    }
+
+    /// Run the scan as the command line tool does
+    ///
+    /// Progress bars are drawn on the terminal, the result tables are printed and every
+    /// requested report file is written. The first Ctrl-C stops the scan and writes the
+    /// results gathered so far, a second one exits immediately.
+    pub async fn exec(&self) -> Result<ScanReport, Box<dyn Error>> {
+        let cancel = self.cancel.clone();
+        let interrupt = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            eprintln!("\nInterrupted, writing partial results (press Ctrl-C again to quit)");
+            cancel.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        });
+
+        let report = self.scan(true).await;
+        interrupt.abort();
+        let report = report?;
+
+        let output_handler = OutputHandler::new();
+        // Tables would corrupt an event stream on stdout
+        if self.config.json.is_none() && !self.config.streams_to_stdout() {
+            output_handler.out_report(&report, self.config.show_all_states());
+        }
+        if let Some(json_file) = &self.config.json {
+            output_handler.out_json(&report, json_file)?;
+        }
+        if let Some(xml_file) = &self.config.xml {
+            output_handler.out_xml(&report, xml_file)?;
+        }
+        if let Some(grepable_file) = &self.config.grepable {
+            output_handler.out_grepable(&report, grepable_file)?;
+        }
+        if let Some(csv_file) = &self.config.csv {
+            output_handler.out_csv(&report, csv_file)?;
+        }
+        if let Some(tsv_file) = &self.config.tsv {
+            output_handler.out_tsv(&report, tsv_file)?;
+        }
+        if let Some(html_file) = &self.config.html {
+            output_handler.out_html(&report, html_file)?;
+        }
+        Ok(report)
+    }
+
+    /// Check settings that would otherwise only fail once the port scan is done
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(lua_script) = &self.config.lua_script {
+            let mut script_runner = LuaScriptRunner::new()?;
+            script_runner.set_scripts_dir(&self.config.scripts_dir);
+            script_runner.select_scripts(lua_script)?;
+        }
+        if self.config.web_enum {
+            web::load_wordlists(&self.config.wordlists)?;
         }
         Ok(())
     }
+
+    /// The prober of `service_version`, with the probes of `service_probes` when given
+    fn prober(&self) -> Result<Option<Arc<Prober>>, Box<dyn Error>> {
+        if !self.config.service_version {
+            return Ok(None);
+        }
+        let mut prober = Prober::new();
+        if let Some(path) = &self.config.service_probes {
+            prober
+                .load_probes(path)
+                .map_err(|e| format!("Error loading service probes {}", e))?;
+        }
+        prober.set_timeout(self.config.timeout);
+        prober.set_concurrency(self.config.threads as usize);
+        prober.set_cancellation_token(self.cancel.clone());
+        Ok(Some(Arc::new(prober)))
+    }
+
+    async fn scan(&self, interactive: bool) -> Result<ScanReport, Box<dyn Error>> {
+        self.validate()?;
+        let prober = self.prober()?;
+        let mut report = ScanReport::new(&self.config);
+        if let Some(resume) = &self.resume {
+            report.metadata.command_line = resume.command_line.clone();
+            report.metadata.start_time = resume.start_time;
+        }
+
+        let mut observers = self.observers.clone();
+        if let Some(path) = &self.config.checkpoint {
+            let mut checkpoint = Checkpoint::new(&self.config, &report.metadata);
+            if let Some(resume) = &self.resume {
+                checkpoint.finished = resume.finished.clone();
+                checkpoint.pending = resume.pending.clone();
+            }
+            observers.add(Arc::new(CheckpointWriter::create(
+                path,
+                Duration::from_secs(self.config.checkpoint_interval),
+                self.cancel.clone(),
+                checkpoint,
+            )?));
+        }
+        if let Some(path) = &self.config.ndjson {
+            observers.add(Arc::new(NdjsonWriter::create(path)?));
+        }
+        if interactive {
+            observers.add(Arc::new(ProgressReporter::new()));
+        }
+
+        let mut protocols = Vec::new();
+        if self.config.tcp {
+            protocols.push(Protocol::Tcp);
+        }
+        if self.config.udp {
+            protocols.push(Protocol::Udp);
+        }
+        observers.emit(ScanEvent::ScanStarted {
+            version: report.metadata.version.clone(),
+            command_line: report.metadata.command_line.clone(),
+            targets: self.config.target.clone(),
+            ports: self.config.ports.clone(),
+            protocols,
+        });
+
+        // One rate limit for every host and protocol
+        let rate = Arc::new(RateLimiter::new(self.config.min_rate, self.config.max_rate));
+
+        if self.config.tcp {
+            let mut scanner = tcp::TCPScanner::new(self.config.clone());
+            scanner.observers = observers.clone();
+            scanner.cancel = self.cancel.clone();
+            scanner.interactive = interactive;
+            scanner.resume = self.resume.clone();
+            scanner.rate = rate.clone();
+            scanner.prober = prober.clone();
+            report.add_hosts(scanner.exec().await?);
+        }
+        if self.config.udp && !self.cancel.is_cancelled() {
+            let mut scanner = udp::UDPScanner::new(self.config.clone());
+            scanner.observers = observers.clone();
+            scanner.cancel = self.cancel.clone();
+            scanner.resume = self.resume.clone();
+            scanner.rate = rate.clone();
+            scanner.prober = prober;
+            report.add_hosts(scanner.exec().await?);
+        }
+        // An interrupted scan reports what it has without waiting for name servers
+        if !self.cancel.is_cancelled() {
+            self.resolve_hostnames(&mut report, &observers).await;
+        }
+        report.metadata.partial = self.cancel.is_cancelled();
+        report.finish();
+
+        observers.emit(ScanEvent::ScanFinished {
+            hosts: report.hosts.len(),
+            hosts_up: report
+                .hosts
+                .iter()
+                .filter(|host| host.status == HostStatus::Up)
+                .count(),
+            elapsed_seconds: report.metadata.elapsed_seconds,
+            partial: report.metadata.partial,
+        });
+
+        Ok(report)
+    }
+
+    /// Add the names reverse DNS finds for the hosts `reverse_dns` selects to the report
+    async fn resolve_hostnames(&self, report: &mut ScanReport, observers: &Observers) {
+        let wanted = |status: &HostStatus| match self.config.reverse_dns {
+            ReverseDns::All => true,
+            ReverseDns::Live => *status == HostStatus::Up,
+            ReverseDns::Never => false,
+        };
+        let addresses: Vec<IpAddr> = report
+            .hosts
+            .iter()
+            .filter(|host| wanted(&host.status))
+            .filter_map(|host| host.address().parse().ok())
+            .collect();
+        if addresses.is_empty() {
+            return;
+        }
+
+        let names = self.dns.reverse_resolve_all(&addresses).await;
+        for host in &mut report.hosts {
+            let Some(found) = host
+                .address()
+                .parse::<IpAddr>()
+                .ok()
+                .and_then(|address| names.get(&address))
+            else {
+                continue;
+            };
+            for name in found {
+                host.add_hostname(name, "PTR");
+                observers.emit(ScanEvent::Hostname {
+                    target: host.target.clone(),
+                    address: host.address().to_string(),
+                    name: name.clone(),
+                });
+            }
+        }
+    }
 }
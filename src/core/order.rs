@@ -0,0 +1,162 @@
+use std::hash::{BuildHasher, RandomState};
+use std::time::SystemTime;
+
+/// Rounds of the Feistel network, four make every output bit depend on every input bit
+const ROUNDS: usize = 4;
+
+/// The order probes are sent in, as (host index, port index) pairs
+///
+/// Ordered scans walk the ports of each host in the order given, one host after another.
+/// Randomized scans visit every pair exactly once in a pseudo-random order decided by the seed,
+/// so ports of a host are not probed sequentially and consecutive probes are spread across
+/// hosts. The order is computed on the fly, nothing proportional to the number of probes is
+/// kept in memory.
+pub struct ProbeOrder {
+    ports: u64,
+    len: u64,
+    next: u64,
+    permutation: Option<Permutation>,
+}
+
+impl ProbeOrder {
+    /// Order for `ports` ports on each of `hosts` hosts, randomized when a seed is given
+    pub fn new(hosts: usize, ports: usize, seed: Option<u64>) -> ProbeOrder {
+        let len = hosts as u64 * ports as u64;
+        ProbeOrder {
+            ports: ports as u64,
+            len,
+            next: 0,
+            permutation: seed.map(|seed| Permutation::new(len, seed)),
+        }
+    }
+}
+
+impl Iterator for ProbeOrder {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.next >= self.len {
+            return None;
+        }
+        let index = match &self.permutation {
+            Some(permutation) => permutation.get(self.next),
+            None => self.next,
+        };
+        self.next += 1;
+        Some(((index / self.ports) as usize, (index % self.ports) as usize))
+    }
+}
+
+/// A seed for randomized scans that are not given one
+pub fn random_seed() -> u64 {
+    RandomState::new().hash_one(SystemTime::now())
+}
+
+/// A bijection of `0..len` onto itself
+///
+/// A Feistel network is a permutation of the `2 * half_bits` bit numbers whatever its round
+/// function. Values it maps outside `0..len` are fed through it again until they land inside,
+/// which keeps the mapping a permutation of `0..len` (cycle walking). The domain is less than
+/// four times `len`, so few steps are needed.
+struct Permutation {
+    len: u64,
+    half_bits: u32,
+    keys: [u64; ROUNDS],
+}
+
+impl Permutation {
+    fn new(len: u64, seed: u64) -> Permutation {
+        let bits = u64::BITS - len.saturating_sub(1).leading_zeros();
+        let mut state = seed;
+        Permutation {
+            len,
+            half_bits: bits.div_ceil(2).max(1),
+            keys: std::array::from_fn(|_| {
+                state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                mix(state)
+            }),
+        }
+    }
+
+    fn get(&self, index: u64) -> u64 {
+        let mut value = self.encrypt(index);
+        while value >= self.len {
+            value = self.encrypt(value);
+        }
+        value
+    }
+
+    fn encrypt(&self, value: u64) -> u64 {
+        let mask = (1u64 << self.half_bits) - 1;
+        let mut left = value >> self.half_bits;
+        let mut right = value & mask;
+        for key in self.keys {
+            (left, right) = (right, left ^ (mix(right ^ key) & mask));
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+/// The SplitMix64 finalizer, spreads every input bit over the whole output
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTHS: [usize; 6] = [0, 1, 2, 7, 1000, 65536];
+    const SEEDS: [u64; 4] = [0, 1, 42, u64::MAX];
+
+    /// Flat indexes of a single-host order
+    fn indexes(len: usize, seed: Option<u64>) -> Vec<usize> {
+        ProbeOrder::new(1, len, seed)
+            .map(|(_, port)| port)
+            .collect()
+    }
+
+    #[test]
+    fn randomized_orders_are_permutations() {
+        for len in LENGTHS {
+            for seed in SEEDS {
+                let mut order = indexes(len, Some(seed));
+                order.sort_unstable();
+                assert!(order.into_iter().eq(0..len), "len {} seed {}", len, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_order() {
+        for len in LENGTHS {
+            for seed in SEEDS {
+                assert_eq!(indexes(len, Some(seed)), indexes(len, Some(seed)));
+            }
+        }
+        assert_ne!(indexes(1000, Some(1)), indexes(1000, Some(2)));
+    }
+
+    #[test]
+    fn unseeded_order_is_the_identity() {
+        for len in LENGTHS {
+            assert!(indexes(len, None).into_iter().eq(0..len), "len {}", len);
+        }
+        let pairs: Vec<_> = ProbeOrder::new(2, 3, None).collect();
+        assert_eq!(pairs, [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn hosts_and_ports_are_each_visited_once() {
+        for seed in SEEDS {
+            let mut pairs: Vec<_> = ProbeOrder::new(7, 13, Some(seed)).collect();
+            pairs.sort_unstable();
+            let expected: Vec<_> = (0..7)
+                .flat_map(|host| (0..13).map(move |port| (host, port)))
+                .collect();
+            assert_eq!(pairs, expected, "seed {}", seed);
+        }
+    }
+}
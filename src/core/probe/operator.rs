@@ -1,17 +1,36 @@
-use regex::Regex;
+use futures::stream::{self, StreamExt};
+use regex::bytes::{Captures, Regex};
+use std::collections::HashMap;
 use std::error::Error;
 use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::{TcpStream, UdpSocket};
 use tokio::time::timeout;
 
-use super::parser::{MatchEntry, NmapProbes, ProbeEntry};
+use super::parser::{MatchEntry, NmapProbes, ProbeEntry, parse_nmap_probes};
+use crate::core::timing::HostTiming;
+use crate::core::{CancellationToken, PortState, ServiceInfo};
+use crate::events::{Observers, ScanEvent};
+use crate::report::{PortReport, Protocol};
+use crate::utils::parse_ports;
 use std::fs;
 
+/// Probes used when no probes file is loaded
+const BUILTIN_PROBES: &str = include_str!("service-probes");
+/// Bytes of an answer that are matched, nmap reads no more either
+const MAX_RESPONSE: usize = 16 * 1024;
+/// How long to wait for more of an answer once its first bytes arrived
+const READ_MORE: Duration = Duration::from_millis(250);
+/// Confidence of services named by a `match` line, from 0 to 10 like nmap's `conf`
+const MATCH_CONFIDENCE: u8 = 10;
+/// Confidence of services named by a `softmatch` line, which names the service but no version
+const SOFT_MATCH_CONFIDENCE: u8 = 8;
+
 /// Service detection result
 #[derive(Debug, Clone)]
-pub struct ServiceInfo {
+pub struct DetectedService {
     pub service: String,
     pub version: Option<String>,
     pub product: Option<String>,
@@ -19,197 +38,336 @@ pub struct ServiceInfo {
     pub hostname: Option<String>,
     pub os_info: Option<String>,
     pub device_type: Option<String>,
-    pub cpe: Option<String>,
+    pub cpe: Vec<String>,
     pub confidence: u8,
 }
 
-/// Port scan result with service detection
-#[derive(Debug, Clone)]
-pub struct ProbeResult {
-    pub host: IpAddr,
-    pub port: u16,
-    pub protocol: String,
-    pub state: String,
-    pub service: Option<ServiceInfo>,
-    pub banner: Option<String>,
+impl From<DetectedService> for ServiceInfo {
+    fn from(detected: DetectedService) -> Self {
+        ServiceInfo {
+            name: detected.service,
+            version: detected.version,
+            product: detected.product,
+            extra_info: detected.extra_info,
+            cpe: detected.cpe,
+            confidence: Some(detected.confidence),
+        }
+    }
 }
 
 /// The prober utility is used to perform banner grabbing and detailed service detection
 /// for a list of ports on a specified host
+///
+/// Probes and their matches follow nmap's `nmap-service-probes`. A TCP port is first read
+/// without sending anything (the `NULL` probe), then sent the probes that list the port, each
+/// on a new connection, until a `match` line names the service. Without such probes
+/// `GetRequest` and `GenericLines` are tried. UDP ports are only sent the probes that list
+/// them. A `softmatch` is kept in case no `match` follows.
 pub struct Prober {
-    probes: Option<NmapProbes>,
+    probes: NmapProbes,
+    /// Compiled patterns, `None` for patterns the regex crate cannot compile
+    regexes: Mutex<HashMap<String, Option<Arc<Regex>>>>,
     timeout_ms: u64,
+    concurrency: usize,
+    cancel: CancellationToken,
+}
+
+impl Default for Prober {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Prober {
+    /// A prober with the built-in probes
     pub fn new() -> Prober {
         Prober {
-            probes: None,
+            probes: parse_nmap_probes(BUILTIN_PROBES),
+            regexes: Mutex::new(HashMap::new()),
             timeout_ms: 5000,
+            concurrency: 10,
+            cancel: CancellationToken::new(),
         }
     }
 
-    /// Load nmap probes from JSON file
+    /// Load probes from a file in the format of nmap's `nmap-service-probes`
     pub fn load_probes(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
-        let content = fs::read_to_string(path)?;
-        self.probes = Some(serde_json::from_str(&content)?);
+        let content = fs::read_to_string(path).map_err(|e| format!("'{}': {}", path, e))?;
+        let probes = parse_nmap_probes(&content);
+        if probes.probes.is_empty() {
+            return Err(format!("'{}' holds no probes", path).into());
+        }
+        self.probes = probes;
+        self.regexes = Mutex::new(HashMap::new());
         Ok(())
     }
 
-    /// Set connection timeout in milliseconds
+    /// Set how long to wait for a connection or an answer in milliseconds
     pub fn set_timeout(&mut self, timeout_ms: u64) {
         self.timeout_ms = timeout_ms;
     }
 
-    /// Perform service detection on a single port
-    pub async fn probe_port(&self, host: IpAddr, port: u16) -> Result<ProbeResult, Box<dyn Error>> {
-        let mut result = ProbeResult {
-            host,
-            port,
-            protocol: "tcp".to_string(),
-            state: "unknown".to_string(),
-            service: None,
-            banner: None,
+    /// Set how many ports of a host are probed at the same time
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    /// Stop probing once `cancel` is cancelled
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) {
+        self.cancel = cancel;
+    }
+
+    /// Detect the services of the open ports among `ports` of a host
+    ///
+    /// UDP ports without an answer to the scan are probed as well, since the scan payload may
+    /// not be one the service answers. Services that are detected replace the ones named from
+    /// the port table and are reported as service events.
+    pub(crate) async fn detect_services(
+        &self,
+        target: &str,
+        address: &str,
+        ports: &mut [PortReport],
+        timing: &HostTiming,
+        observers: &Observers,
+    ) {
+        let Ok(ip_addr) = address.parse::<IpAddr>() else {
+            return;
         };
+        let candidates: Vec<(usize, Protocol, u16)> = ports
+            .iter()
+            .enumerate()
+            .filter(|(_, port)| {
+                port.state == PortState::Open
+                    || (port.protocol == Protocol::Udp && port.state == PortState::OpenFiltered)
+            })
+            .map(|(index, port)| (index, port.protocol, port.port))
+            .collect();
+
+        let detected: Vec<(usize, DetectedService)> = stream::iter(candidates)
+            .take_while(|_| futures::future::ready(!self.cancel.is_cancelled()))
+            .map(|(index, protocol, port)| async move {
+                let detected = match protocol {
+                    Protocol::Tcp => self.probe_port(ip_addr, port, timing).await,
+                    Protocol::Udp => self.probe_udp_port(ip_addr, port, timing).await,
+                };
+                detected.map(|detected| (index, detected))
+            })
+            .buffer_unordered(self.concurrency)
+            .filter_map(futures::future::ready)
+            .collect()
+            .await;
+
+        for (index, detected) in detected {
+            let port = &mut ports[index];
+            let service = ServiceInfo::from(detected);
+            observers.emit(ScanEvent::Service {
+                target: target.to_string(),
+                address: address.to_string(),
+                protocol: port.protocol,
+                port: port.port,
+                service: service.clone(),
+            });
+            port.service = Some(service);
+        }
+    }
+
+    /// Perform service detection on a single TCP port
+    pub async fn probe_port(
+        &self,
+        host: IpAddr,
+        port: u16,
+        timing: &HostTiming,
+    ) -> Option<DetectedService> {
+        let mut soft_match = None;
 
-        // Try to connect to the port
-        let socket_addr = SocketAddr::new(host, port);
-        let mut stream = match timeout(
-            Duration::from_millis(self.timeout_ms),
-            TcpStream::connect(socket_addr),
-        )
-        .await
+        // Services that greet first are matched against the NULL probe
+        if let Some(null_probe) = self.probe_named("TCP", "NULL")
+            && let Some(response) = self.exchange_tcp(host, port, null_probe, timing).await
         {
-            Ok(Ok(stream)) => {
-                result.state = "open".to_string();
-                stream
+            match self.match_probe(&response, null_probe) {
+                Some(detected) if detected.confidence == MATCH_CONFIDENCE => return Some(detected),
+                Some(detected) => soft_match = Some(detected),
+                None => {}
             }
-            Ok(Err(_)) => {
-                result.state = "closed".to_string();
-                return Ok(result);
+        }
+
+        for probe in self.relevant_probes("TCP", port, true) {
+            if self.cancel.is_cancelled() {
+                return None;
             }
-            Err(_) => {
-                result.state = "filtered".to_string();
-                return Ok(result);
+            let Some(response) = self.exchange_tcp(host, port, probe, timing).await else {
+                continue;
+            };
+            match self.match_probe(&response, probe) {
+                Some(detected) if detected.confidence == MATCH_CONFIDENCE => return Some(detected),
+                Some(detected) => {
+                    soft_match.get_or_insert(detected);
+                }
+                None => {}
             }
-        };
-
-        // Perform service detection if probes are loaded
-        if let Some(ref probes) = self.probes {
-            result.service = self.detect_service(&mut stream, port, probes).await;
-        } else {
-            // Fallback to simple banner grabbing
-            result.banner = self.grab_banner(&mut stream).await;
         }
 
-        Ok(result)
+        soft_match
     }
 
-    /// Perform service detection on multiple ports
-    pub async fn probe_ports(
+    /// Perform service detection on a single UDP port
+    pub async fn probe_udp_port(
         &self,
         host: IpAddr,
-        ports: &[u16],
-    ) -> Result<Vec<ProbeResult>, Box<dyn Error>> {
-        let mut results = Vec::new();
-
-        for &port in ports {
-            match self.probe_port(host, port).await {
-                Ok(result) => results.push(result),
-                Err(e) => eprintln!("Error probing {}:{} - {}", host, port, e),
+        port: u16,
+        timing: &HostTiming,
+    ) -> Option<DetectedService> {
+        let mut soft_match = None;
+        for probe in self.relevant_probes("UDP", port, false) {
+            if self.cancel.is_cancelled() {
+                return None;
+            }
+            let Some(response) = self.exchange_udp(host, port, probe, timing).await else {
+                continue;
+            };
+            match self.match_probe(&response, probe) {
+                Some(detected) if detected.confidence == MATCH_CONFIDENCE => return Some(detected),
+                Some(detected) => {
+                    soft_match.get_or_insert(detected);
+                }
+                None => {}
             }
         }
-
-        Ok(results)
+        soft_match
     }
 
-    /// Main execution function
-    pub async fn exec(&self) -> Result<(), Box<dyn Error>> {
-        println!("Service detection probe ready");
-        if self.probes.is_some() {
-            println!("Nmap probes loaded successfully");
-        } else {
-            println!("No probes loaded - using basic banner grabbing");
+    /// Send `probe` on a new connection and read the answer
+    ///
+    /// `None` if the connection fails, nothing is answered or the scan is cancelled.
+    async fn exchange_tcp(
+        &self,
+        host: IpAddr,
+        port: u16,
+        probe: &ProbeEntry,
+        timing: &HostTiming,
+    ) -> Option<Vec<u8>> {
+        let exchange = async {
+            timing.wait_turn().await;
+            let connect = TcpStream::connect(SocketAddr::new(host, port));
+            let mut stream = timeout(self.timeout(), connect).await.ok()?.ok()?;
+            if !probe.no_payload && !probe.probe_string.is_empty() {
+                let payload = decode_probe_string(&probe.probe_string);
+                timeout(self.timeout(), stream.write_all(&payload))
+                    .await
+                    .ok()?
+                    .ok()?;
+            }
+            self.read_response(&mut stream, self.wait(probe)).await
+        };
+        tokio::select! {
+            response = exchange => response,
+            _ = self.cancel.drained() => None,
         }
-        Ok(())
     }
 
-    /// Detect service using nmap probes
-    async fn detect_service(
+    /// Send `probe` in a datagram and read the answer
+    async fn exchange_udp(
         &self,
-        stream: &mut TcpStream,
+        host: IpAddr,
         port: u16,
-        probes: &NmapProbes,
-    ) -> Option<ServiceInfo> {
-        // First try NULL probe (banner grabbing)
-        if let Some(null_probe) = probes
-            .probes
-            .iter()
-            .find(|p| p.name == "NULL" && p.protocol == "TCP")
-        {
-            if let Some(service_info) = self.try_probe(stream, null_probe, port).await {
-                return Some(service_info);
+        probe: &ProbeEntry,
+        timing: &HostTiming,
+    ) -> Option<Vec<u8>> {
+        let exchange = async {
+            timing.wait_turn().await;
+            let local: SocketAddr = match host {
+                IpAddr::V4(_) => "0.0.0.0:0",
+                IpAddr::V6(_) => "[::]:0",
             }
+            .parse()
+            .ok()?;
+            let socket = UdpSocket::bind(local).await.ok()?;
+            socket.connect(SocketAddr::new(host, port)).await.ok()?;
+            socket
+                .send(&decode_probe_string(&probe.probe_string))
+                .await
+                .ok()?;
+            let mut buffer = vec![0; MAX_RESPONSE];
+            let received = timeout(self.wait(probe), socket.recv(&mut buffer))
+                .await
+                .ok()?
+                .ok()?;
+            buffer.truncate(received);
+            (!buffer.is_empty()).then_some(buffer)
+        };
+        tokio::select! {
+            response = exchange => response,
+            _ = self.cancel.drained() => None,
         }
+    }
 
-        // Try other probes based on port
-        let relevant_probes = self.get_relevant_probes(probes, port);
-        for probe in relevant_probes {
-            if let Some(service_info) = self.try_probe(stream, probe, port).await {
-                return Some(service_info);
+    /// Read an answer, waiting up to `wait` for its first bytes and briefly for more
+    async fn read_response(&self, stream: &mut TcpStream, wait: Duration) -> Option<Vec<u8>> {
+        let mut response = Vec::new();
+        let mut buffer = [0; 4096];
+        let mut wait = wait;
+        while response.len() < MAX_RESPONSE {
+            match timeout(wait, stream.read(&mut buffer)).await {
+                Ok(Ok(n)) if n > 0 => response.extend_from_slice(&buffer[..n]),
+                _ => break,
             }
+            wait = READ_MORE;
         }
+        response.truncate(MAX_RESPONSE);
+        (!response.is_empty()).then_some(response)
+    }
 
-        None
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
     }
 
-    /// Try a specific probe against the service
-    async fn try_probe(
-        &self,
-        stream: &mut TcpStream,
-        probe: &ProbeEntry,
-        _port: u16,
-    ) -> Option<ServiceInfo> {
-        // Send probe string if not empty
-        if !probe.probe_string.is_empty() {
-            let probe_data = self.decode_probe_string(&probe.probe_string);
-            if let Err(_) = timeout(
-                Duration::from_millis(self.timeout_ms),
-                stream.write_all(&probe_data),
-            )
-            .await
-            {
-                return None;
-            }
-        }
+    /// How long to wait for an answer to `probe`
+    fn wait(&self, probe: &ProbeEntry) -> Duration {
+        probe
+            .total_wait_ms
+            .map_or(self.timeout(), |ms| Duration::from_millis(ms.into()))
+    }
 
-        // Read response
-        let mut buffer = vec![0; 4096];
-        let response = match timeout(
-            Duration::from_millis(self.timeout_ms),
-            stream.read(&mut buffer),
-        )
-        .await
-        {
-            Ok(Ok(n)) if n > 0 => {
-                buffer.truncate(n);
-                String::from_utf8_lossy(&buffer).to_string()
+    fn probe_named(&self, protocol: &str, name: &str) -> Option<&ProbeEntry> {
+        self.probes
+            .probes
+            .iter()
+            .find(|p| p.protocol == protocol && p.name == name)
+    }
+
+    /// Match `response` against the matches of `probe` and of its fallbacks
+    ///
+    /// Like nmap, TCP probes without fallbacks fall back to the NULL probe, whose matches
+    /// cover services that greet first and answer any probe the same way.
+    fn match_probe(&self, response: &[u8], probe: &ProbeEntry) -> Option<DetectedService> {
+        let mut candidates = vec![probe];
+        match &probe.fallback {
+            Some(fallback) => candidates.extend(
+                fallback
+                    .split(',')
+                    .filter_map(|name| self.probe_named(&probe.protocol, name.trim())),
+            ),
+            None if probe.protocol == "TCP" && probe.name != "NULL" => {
+                candidates.extend(self.probe_named("TCP", "NULL"))
             }
-            _ => return None,
-        };
+            None => {}
+        }
 
-        // Try to match response against probe patterns
-        for match_entry in &probe.matches {
-            if let Some(service_info) = self.match_response(&response, match_entry) {
-                return Some(service_info);
+        for candidate in &candidates {
+            for match_entry in &candidate.matches {
+                if let Some(detected) = self.match_response(response, match_entry) {
+                    return Some(detected);
+                }
             }
         }
 
         // Try soft matches
-        for match_entry in &probe.soft_matches {
-            if let Some(mut service_info) = self.match_response(&response, match_entry) {
-                service_info.confidence = 50; // Lower confidence for soft matches
-                return Some(service_info);
+        for candidate in &candidates {
+            for match_entry in &candidate.soft_matches {
+                if let Some(mut detected) = self.match_response(response, match_entry) {
+                    detected.confidence = SOFT_MATCH_CONFIDENCE;
+                    return Some(detected);
+                }
             }
         }
 
@@ -217,156 +375,315 @@ impl Prober {
     }
 
     /// Match response against a pattern
-    fn match_response(&self, response: &str, match_entry: &MatchEntry) -> Option<ServiceInfo> {
-        // Create regex from pattern (simplified - real implementation would handle all nmap regex features)
-        let pattern = &match_entry.pattern;
-        if let Ok(regex) = Regex::new(pattern) {
-            if let Some(captures) = regex.captures(response) {
-                let mut service_info = ServiceInfo {
-                    service: match_entry.service.clone(),
-                    version: None,
-                    product: None,
-                    extra_info: None,
-                    hostname: None,
-                    os_info: None,
-                    device_type: None,
-                    cpe: None,
-                    confidence: 90,
-                };
-
-                // Extract version information
-                for (key, value) in &match_entry.version_info {
-                    let processed_value = self.process_version_field(value, &captures);
-                    match key.as_str() {
-                        "p" => service_info.product = Some(processed_value),
-                        "v" => service_info.version = Some(processed_value),
-                        "i" => service_info.extra_info = Some(processed_value),
-                        "h" => service_info.hostname = Some(processed_value),
-                        "o" => service_info.os_info = Some(processed_value),
-                        "d" => service_info.device_type = Some(processed_value),
-                        "cpe" => service_info.cpe = Some(processed_value),
-                        _ => {}
-                    }
-                }
+    fn match_response(&self, response: &[u8], match_entry: &MatchEntry) -> Option<DetectedService> {
+        let regex = self.regex(&match_entry.pattern)?;
+        let captures = regex.captures(response)?;
+        let mut detected = DetectedService {
+            service: match_entry.service.clone(),
+            version: None,
+            product: None,
+            extra_info: None,
+            hostname: None,
+            os_info: None,
+            device_type: None,
+            cpe: Vec::new(),
+            confidence: MATCH_CONFIDENCE,
+        };
 
-                return Some(service_info);
+        // Extract version information
+        for (key, value) in &match_entry.version_info {
+            let processed_value = process_version_field(value, &captures);
+            if processed_value.is_empty() {
+                continue;
             }
-        }
-        None
-    }
-
-    /// Process version field with capture group substitution
-    fn process_version_field(&self, value: &str, captures: &regex::Captures) -> String {
-        let mut result = value.to_string();
-
-        // Replace $1, $2, etc. with capture groups
-        for i in 1..captures.len() {
-            if let Some(capture) = captures.get(i) {
-                result = result.replace(&format!("${}", i), capture.as_str());
+            match key.as_str() {
+                "p" => detected.product = Some(processed_value),
+                "v" => detected.version = Some(processed_value),
+                "i" => detected.extra_info = Some(processed_value),
+                "h" => detected.hostname = Some(processed_value),
+                "o" => detected.os_info = Some(processed_value),
+                "d" => detected.device_type = Some(processed_value),
+                _ => {}
             }
         }
+        detected.cpe = match_entry
+            .cpe
+            .iter()
+            .map(|cpe| format!("cpe:/{}", process_version_field(cpe, &captures)))
+            .collect();
 
-        result
+        Some(detected)
     }
 
-    /// Get probes relevant to a specific port
-    fn get_relevant_probes<'a>(&self, probes: &'a NmapProbes, port: u16) -> Vec<&'a ProbeEntry> {
-        let mut relevant = Vec::new();
-
-        for probe in &probes.probes {
-            if probe.protocol != "TCP" {
-                continue;
-            }
+    /// The compiled `pattern`, compiled on first use since probes files hold thousands
+    fn regex(&self, pattern: &str) -> Option<Arc<Regex>> {
+        let mut regexes = match self.regexes.lock() {
+            Ok(regexes) => regexes,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        regexes
+            .entry(pattern.to_string())
+            .or_insert_with(|| {
+                // Patterns match bytes, not UTF-8 text, as in nmap
+                Regex::new(&format!("(?-u){}", translate_pattern(pattern)))
+                    .ok()
+                    .map(Arc::new)
+            })
+            .clone()
+    }
 
-            // Check if port is in the probe's port list
-            let port_str = port.to_string();
-            for port_spec in &probe.ports {
-                if port_spec.contains(&port_str) || port_spec.contains(&format!("T:{}", port)) {
-                    relevant.push(probe);
-                    break;
-                }
-            }
-        }
+    /// Probes of `protocol` whose ports include `port`
+    ///
+    /// With `fallback`, `GetRequest` and `GenericLines` are tried when no probe lists the port.
+    fn relevant_probes(&self, protocol: &str, port: u16, fallback: bool) -> Vec<&ProbeEntry> {
+        let mut relevant: Vec<&ProbeEntry> = self
+            .probes
+            .probes
+            .iter()
+            .filter(|probe| probe.protocol == protocol && probe.name != "NULL")
+            .filter(|probe| {
+                probe
+                    .ports
+                    .iter()
+                    .any(|ports| parse_ports(ports).contains(&port))
+            })
+            .collect();
 
         // If no specific probes found, add some common ones
-        if relevant.is_empty() {
-            for probe in &probes.probes {
-                if probe.protocol == "TCP"
+        if relevant.is_empty() && fallback {
+            relevant.extend(self.probes.probes.iter().filter(|probe| {
+                probe.protocol == protocol
                     && matches!(probe.name.as_str(), "GetRequest" | "GenericLines")
-                {
-                    relevant.push(probe);
-                }
-            }
+            }));
         }
 
         relevant
     }
+}
 
-    /// Decode probe string (handle escape sequences)
-    fn decode_probe_string(&self, probe_string: &str) -> Vec<u8> {
-        let mut result = Vec::new();
-        let mut chars = probe_string.chars().peekable();
+/// Replace `$1`, `$2` and so on with the groups captured by the pattern
+fn process_version_field(value: &str, captures: &Captures) -> String {
+    let mut result = value.to_string();
+
+    // Higher groups first, so `$1` does not replace the start of `$10`
+    for i in (1..captures.len()).rev() {
+        let capture = captures
+            .get(i)
+            .map(|capture| String::from_utf8_lossy(capture.as_bytes()).to_string())
+            .unwrap_or_default();
+        result = result.replace(&format!("${}", i), &capture);
+    }
 
-        while let Some(ch) = chars.next() {
-            if ch == '\\' {
-                if let Some(&next_ch) = chars.peek() {
-                    match next_ch {
-                        'n' => {
-                            result.push(b'\n');
-                            chars.next();
-                        }
-                        'r' => {
-                            result.push(b'\r');
-                            chars.next();
-                        }
-                        't' => {
-                            result.push(b'\t');
-                            chars.next();
-                        }
-                        '0' => {
-                            result.push(0);
-                            chars.next();
-                        }
-                        '\\' => {
-                            result.push(b'\\');
-                            chars.next();
-                        }
-                        'x' => {
-                            chars.next(); // consume 'x'
-                            let hex1 = chars.next().unwrap_or('0');
-                            let hex2 = chars.next().unwrap_or('0');
-                            if let Ok(byte) = u8::from_str_radix(&format!("{}{}", hex1, hex2), 16) {
-                                result.push(byte);
-                            }
+    result.trim().to_string()
+}
+
+/// Rewrite the Perl escapes of nmap patterns the regex crate lacks, `\0` for a NUL byte
+fn translate_pattern(pattern: &str) -> String {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            translated.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => translated.push_str(r"\x00"),
+            Some(next) => {
+                translated.push('\\');
+                translated.push(next);
+            }
+            None => translated.push('\\'),
+        }
+    }
+    translated
+}
+
+/// Decode probe string (handle escape sequences)
+fn decode_probe_string(probe_string: &str) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut chars = probe_string.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            if let Some(&next_ch) = chars.peek() {
+                match next_ch {
+                    'n' => {
+                        result.push(b'\n');
+                        chars.next();
+                    }
+                    'r' => {
+                        result.push(b'\r');
+                        chars.next();
+                    }
+                    't' => {
+                        result.push(b'\t');
+                        chars.next();
+                    }
+                    '0' => {
+                        result.push(0);
+                        chars.next();
+                    }
+                    '\\' => {
+                        result.push(b'\\');
+                        chars.next();
+                    }
+                    'x' => {
+                        chars.next(); // consume 'x'
+                        let hex1 = chars.next().unwrap_or('0');
+                        let hex2 = chars.next().unwrap_or('0');
+                        if let Ok(byte) = u8::from_str_radix(&format!("{}{}", hex1, hex2), 16) {
+                            result.push(byte);
                         }
-                        _ => result.push(ch as u8),
                     }
-                } else {
-                    result.push(ch as u8);
+                    _ => result.push(ch as u8),
                 }
             } else {
                 result.push(ch as u8);
             }
+        } else {
+            let mut encoded = [0; 4];
+            result.extend_from_slice(ch.encode_utf8(&mut encoded).as_bytes());
         }
+    }
+
+    result
+}
 
-        result
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Config;
+    use crate::core::rate::RateLimiter;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve one connection after another on a local port, answering with `answer`
+    fn serve(answer: impl Fn(&[u8]) -> Vec<u8> + Send + 'static) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                stream
+                    .set_read_timeout(Some(Duration::from_millis(100)))
+                    .unwrap();
+                let mut buffer = [0; 1024];
+                let received = stream.read(&mut buffer).unwrap_or(0);
+                let _ = stream.write_all(&answer(&buffer[..received]));
+            }
+        });
+        port
     }
 
-    /// Simple banner grabbing fallback
-    async fn grab_banner(&self, stream: &mut TcpStream) -> Option<String> {
-        let mut buffer = vec![0; 1024];
+    fn prober() -> Prober {
+        let mut prober = Prober::new();
+        prober.set_timeout(500);
+        prober
+    }
 
-        match timeout(
-            Duration::from_millis(self.timeout_ms),
-            stream.read(&mut buffer),
-        )
-        .await
-        {
-            Ok(Ok(n)) if n > 0 => {
-                buffer.truncate(n);
-                Some(String::from_utf8_lossy(&buffer).trim().to_string())
+    fn timing() -> HostTiming {
+        HostTiming::new(&Config::default(), Arc::new(RateLimiter::default()))
+    }
+
+    #[test]
+    fn builtin_patterns_compile() {
+        let prober = Prober::new();
+        for probe in &prober.probes.probes {
+            for entry in probe.matches.iter().chain(&probe.soft_matches) {
+                assert!(prober.regex(&entry.pattern).is_some(), "{}", entry.pattern);
             }
-            _ => None,
         }
     }
+
+    #[test]
+    fn matches_fill_in_groups_and_cpe() {
+        let prober = Prober::new();
+        let null = prober.probe_named("TCP", "NULL").unwrap();
+        let detected = prober
+            .match_probe(b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n", null)
+            .unwrap();
+        assert_eq!(detected.service, "ssh");
+        assert_eq!(detected.product.as_deref(), Some("OpenSSH"));
+        assert_eq!(detected.version.as_deref(), Some("9.6p1"));
+        assert_eq!(
+            detected.extra_info.as_deref(),
+            Some("Ubuntu 3ubuntu13; protocol 2.0")
+        );
+        assert_eq!(detected.cpe[0], "cpe:/a:openbsd:openssh:9.6p1");
+        assert_eq!(detected.confidence, MATCH_CONFIDENCE);
+
+        let soft = prober.match_probe(b"SSH-1.99-Unknown\r\n", null).unwrap();
+        assert_eq!(soft.service, "ssh");
+        assert_eq!(soft.product, None);
+        assert_eq!(soft.confidence, SOFT_MATCH_CONFIDENCE);
+
+        assert!(prober.match_probe(b"hello", null).is_none());
+    }
+
+    #[test]
+    fn patterns_match_bytes() {
+        assert_eq!(translate_pattern(r"^.\0\0\\0"), r"^.\x00\x00\\0");
+        let prober = Prober::new();
+        let null = prober.probe_named("TCP", "NULL").unwrap();
+        let detected = prober
+            .match_probe(b"J\0\0\0\n8.0.36\0\xff\xfe", null)
+            .unwrap();
+        assert_eq!(detected.product.as_deref(), Some("MySQL"));
+        assert_eq!(detected.version.as_deref(), Some("8.0.36"));
+    }
+
+    #[test]
+    fn probe_strings_are_decoded() {
+        assert_eq!(
+            decode_probe_string(r"GET / HTTP/1.0\r\n\r\n"),
+            b"GET / HTTP/1.0\r\n\r\n"
+        );
+        assert_eq!(decode_probe_string(r"\0\x06\x01\\"), b"\0\x06\x01\\");
+    }
+
+    #[test]
+    fn fallback_probes_are_listed_ports_or_common_ones() {
+        let prober = Prober::new();
+        let names = |port| -> Vec<&str> {
+            prober
+                .relevant_probes("TCP", port, true)
+                .iter()
+                .map(|probe| probe.name.as_str())
+                .collect()
+        };
+        assert_eq!(names(8080), ["GetRequest"]);
+        assert_eq!(names(6379), ["GenericLines"]);
+        assert_eq!(names(4444), ["GetRequest", "GenericLines"]);
+        assert!(prober.relevant_probes("UDP", 4444, false).is_empty());
+    }
+
+    #[tokio::test]
+    async fn banners_are_matched_without_sending() {
+        let port = serve(|_| b"220 (vsFTPd 3.0.5)\r\n".to_vec());
+        let detected = prober()
+            .probe_port("127.0.0.1".parse().unwrap(), port, &timing())
+            .await
+            .unwrap();
+        assert_eq!(detected.product.as_deref(), Some("vsftpd"));
+        assert_eq!(detected.version.as_deref(), Some("3.0.5"));
+    }
+
+    #[tokio::test]
+    async fn silent_services_get_probes() {
+        let port = serve(|received| {
+            if received.starts_with(b"GET / ") {
+                b"HTTP/1.0 200 OK\r\nServer: nginx/1.24.0\r\n\r\n".to_vec()
+            } else {
+                Vec::new()
+            }
+        });
+        let detected = prober()
+            .probe_port("127.0.0.1".parse().unwrap(), port, &timing())
+            .await
+            .unwrap();
+        assert_eq!(detected.service, "http");
+        assert_eq!(detected.product.as_deref(), Some("nginx"));
+        assert_eq!(detected.version.as_deref(), Some("1.24.0"));
+        assert_eq!(detected.cpe, ["cpe:/a:igor_sysoev:nginx:1.24.0"]);
+    }
 }
@@ -1,7 +1,5 @@
-use std::fs;
-use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NmapProbes {
@@ -28,58 +26,54 @@ pub struct ProbeEntry {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MatchEntry {
     pub service: String,
+    /// Regular expression, with the `s` and `i` flags of the match line as an inline group
     pub pattern: String,
+    /// Product (`p`), version (`v`), extra info (`i`), hostname (`h`), OS (`o`) and device
+    /// type (`d`) templates, which may refer to groups of the pattern as `$1`
     pub version_info: HashMap<String, String>,
+    /// CPE name templates, without the `cpe:/` prefix
+    #[serde(default)]
+    pub cpe: Vec<String>,
 }
 
-pub fn parse_nmap_probes_json(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let content = fs::read_to_string(path)?;
+/// Parse probes in the format of nmap's `nmap-service-probes`
+///
+/// Lines that cannot be parsed are skipped, so a probes file written for a newer nmap still
+/// loads.
+pub fn parse_nmap_probes(content: &str) -> NmapProbes {
     let mut nmap_probes = NmapProbes {
         excludes: Vec::new(),
         probes: Vec::new(),
     };
-    
-    let lines: Vec<&str> = content.lines().collect();
-    let mut i = 0;
     let mut current_probe: Option<ProbeEntry> = None;
-    
-    while i < lines.len() {
-        let line = lines[i].trim();
-        i += 1;
-        
+
+    for line in content.lines() {
+        let line = line.trim();
+
         // Skip comments and empty lines
         if line.starts_with('#') || line.is_empty() {
             continue;
         }
-        
+
         let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
-            continue;
-        }
-        
         match parts[0] {
-            "Exclude" => {
-                if parts.len() > 1 {
-                    nmap_probes.excludes.push(parts[1..].join(" "));
-                }
-            },
+            "Exclude" if parts.len() > 1 => {
+                nmap_probes.excludes.push(parts[1..].join(" "));
+            }
             "Probe" => {
                 // Save previous probe if exists
                 if let Some(probe) = current_probe.take() {
                     nmap_probes.probes.push(probe);
                 }
-                
-                if parts.len() >= 4 {
-                    let protocol = parts[1].to_string();
-                    let name = parts[2].to_string();
-                    let probe_string = parse_probe_string(&parts[3..].join(" "));
-                    let no_payload = parts.contains(&"no-payload");
-                    
+
+                if parts.len() >= 4
+                    && let Some(probe_string) = parse_probe_string(&parts[3..].join(" "))
+                {
                     current_probe = Some(ProbeEntry {
-                        protocol,
-                        name,
+                        protocol: parts[1].to_string(),
+                        name: parts[2].to_string(),
                         probe_string,
-                        no_payload,
+                        no_payload: parts.contains(&"no-payload"),
                         matches: Vec::new(),
                         soft_matches: Vec::new(),
                         ports: Vec::new(),
@@ -90,184 +84,192 @@ pub fn parse_nmap_probes_json(path: &str) -> Result<(), Box<dyn std::error::Erro
                         fallback: None,
                     });
                 }
-            },
+            }
             "match" => {
-                if let Some(ref mut probe) = current_probe {
-                    if let Some(match_entry) = parse_match_line(line) {
-                        probe.matches.push(match_entry);
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && let Some(match_entry) = parse_match_line(line)
+                {
+                    probe.matches.push(match_entry);
                 }
-            },
+            }
             "softmatch" => {
-                if let Some(ref mut probe) = current_probe {
-                    if let Some(match_entry) = parse_match_line(line) {
-                        probe.soft_matches.push(match_entry);
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && let Some(match_entry) = parse_match_line(line)
+                {
+                    probe.soft_matches.push(match_entry);
                 }
-            },
+            }
             "ports" => {
-                if let Some(ref mut probe) = current_probe {
-                    if parts.len() > 1 {
-                        probe.ports.push(parts[1..].join(" "));
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && parts.len() > 1
+                {
+                    probe.ports.push(parts[1..].join(" "));
                 }
-            },
+            }
             "sslports" => {
-                if let Some(ref mut probe) = current_probe {
-                    if parts.len() > 1 {
-                        probe.ssl_ports.push(parts[1..].join(" "));
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && parts.len() > 1
+                {
+                    probe.ssl_ports.push(parts[1..].join(" "));
                 }
-            },
+            }
             "totalwaitms" => {
-                if let Some(ref mut probe) = current_probe {
-                    if parts.len() > 1 {
-                        if let Ok(ms) = parts[1].parse::<u32>() {
-                            probe.total_wait_ms = Some(ms);
-                        }
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && parts.len() > 1
+                    && let Ok(ms) = parts[1].parse::<u32>()
+                {
+                    probe.total_wait_ms = Some(ms);
                 }
-            },
+            }
             "tcpwrappedms" => {
-                if let Some(ref mut probe) = current_probe {
-                    if parts.len() > 1 {
-                        if let Ok(ms) = parts[1].parse::<u32>() {
-                            probe.tcp_wrapped_ms = Some(ms);
-                        }
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && parts.len() > 1
+                    && let Ok(ms) = parts[1].parse::<u32>()
+                {
+                    probe.tcp_wrapped_ms = Some(ms);
                 }
-            },
+            }
             "rarity" => {
-                if let Some(ref mut probe) = current_probe {
-                    if parts.len() > 1 {
-                        if let Ok(r) = parts[1].parse::<u8>() {
-                            probe.rarity = Some(r);
-                        }
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && parts.len() > 1
+                    && let Ok(r) = parts[1].parse::<u8>()
+                {
+                    probe.rarity = Some(r);
                 }
-            },
+            }
             "fallback" => {
-                if let Some(ref mut probe) = current_probe {
-                    if parts.len() > 1 {
-                        probe.fallback = Some(parts[1..].join(" "));
-                    }
+                if let Some(ref mut probe) = current_probe
+                    && parts.len() > 1
+                {
+                    probe.fallback = Some(parts[1..].join(" "));
                 }
-            },
+            }
             _ => {
                 // Unknown directive, skip
             }
         }
     }
-    
+
     // Save the last probe if exists
     if let Some(probe) = current_probe {
         nmap_probes.probes.push(probe);
     }
-    
-    // Write to JSON file
-    let json_output = serde_json::to_string_pretty(&nmap_probes)?;
-    let mut file = fs::File::create("./assets/nmap-probes.json")?;
-    file.write_all(json_output.as_bytes())?;
-    
-    println!("Successfully parsed {} excludes and {} probes to nmap-probes.json", 
-             nmap_probes.excludes.len(), nmap_probes.probes.len());
-    
-    Ok(())
+
+    nmap_probes
+}
+
+/// Split `text` starting with a delimiter into the part up to the next delimiter and the rest
+fn delimited(text: &str) -> Option<(&str, &str)> {
+    let delimiter = text.chars().next()?;
+    let body = &text[delimiter.len_utf8()..];
+    let end = body.find(delimiter)?;
+    Some((&body[..end], &body[end + delimiter.len_utf8()..]))
 }
 
-fn parse_probe_string(probe_part: &str) -> String {
+fn parse_probe_string(probe_part: &str) -> Option<String> {
     // Handle probe strings like q|GET / HTTP/1.0\r\n\r\n|
-    if probe_part.starts_with('q') && probe_part.len() > 2 {
-        let mut chars = probe_part.chars();
-        chars.next(); // skip 'q'
-        if let Some(delimiter) = chars.next() {
-            if let Some(start) = probe_part.find(delimiter) {
-                if let Some(end) = probe_part.rfind(delimiter) {
-                    if start != end {
-                        return probe_part[start+delimiter.len_utf8()..end].to_string();
-                    }
-                }
-            }
-        }
-    }
-    probe_part.to_string()
+    let (probe_string, _) = delimited(probe_part.strip_prefix('q')?)?;
+    Some(probe_string.to_string())
 }
 
 fn parse_match_line(line: &str) -> Option<MatchEntry> {
     // Parse lines like: match ftp m/^220.*Welcome to .*Pure-?FTPd (\d\S+\s*)/ p/Pure-FTPd/ v/$1/ cpe:/a:pureftpd:pure-ftpd:$1/
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.len() < 3 {
-        return None;
-    }
-    
-    let service = parts[1].to_string();
-    let pattern_part = parts[2].to_string();
-    
-    // Extract pattern from m/pattern/flags format
-    let pattern = if pattern_part.starts_with('m') && pattern_part.len() > 2 {
-        let mut chars = pattern_part.chars();
-        chars.next(); // skip 'm'
-        if let Some(delimiter) = chars.next() {
-            if let Some(start) = pattern_part.find(delimiter) {
-                if let Some(end) = pattern_part.rfind(delimiter) {
-                    if start != end {
-                        pattern_part[start+delimiter.len_utf8()..end].to_string()
-                    } else {
-                        pattern_part
-                    }
-                } else {
-                    pattern_part
-                }
-            } else {
-                pattern_part
-            }
-        } else {
-            pattern_part
-        }
+    // Patterns and fields may hold spaces, so they are split at their delimiters
+    let (_, rest) = line.split_once(char::is_whitespace)?;
+    let (service, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    let (pattern, rest) = delimited(rest.trim_start().strip_prefix('m')?)?;
+    let flags: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    let mut rest = rest[flags.len()..].trim_start();
+
+    let inline_flags: String = flags.chars().filter(|c| matches!(c, 's' | 'i')).collect();
+    let pattern = if inline_flags.is_empty() {
+        pattern.to_string()
     } else {
-        pattern_part
+        format!("(?{}){}", inline_flags, pattern)
     };
-    
+
     // Parse version info fields (p/, v/, i/, h/, o/, d/, cpe:)
     let mut version_info = HashMap::new();
-    for part in &parts[3..] {
-        if let Some(field_info) = parse_version_field(part) {
-            version_info.insert(field_info.0, field_info.1);
+    let mut cpe = Vec::new();
+    while !rest.is_empty() {
+        if let Some(field) = rest.strip_prefix("cpe:") {
+            let (value, remaining) = delimited(field)?;
+            cpe.push(value.to_string());
+            // `a` marks a CPE that names an application
+            rest = remaining.trim_start_matches('a');
+        } else {
+            let field_type = rest.chars().next()?;
+            let (value, remaining) = delimited(&rest[field_type.len_utf8()..])?;
+            if matches!(field_type, 'p' | 'v' | 'i' | 'h' | 'o' | 'd') {
+                version_info.insert(field_type.to_string(), value.to_string());
+            }
+            rest = remaining;
         }
+        rest = rest.trim_start();
     }
-    
+
     Some(MatchEntry {
-        service,
+        service: service.to_string(),
         pattern,
         version_info,
+        cpe,
     })
 }
 
-fn parse_version_field(field: &str) -> Option<(String, String)> {
-    // Parse fields like p/Pure-FTPd/, v/$1/, i/protocol $1/, etc.
-    if field.len() < 3 {
-        return None;
-    }
-    
-    let mut chars = field.chars();
-    let field_type = chars.next()?.to_string();
-    if !matches!(field_type.as_str(), "p" | "v" | "i" | "h" | "o" | "d") && !field.starts_with("cpe:") {
-        return None;
-    }
-    
-    if field.starts_with("cpe:") {
-        return Some(("cpe".to_string(), field[4..].to_string()));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_lines_keep_spaces_of_patterns_and_fields() {
+        let entry = parse_match_line(
+            r"match ftp m|^220 .*Welcome to Pure-?FTPd (\d\S+)|si p/Pure-FTPd/ v/$1/ i/protocol $1/ cpe:/a:pureftpd:pure-ftpd:$1/a",
+        )
+        .unwrap();
+        assert_eq!(entry.service, "ftp");
+        assert_eq!(entry.pattern, r"(?si)^220 .*Welcome to Pure-?FTPd (\d\S+)");
+        assert_eq!(entry.version_info["p"], "Pure-FTPd");
+        assert_eq!(entry.version_info["v"], "$1");
+        assert_eq!(entry.version_info["i"], "protocol $1");
+        assert_eq!(entry.cpe, ["a:pureftpd:pure-ftpd:$1"]);
+
+        assert!(parse_match_line("match ftp m|unterminated").is_none());
     }
-    
-    let delimiter = chars.next()?;
-    if let Some(start) = field.find(delimiter) {
-        if let Some(end) = field.rfind(delimiter) {
-            if start != end {
-                let value = field[start+delimiter.len_utf8()..end].to_string();
-                return Some((field_type, value));
-            }
-        }
+
+    #[test]
+    fn probes_collect_their_directives() {
+        let probes = parse_nmap_probes(
+            "# comment\n\
+             Exclude T:9100-9107\n\
+             Probe TCP NULL q||\n\
+             totalwaitms 6000\n\
+             match ssh m|^SSH-([\\d.]+)-| i/protocol $1/\n\
+             Probe TCP GetRequest q|GET / HTTP/1.0\\r\\n\\r\\n|\n\
+             rarity 1\n\
+             ports 80,8000-8010\n\
+             fallback GenericLines\n\
+             softmatch http m|^HTTP/1\\.[01]|\n\
+             Probe UDP Empty q|| no-payload\n",
+        );
+        assert_eq!(probes.excludes, ["T:9100-9107"]);
+        let names: Vec<_> = probes.probes.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["NULL", "GetRequest", "Empty"]);
+
+        let [null, get, empty] = &probes.probes[..] else {
+            unreachable!();
+        };
+        assert_eq!(null.probe_string, "");
+        assert_eq!(null.total_wait_ms, Some(6000));
+        assert_eq!(null.matches.len(), 1);
+        assert_eq!(get.probe_string, r"GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(get.rarity, Some(1));
+        assert_eq!(get.ports, ["80,8000-8010"]);
+        assert_eq!(get.fallback.as_deref(), Some("GenericLines"));
+        assert_eq!(get.soft_matches.len(), 1);
+        assert_eq!(empty.protocol, "UDP");
+        assert!(empty.no_payload);
     }
-    
-    None
-}
\ No newline at end of file
+}
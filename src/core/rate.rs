@@ -0,0 +1,365 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Lowest rate backing off can reach when no minimum rate is set
+const MIN_BACKOFF_RATE: f64 = 1.0;
+/// Backing off again sooner would react to drops caused by the previous rate
+const BACKOFF_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the rate may grow by [`RECOVERY_FACTOR`] while probes are answered
+const RECOVERY_INTERVAL: Duration = Duration::from_millis(100);
+const RECOVERY_FACTOR: f64 = 1.01;
+/// Probes a full bucket holds, as a share of the rate
+const BURST: f64 = 0.1;
+
+/// Token bucket limiting how many probes per second are sent
+///
+/// The bucket fills at the current rate and holds a tenth of a second of probes, so short
+/// bursts pass right away while the average stays at the rate. A probe that finds the bucket
+/// empty reserves the next token and waits for it. The rate is halved by
+/// [`RateLimiter::backoff`] when probes get lost, never below the minimum rate, and grows back
+/// slowly with [`RateLimiter::recover`] while probes are answered. Without a maximum rate
+/// probes are not delayed until the first backoff.
+///
+/// With a minimum rate, [`RateLimiter::permit`] starts probes beyond the concurrency limit
+/// when they would otherwise fall behind it.
+pub struct RateLimiter {
+    min_rate: Option<f64>,
+    max_rate: Option<f64>,
+    state: Mutex<RateState>,
+}
+
+struct RateState {
+    /// Probes per second, `None` while unlimited
+    rate: Option<f64>,
+    /// Tokens in the bucket, negative while probes wait for reserved ones
+    tokens: f64,
+    last_refill: Instant,
+    /// Rate to return to unlimited at, the rate measured when backing off without a maximum
+    unlimited_rate: Option<f64>,
+    last_backoff: Option<Instant>,
+    last_recovery: Instant,
+    /// When the last probe was started by [`RateLimiter::permit`]
+    last_start: Instant,
+    window_start: Instant,
+    window_sent: u64,
+    /// Probes per second sent during the last full second
+    measured_rate: f64,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(min_rate: Option<f64>, max_rate: Option<f64>) -> RateLimiter {
+        let now = Instant::now();
+        RateLimiter {
+            min_rate,
+            max_rate,
+            state: Mutex::new(RateState {
+                rate: max_rate,
+                tokens: max_rate.map_or(0.0, capacity),
+                last_refill: now,
+                unlimited_rate: None,
+                last_backoff: None,
+                last_recovery: now,
+                last_start: now,
+                window_start: now,
+                window_sent: 0,
+                measured_rate: 0.0,
+            }),
+        }
+    }
+
+    /// Wait until the next probe may be sent
+    pub async fn acquire(&self) {
+        let wait = self.take(Instant::now());
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a token for a probe sent at `now`, with how long the probe has to wait for it
+    fn take(&self, now: Instant) -> Option<Duration> {
+        let mut state = self.state();
+        state.count_probe(now);
+        let rate = state.rate?;
+        state.refill(now, rate);
+        state.tokens -= 1.0;
+        (state.tokens < 0.0).then(|| Duration::from_secs_f64(-state.tokens / rate))
+    }
+
+    /// Wait for a permit of `semaphore` before starting a probe
+    ///
+    /// With a minimum rate, gives `None` once waiting longer would send fewer probes than it,
+    /// and the probe is started without a permit.
+    pub async fn permit(&self, semaphore: &Arc<Semaphore>) -> Option<OwnedSemaphorePermit> {
+        let permit = match self.min_rate {
+            Some(min_rate) => {
+                let deadline = self.state().last_start + Duration::from_secs_f64(1.0 / min_rate);
+                tokio::select! {
+                    permit = semaphore.clone().acquire_owned() => permit.ok(),
+                    _ = tokio::time::sleep_until(deadline.into()) => None,
+                }
+            }
+            None => semaphore.clone().acquire_owned().await.ok(),
+        };
+        self.state().last_start = Instant::now();
+        permit
+    }
+
+    /// Halve the rate after signs of dropped probes
+    pub fn backoff(&self) {
+        self.backoff_at(Instant::now());
+    }
+
+    fn backoff_at(&self, now: Instant) {
+        let mut state = self.state();
+        if state
+            .last_backoff
+            .is_some_and(|last| now.duration_since(last) < BACKOFF_INTERVAL)
+        {
+            return;
+        }
+
+        let floor = self.min_rate.unwrap_or(MIN_BACKOFF_RATE);
+        let current = match state.rate {
+            Some(rate) => {
+                state.refill(now, rate);
+                rate
+            }
+            None => {
+                let measured = state.current_rate(now).max(floor);
+                state.unlimited_rate = Some(measured);
+                state.tokens = 0.0;
+                state.last_refill = now;
+                measured
+            }
+        };
+        state.rate = Some((current / 2.0).max(floor));
+        state.last_backoff = Some(now);
+        state.last_recovery = now;
+    }
+
+    /// Let the rate grow back towards its maximum while probes are answered
+    pub fn recover(&self) {
+        self.recover_at(Instant::now());
+    }
+
+    fn recover_at(&self, now: Instant) {
+        let mut state = self.state();
+        let Some(rate) = state.rate else {
+            return;
+        };
+        if now.duration_since(state.last_recovery) < RECOVERY_INTERVAL {
+            return;
+        }
+        state.last_recovery = now;
+        state.refill(now, rate);
+
+        let rate = rate * RECOVERY_FACTOR;
+        match self.max_rate.or(state.unlimited_rate) {
+            Some(ceiling) if rate < ceiling => state.rate = Some(rate),
+            _ => {
+                state.rate = self.max_rate;
+                state.unlimited_rate = None;
+            }
+        }
+    }
+
+    /// Probes per second, `None` while unlimited
+    #[cfg(test)]
+    pub(crate) fn rate(&self) -> Option<f64> {
+        self.state().rate
+    }
+
+    fn state(&self) -> MutexGuard<'_, RateState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// Tokens a full bucket holds at `rate`
+fn capacity(rate: f64) -> f64 {
+    (rate * BURST).max(1.0)
+}
+
+impl RateState {
+    /// Add the tokens earned at `rate` since the last refill
+    fn refill(&mut self, now: Instant, rate: f64) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity(rate));
+        self.last_refill = self.last_refill.max(now);
+    }
+
+    fn count_probe(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= Duration::from_secs(1) {
+            self.measured_rate = self.window_sent as f64 / elapsed.as_secs_f64();
+            self.window_start = now;
+            self.window_sent = 0;
+        }
+        self.window_sent += 1;
+    }
+
+    /// The rate of the last full second, or of the current one early in the scan
+    fn current_rate(&self, now: Instant) -> f64 {
+        if self.measured_rate > 0.0 {
+            return self.measured_rate;
+        }
+        let elapsed = now.duration_since(self.window_start).as_secs_f64();
+        if elapsed > 0.0 {
+            self.window_sent as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Back off `times` times, each far enough apart to count
+    fn back_off(limiter: &RateLimiter, start: Instant, times: u32) -> Instant {
+        let mut now = start;
+        for _ in 0..times {
+            now += BACKOFF_INTERVAL;
+            limiter.backoff_at(now);
+        }
+        now
+    }
+
+    #[test]
+    fn backoff_halves_down_to_the_floor() {
+        let limiter = RateLimiter::new(Some(10.0), Some(100.0));
+        let start = Instant::now();
+        let mut rates = Vec::new();
+        for times in 1..=5 {
+            limiter.backoff_at(start + BACKOFF_INTERVAL * times);
+            rates.push(limiter.rate().unwrap());
+        }
+        assert_eq!(rates, [50.0, 25.0, 12.5, 10.0, 10.0]);
+
+        // Without a minimum rate the floor is one probe per second
+        let limiter = RateLimiter::new(None, Some(100.0));
+        back_off(&limiter, start, 20);
+        assert_eq!(limiter.rate(), Some(MIN_BACKOFF_RATE));
+    }
+
+    #[test]
+    fn backoff_is_debounced() {
+        let limiter = RateLimiter::new(None, Some(100.0));
+        let start = Instant::now();
+        limiter.backoff_at(start);
+        limiter.backoff_at(start + BACKOFF_INTERVAL / 2);
+        limiter.backoff_at(start + BACKOFF_INTERVAL - Duration::from_millis(1));
+        assert_eq!(limiter.rate(), Some(50.0));
+        limiter.backoff_at(start + BACKOFF_INTERVAL);
+        assert_eq!(limiter.rate(), Some(25.0));
+    }
+
+    #[test]
+    fn recovery_grows_back_to_the_maximum() {
+        let limiter = RateLimiter::new(None, Some(100.0));
+        let start = Instant::now();
+        let mut now = back_off(&limiter, start, 1);
+        assert_eq!(limiter.rate(), Some(50.0));
+
+        // Too soon after the last change
+        limiter.recover_at(now + RECOVERY_INTERVAL / 2);
+        assert_eq!(limiter.rate(), Some(50.0));
+
+        let mut previous = 50.0;
+        loop {
+            now += RECOVERY_INTERVAL;
+            limiter.recover_at(now);
+            let current = limiter.rate().unwrap();
+            assert!(current > previous && current <= 100.0);
+            if current == 100.0 {
+                break;
+            }
+            previous = current;
+        }
+        now += RECOVERY_INTERVAL;
+        limiter.recover_at(now);
+        assert_eq!(limiter.rate(), Some(100.0));
+    }
+
+    #[test]
+    fn recovery_without_a_maximum_becomes_unlimited_again() {
+        let limiter = RateLimiter::new(None, None);
+        let start = Instant::now();
+        limiter.recover_at(start + RECOVERY_INTERVAL);
+        assert_eq!(limiter.rate(), None);
+
+        limiter.state().measured_rate = 80.0;
+        let mut now = back_off(&limiter, start, 1);
+        assert_eq!(limiter.rate(), Some(40.0));
+        assert_eq!(limiter.state().unlimited_rate, Some(80.0));
+
+        let mut steps = 0;
+        while limiter.rate().is_some() {
+            now += RECOVERY_INTERVAL;
+            limiter.recover_at(now);
+            steps += 1;
+            assert!(limiter.rate().is_none_or(|rate| rate < 80.0));
+        }
+        // 1.01 to the power of 70 is just above 2
+        assert_eq!(steps, 70);
+        assert_eq!(limiter.state().unlimited_rate, None);
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_spaces_probes() {
+        let limiter = RateLimiter::new(None, Some(100.0));
+        let now = Instant::now();
+        // A tenth of a second of probes goes out at once
+        for _ in 0..10 {
+            assert_eq!(limiter.take(now), None);
+        }
+        let waits: Vec<_> = (0..3).map(|_| limiter.take(now).unwrap()).collect();
+        for (wait, expected) in waits.iter().zip([10, 20, 30]) {
+            assert!(wait.abs_diff(Duration::from_millis(expected)) < Duration::from_micros(10));
+        }
+
+        // Tokens earned while idle do not exceed the bucket
+        let later = now + Duration::from_secs(10);
+        for _ in 0..10 {
+            assert_eq!(limiter.take(later), None);
+        }
+        assert!(limiter.take(later).is_some());
+    }
+
+    #[test]
+    fn unlimited_probes_do_not_wait() {
+        let limiter = RateLimiter::default();
+        let now = Instant::now();
+        assert!((0..1000).all(|_| limiter.take(now).is_none()));
+    }
+
+    #[tokio::test]
+    async fn minimum_rate_starts_probes_without_a_permit() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let limiter = RateLimiter::new(Some(20.0), None);
+        let held = limiter.permit(&semaphore).await;
+        assert!(held.is_some());
+
+        // The only permit is taken, so the next probe starts after 1/20 s without one
+        let started = Instant::now();
+        assert!(limiter.permit(&semaphore).await.is_none());
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(45), "{:?}", waited);
+
+        let limiter = RateLimiter::default();
+        drop(held);
+        assert!(limiter.permit(&semaphore).await.is_some());
+    }
+}
@@ -0,0 +1,424 @@
+use regex::Regex;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Metadata describing a Lua script on disk
+///
+/// Scripts declare their metadata with plain Lua assignments at the top level, e.g.
+///
+/// ```lua
+/// description = "Grabs the HTTP title of the index page"
+/// categories = {"default", "safe"}
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScriptInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub description: Option<String>,
+    pub categories: Vec<String>,
+}
+
+impl ScriptInfo {
+    /// Read a script file and extract its metadata without executing it
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid script path: {}", path.display()))?
+            .to_string();
+        let content = fs::read_to_string(path)?;
+
+        let description_re =
+            Regex::new(r#"(?m)^description\s*=\s*(?:"([^"]*)"|'([^']*)'|\[\[([^\]]*)\]\])"#)?;
+        let description = description_re.captures(&content).and_then(|caps| {
+            caps.get(1)
+                .or_else(|| caps.get(2))
+                .or_else(|| caps.get(3))
+                .map(|m| m.as_str().trim().to_string())
+        });
+
+        let categories_re = Regex::new(r"(?m)^categories\s*=\s*\{([^}]*)\}")?;
+        let categories = categories_re
+            .captures(&content)
+            .map(|caps| {
+                caps[1]
+                    .split(',')
+                    .map(|c| {
+                        c.trim()
+                            .trim_matches(|ch| ch == '"' || ch == '\'')
+                            .to_string()
+                    })
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ScriptInfo {
+            name,
+            path: path.to_path_buf(),
+            description,
+            categories,
+        })
+    }
+
+    fn has_category(&self, category: &str) -> bool {
+        self.categories
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(category))
+    }
+}
+
+/// Collect every `.lua` script in a directory, sorted by name
+///
+/// Scripts that cannot be read, or are not UTF-8, are skipped with a warning so one broken
+/// file does not hide the others.
+pub fn discover_scripts(dir: &Path) -> Result<Vec<ScriptInfo>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut scripts = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "lua") {
+            match ScriptInfo::from_path(&path) {
+                Ok(script) => scripts.push(script),
+                Err(e) => eprintln!("Warning: skipping script {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    scripts.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(scripts)
+}
+
+/// Resolve a script selection into the list of scripts to run
+///
+/// The selection is a comma-separated list where each item is one of:
+///
+/// * a path to a `.lua` file or to a directory of scripts
+/// * a script name or glob (`http-*`) from the scripts directory
+/// * a category (`default`, `safe`, `intrusive`, ...) or `all`
+/// * a boolean expression combining the above with `and`, `or`, `not` and parentheses,
+///   e.g. `"default and not intrusive"`
+///
+/// # Arguments
+///
+/// * `selection` - The selection string given on the command line or in the config
+/// * `scripts_dir` - The directory holding the available scripts
+///
+/// # Returns
+///
+/// The matching scripts in selection order without duplicates, or an error if an item
+/// matches nothing or an expression cannot be parsed
+pub fn select_scripts(
+    selection: &str,
+    scripts_dir: &Path,
+) -> Result<Vec<ScriptInfo>, Box<dyn Error>> {
+    let available = discover_scripts(scripts_dir)?;
+    let mut selected: Vec<ScriptInfo> = Vec::new();
+
+    for item in split_top_level(selection) {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+
+        let matches = select_item(item, &available)?;
+        if matches.is_empty() {
+            return Err(format!("No scripts match '{}' in {}", item, scripts_dir.display()).into());
+        }
+
+        for script in matches {
+            if !selected.iter().any(|s| s.path == script.path) {
+                selected.push(script);
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+fn select_item(item: &str, available: &[ScriptInfo]) -> Result<Vec<ScriptInfo>, Box<dyn Error>> {
+    // Explicit paths take precedence over names and categories
+    let path = Path::new(item);
+    if path.is_dir() {
+        return discover_scripts(path);
+    }
+    if path.is_file() {
+        return Ok(vec![ScriptInfo::from_path(path)?]);
+    }
+
+    let expr = Expr::parse(item)?;
+    Ok(available
+        .iter()
+        .filter(|script| expr.matches(script))
+        .cloned()
+        .collect())
+}
+
+/// Split on commas that are not nested inside parentheses
+fn split_top_level(selection: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, ch) in selection.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                items.push(&selection[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&selection[start..]);
+
+    items
+}
+
+/// Boolean selection expression over script names and categories
+#[derive(Debug)]
+enum Expr {
+    Pattern(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn parse(input: &str) -> Result<Expr, Box<dyn Error>> {
+        let tokens = tokenize(input);
+        let mut pos = 0;
+        let expr = Self::parse_or(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(format!(
+                "Unexpected '{}' in script expression '{}'",
+                tokens[pos], input
+            )
+            .into());
+        }
+        Ok(expr)
+    }
+
+    fn parse_or(tokens: &[String], pos: &mut usize) -> Result<Expr, Box<dyn Error>> {
+        let mut left = Self::parse_and(tokens, pos)?;
+        while tokens.get(*pos).is_some_and(|t| t == "or") {
+            *pos += 1;
+            let right = Self::parse_and(tokens, pos)?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(tokens: &[String], pos: &mut usize) -> Result<Expr, Box<dyn Error>> {
+        let mut left = Self::parse_not(tokens, pos)?;
+        while tokens.get(*pos).is_some_and(|t| t == "and") {
+            *pos += 1;
+            let right = Self::parse_not(tokens, pos)?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(tokens: &[String], pos: &mut usize) -> Result<Expr, Box<dyn Error>> {
+        match tokens.get(*pos).map(String::as_str) {
+            Some("not") => {
+                *pos += 1;
+                Ok(Expr::Not(Box::new(Self::parse_not(tokens, pos)?)))
+            }
+            Some("(") => {
+                *pos += 1;
+                let expr = Self::parse_or(tokens, pos)?;
+                if tokens.get(*pos).map(String::as_str) != Some(")") {
+                    return Err("Missing ')' in script expression".into());
+                }
+                *pos += 1;
+                Ok(expr)
+            }
+            Some(")") | Some("and") | Some("or") | None => {
+                Err("Expected a script name or category in script expression".into())
+            }
+            Some(pattern) => {
+                *pos += 1;
+                Ok(Expr::Pattern(pattern.to_string()))
+            }
+        }
+    }
+
+    fn matches(&self, script: &ScriptInfo) -> bool {
+        match self {
+            Expr::Pattern(pattern) => {
+                pattern == "all"
+                    || script.has_category(pattern)
+                    || glob_match(pattern, &script.name)
+            }
+            Expr::Not(inner) => !inner.matches(script),
+            Expr::And(left, right) => left.matches(script) && right.matches(script),
+            Expr::Or(left, right) => left.matches(script) || right.matches(script),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+
+    for ch in input.chars() {
+        match ch {
+            '(' | ')' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(ch.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// Shell-style wildcard matching supporting `*` and `?`
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(name: &str, categories: &[&str]) -> ScriptInfo {
+        ScriptInfo {
+            name: name.to_string(),
+            path: PathBuf::from(format!("{}.lua", name)),
+            description: None,
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    fn scripts() -> Vec<ScriptInfo> {
+        vec![
+            script("http-title", &["default", "safe"]),
+            script("http-enum", &["discovery", "intrusive"]),
+            script("ssl-cert", &["default", "safe"]),
+            script("smb-brute", &["brute", "intrusive"]),
+        ]
+    }
+
+    fn selected(expression: &str) -> Vec<String> {
+        let expr = Expr::parse(expression).unwrap();
+        scripts()
+            .into_iter()
+            .filter(|script| expr.matches(script))
+            .map(|script| script.name)
+            .collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            selected("brute or default and http-*"),
+            ["http-title", "smb-brute"]
+        );
+        assert_eq!(
+            selected("(brute or default) and not ssl-*"),
+            ["http-title", "smb-brute"]
+        );
+    }
+
+    #[test]
+    fn nested_parentheses() {
+        assert_eq!(
+            selected("((http-* or ssl-*) and (safe or (brute)))"),
+            ["http-title", "ssl-cert"]
+        );
+        assert_eq!(selected("not (not intrusive)"), ["http-enum", "smb-brute"]);
+    }
+
+    #[test]
+    fn not_with_category() {
+        assert_eq!(
+            selected("default and not intrusive"),
+            ["http-title", "ssl-cert"]
+        );
+        assert_eq!(selected("not safe"), ["http-enum", "smb-brute"]);
+        assert_eq!(selected("all and not DEFAULT").len(), 2);
+    }
+
+    #[test]
+    fn unbalanced_parentheses_are_errors() {
+        for expression in ["(default or safe", "default)", "not", "default and", "()"] {
+            assert!(Expr::parse(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        let cases = [
+            ("http-*", "http-title", true),
+            ("http-*", "ssl-cert", false),
+            ("*", "anything", true),
+            ("*-cert", "ssl-cert", true),
+            ("ssl-?ert", "ssl-cert", true),
+            ("ssl-?ert", "ssl-ert", false),
+            ("h*t*e", "http-title", true),
+            ("h*t*x", "http-title", false),
+            ("http-title", "http-title", true),
+            ("http-title", "http-titles", false),
+        ];
+        for (pattern, name, expected) in cases {
+            assert_eq!(
+                glob_match(pattern, name),
+                expected,
+                "{} ~ {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn commas_split_outside_parentheses_only() {
+        assert_eq!(
+            split_top_level("http-title,(default or safe, brute),ssl-*"),
+            ["http-title", "(default or safe, brute)", "ssl-*"]
+        );
+        assert_eq!(split_top_level("single"), ["single"]);
+        assert_eq!(split_top_level("a,,b"), ["a", "", "b"]);
+    }
+}
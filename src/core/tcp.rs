@@ -1,44 +1,143 @@
-use crate::args::{Config, get_config};
-use crate::core::lua::LuaScriptRunner;
-use crate::output::OutputHandler;
-use crate::utils::valid_ip;
+use crate::args::Config;
+use crate::checkpoint::Checkpoint;
+use crate::core::cancel::CancellationToken;
+use crate::core::lua::{LuaScriptRunner, ScriptResult};
+use crate::core::order::ProbeOrder;
+use crate::core::probe::Prober;
+use crate::core::rate::RateLimiter;
+use crate::core::timing::HostTiming;
+use crate::core::web::{self, WebEnumOptions, WebEnumResult};
 use crate::dns::DNSResolver;
-use std::collections::HashMap;
+use crate::events::{HostProgress, Observers, ScanEvent};
+use crate::output::OutputHandler;
+use crate::report::{HostReport, PortReport, Protocol};
+use crate::utils::{parse_ports, valid_ip};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
 use std::io::ErrorKind;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
-use tokio::sync::Semaphore;
-use indicatif::{ProgressBar, ProgressStyle};
-use serde::Serialize;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
 
 pub struct TCPScanner {
     /// The configuration for the current scan
     pub config: Config,
     /// A local DNS resolver
     pub dns: DNSResolver,
+    /// Receivers of progress and result events
+    pub observers: Observers,
+    /// Stops the scan early, results gathered so far are still returned
+    pub cancel: CancellationToken,
+    /// Draw progress bars and status messages on the terminal
+    pub interactive: bool,
+    /// Results of an earlier run of this scan, done hosts and ports are not probed again
+    pub resume: Option<Arc<Checkpoint>>,
+    /// Rate limit shared by every host and protocol of the scan
+    pub rate: Arc<RateLimiter>,
+    /// Detects the versions of services on open ports, when `service_version` is set
+    pub prober: Option<Arc<Prober>>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum PortState {
     Open,
     Closed,
     Filtered, // Likely blocked by firewall
+    /// No answer to a UDP probe, the port is either open or filtered
+    #[serde(rename = "open|filtered")]
+    OpenFiltered,
+}
+
+impl PortState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::OpenFiltered => "open|filtered",
+        }
+    }
+}
+
+/// Why a port was given its state
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PortReason {
+    /// The TCP handshake completed
+    SynAck,
+    /// The connection was actively refused (RST)
+    ConnRefused,
+    /// The operating system gave up on the connection attempt
+    Timeout,
+    /// No answer within the probe timeout
+    NoResponse,
+    HostUnreach,
+    NetUnreach,
+    AdminProhibited,
+    /// A UDP probe was answered
+    UdpResponse,
+    /// A UDP probe was answered with ICMP port unreachable
+    PortUnreach,
+    /// The probe could not be sent
+    Error,
+}
+
+impl PortReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PortReason::SynAck => "syn-ack",
+            PortReason::ConnRefused => "conn-refused",
+            PortReason::Timeout => "timeout",
+            PortReason::NoResponse => "no-response",
+            PortReason::HostUnreach => "host-unreach",
+            PortReason::NetUnreach => "net-unreach",
+            PortReason::AdminProhibited => "admin-prohibited",
+            PortReason::UdpResponse => "udp-response",
+            PortReason::PortUnreach => "port-unreach",
+            PortReason::Error => "error",
+        }
+    }
+
+    /// Whether the reason proves the host answered
+    pub fn is_reply(&self) -> bool {
+        matches!(
+            self,
+            PortReason::SynAck
+                | PortReason::ConnRefused
+                | PortReason::UdpResponse
+                | PortReason::PortUnreach
+        )
+    }
 }
 
 #[derive(Debug)]
 pub struct PortResult {
     pub port: String,
     pub state: PortState,
+    pub reason: PortReason,
+    /// Probes sent before the state was decided, retries included
+    pub attempts: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInfo {
     pub name: String,
     pub version: Option<String>,
     pub product: Option<String>,
     pub extra_info: Option<String>,
+    /// CPE names identifying the product, e.g. `cpe:/a:openbsd:openssh:9.6`
+    #[serde(default)]
+    pub cpe: Vec<String>,
+    /// How sure version detection is of the service, from 0 to 10 like nmap's `conf`, `None`
+    /// when the service is named from the port table
+    #[serde(default)]
+    pub confidence: Option<u8>,
 }
 
 pub struct SynScanResult {
@@ -47,164 +146,254 @@ pub struct SynScanResult {
     pub filtered_ports: Vec<PortResult>,
 }
 
-impl TCPScanner {
-    pub fn new() -> TCPScanner {
-        let config = get_config();
-        TCPScanner {
-            config,
-            dns: DNSResolver::new(),
+impl SynScanResult {
+    fn push(&mut self, result: PortResult) {
+        match result.state {
+            PortState::Open => self.open_ports.push(result),
+            PortState::Closed => self.closed_ports.push(result),
+            PortState::Filtered | PortState::OpenFiltered => self.filtered_ports.push(result),
         }
     }
+}
 
-    // Parse port ranges and individual ports
-    fn parse_ports(ports_str: &str) -> Vec<u16> {
-        let mut ports = Vec::new();
-
-        for part in ports_str.split(',') {
-            let part = part.trim();
-            if part.contains('-') {
-                // Handle range like "1-1024"
-                let range_parts: Vec<&str> = part.split('-').collect();
-                if range_parts.len() == 2 {
-                    if let (Ok(start), Ok(end)) =
-                        (range_parts[0].parse::<u16>(), range_parts[1].parse::<u16>())
-                    {
-                        for port in start..=end {
-                            ports.push(port);
-                        }
-                    }
-                }
-            } else {
-                // Handle individual port
-                if let Ok(port) = part.parse::<u16>() {
-                    ports.push(port);
-                }
-            }
+impl ServiceInfo {
+    /// The service commonly registered for a port, without any probing
+    pub fn from_port_table(port: &str) -> ServiceInfo {
+        ServiceInfo {
+            name: OutputHandler::get_service_for_port(port).to_string(),
+            version: None,
+            product: None,
+            extra_info: None,
+            cpe: Vec::new(),
+            confidence: None,
         }
+    }
+}
+
+type SynScanOutcome = Result<SynScanResult, Box<dyn Error + Send + Sync>>;
+
+/// A host being probed, shared by its probes
+struct ProbeTarget {
+    address: String,
+    display_target: String,
+    timing: HostTiming,
+    host_up: AtomicBool,
+    progress: HostProgress,
+}
+
+/// Probes of one host the dispatcher has yet to send
+struct HostProbes {
+    target: Arc<ProbeTarget>,
+    /// Ports probed before the scan was interrupted
+    done_ports: HashSet<u16>,
+    /// Probes left to send
+    remaining: usize,
+    /// Dropped once the last probe is sent
+    results: Option<UnboundedSender<PortResult>>,
+}
+
+/// A host of the scan, either being probed or taken as it is from a checkpoint
+enum HostTask {
+    Running {
+        address: String,
+        display_target: String,
+        handle: JoinHandle<SynScanOutcome>,
+    },
+    Resumed(HostReport),
+}
 
-        ports
+impl TCPScanner {
+    pub fn new(config: Config) -> TCPScanner {
+        TCPScanner {
+            config,
+            dns: DNSResolver::new(),
+            observers: Observers::default(),
+            cancel: CancellationToken::new(),
+            interactive: false,
+            resume: None,
+            rate: Arc::new(RateLimiter::default()),
+            prober: None,
+        }
     }
 
-    // Static version of syn_scan that doesn't require self
-    async fn syn_scan(
-        target: &str,
-        ports: &str,
-        timeout: u64,
+    /// Send the probes of every host in the scan order, at most `semaphore` of them at a time
+    /// unless more are needed to keep up the minimum rate of `rate`
+    ///
+    /// Each host's result sender is dropped after its last probe is sent, so the host's
+    /// collecting task finishes once those probes are done. Nothing is sent once the scan is
+    /// cancelled.
+    async fn dispatch(
+        mut hosts: Vec<Option<HostProbes>>,
+        port_list: Vec<u16>,
+        order: ProbeOrder,
         semaphore: Arc<Semaphore>,
-        _config: &Config,
-    ) -> Result<SynScanResult, Box<dyn Error + Send + Sync>> {
-        let target_owned = target.to_string();
-        let mut handles = vec![];
+        rate: Arc<RateLimiter>,
+        observers: Observers,
+        cancel: CancellationToken,
+    ) {
+        for (host_index, port_index) in order {
+            // Hosts taken from a checkpoint have no probes left
+            let Some(host) = hosts[host_index].as_mut() else {
+                continue;
+            };
+            let port = port_list[port_index];
+            if host.done_ports.contains(&port) {
+                continue;
+            }
 
-        // Parse ports (handles both ranges and individual ports)
-        let port_list = Self::parse_ports(ports);
+            // Acquire semaphore permit before probing, queued probes are not sent once the
+            // scan is cancelled
+            let permit = tokio::select! {
+                permit = rate.permit(&semaphore) => permit,
+                _ = cancel.cancelled() => break,
+            };
+            if cancel.is_cancelled() {
+                break;
+            }
 
-        if port_list.is_empty() {
-            return Ok(SynScanResult {
-                open_ports: Vec::new(),
-                closed_ports: Vec::new(),
-                filtered_ports: Vec::new(),
-            });
+            host.remaining -= 1;
+            let results = if host.remaining == 0 {
+                host.results.take()
+            } else {
+                host.results.clone()
+            };
+            if let Some(results) = results {
+                tokio::spawn(Self::probe_port(
+                    host.target.clone(),
+                    port,
+                    permit,
+                    observers.clone(),
+                    cancel.clone(),
+                    results,
+                ));
+            }
         }
+    }
 
-        // Print scan message
-        println!("\x1b[31mrunning TCP scan\x1b[0m");
-        
-        // Create progress bar
-        let pb = ProgressBar::new(port_list.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{percent:>3}%|{bar:25.red/bright_red}| {pos}/{len} [{elapsed_precise}<{eta_precise}, {per_sec}]")
-                .unwrap()
-                .progress_chars("█▉▊▋▌▍▎▏ "),
-        );
-
-        for port in port_list {
-            let target_clone = target_owned.clone();
-            let port_string = port.to_string();
-            let sem_clone = semaphore.clone();
-            let handle = tokio::spawn(async move {
-                // Acquire semaphore permit before scanning
-                let _permit = sem_clone.acquire().await.unwrap();
-
-                match tokio::time::timeout(
-                    Duration::from_millis(timeout),
-                    TcpStream::connect((target_clone.as_str(), port)),
-                )
-                .await
-                {
-                    Ok(Ok(stream)) => {
-                        // Successfully connected - port is open
-                        drop(stream); // Close the connection
-
-                        Some(PortResult {
-                            port: port_string,
-                            state: PortState::Open,
-                        })
-                    }
-                    Ok(Err(e)) => {
-                        // Analyze the connection error to determine port state
-                        let state = match e.kind() {
-                            ErrorKind::ConnectionRefused => PortState::Closed,
-                            ErrorKind::TimedOut => PortState::Filtered,
-                            ErrorKind::PermissionDenied => PortState::Filtered,
-                            ErrorKind::NetworkUnreachable => PortState::Filtered,
-                            ErrorKind::HostUnreachable => PortState::Filtered,
-                            _ => {
-                                // For unknown errors, try to infer from error message
-                                let error_msg = e.to_string().to_lowercase();
-                                if error_msg.contains("refused") {
-                                    PortState::Closed
-                                } else if error_msg.contains("timeout")
-                                    || error_msg.contains("unreachable")
-                                    || error_msg.contains("filtered")
-                                {
-                                    PortState::Filtered
-                                } else {
-                                    PortState::Closed // Default to closed for unknown errors
-                                }
-                            }
-                        };
-                        Some(PortResult {
-                            port: port_string,
-                            state,
-                        })
+    /// Probe one port and pass the result to the host's collecting task
+    async fn probe_port(
+        host: Arc<ProbeTarget>,
+        port: u16,
+        _permit: Option<OwnedSemaphorePermit>,
+        observers: Observers,
+        cancel: CancellationToken,
+        results: UnboundedSender<PortResult>,
+    ) {
+        let timing = &host.timing;
+
+        // Probes without an answer are sent again with a doubled timeout, answers adapt it
+        let mut retries = 0;
+        let (state, reason) = loop {
+            tokio::select! {
+                _ = timing.wait_turn() => {}
+                _ = cancel.cancelled() => return,
+            }
+            let started = Instant::now();
+            let connect = tokio::time::timeout(
+                timing.timeout(retries),
+                TcpStream::connect((host.address.as_str(), port)),
+            );
+            let outcome = tokio::select! {
+                outcome = connect => outcome,
+                _ = cancel.drained() => return,
+            };
+
+            let (state, reason) = match outcome {
+                Ok(Ok(stream)) => {
+                    // Successfully connected - port is open
+                    drop(stream); // Close the connection
+                    (PortState::Open, PortReason::SynAck)
+                }
+                // Analyze the connection error to determine port state
+                Ok(Err(e)) => match e.kind() {
+                    ErrorKind::ConnectionRefused => (PortState::Closed, PortReason::ConnRefused),
+                    ErrorKind::TimedOut => (PortState::Filtered, PortReason::Timeout),
+                    ErrorKind::PermissionDenied => {
+                        (PortState::Filtered, PortReason::AdminProhibited)
                     }
-                    Err(_) => {
-                        // Timeout from tokio::time::timeout - likely filtered by firewall
-                        Some(PortResult {
-                            port: port_string,
-                            state: PortState::Filtered,
-                        })
+                    ErrorKind::NetworkUnreachable => (PortState::Filtered, PortReason::NetUnreach),
+                    ErrorKind::HostUnreachable => (PortState::Filtered, PortReason::HostUnreach),
+                    _ => {
+                        // For unknown errors, try to infer from error message
+                        let error_msg = e.to_string().to_lowercase();
+                        if error_msg.contains("refused") {
+                            (PortState::Closed, PortReason::ConnRefused)
+                        } else if error_msg.contains("timeout") || error_msg.contains("filtered") {
+                            (PortState::Filtered, PortReason::Timeout)
+                        } else if error_msg.contains("unreachable") {
+                            (PortState::Filtered, PortReason::HostUnreach)
+                        } else {
+                            // Default to closed for unknown errors
+                            (PortState::Closed, PortReason::Error)
+                        }
                     }
-                }
-                // Permit is automatically released when _permit is dropped
-            });
-            handles.push(handle);
-        }
-
-        let mut open_ports = Vec::new();
-        let mut closed_ports = Vec::new();
-        let mut filtered_ports = Vec::new();
+                },
+                // Timeout from tokio::time::timeout - likely filtered by firewall
+                Err(_) => (PortState::Filtered, PortReason::NoResponse),
+            };
 
-        for handle in handles {
-            if let Ok(Some(result)) = handle.await {
-                match result.state {
-                    PortState::Open => open_ports.push(result),
-                    PortState::Closed => closed_ports.push(result),
-                    PortState::Filtered => filtered_ports.push(result),
+            if reason.is_reply() {
+                timing.record_answer(started.elapsed(), retries);
+            }
+            let answered = !matches!(reason, PortReason::NoResponse | PortReason::Timeout);
+            if answered || retries >= timing.max_retries() || cancel.is_cancelled() {
+                if !answered && !cancel.is_cancelled() {
+                    timing.record_timeout();
                 }
+                break (state, reason);
             }
-            pb.inc(1);
-        }
+            retries += 1;
+        };
+
+        let result = PortResult {
+            port: port.to_string(),
+            state,
+            reason,
+            attempts: retries + 1,
+        };
+        observers.port_result(
+            &host.display_target,
+            &host.address,
+            Protocol::Tcp,
+            &result,
+            &host.host_up,
+        );
+        host.progress.advance(&observers);
+        // The collecting task only stops once every sender is dropped
+        let _ = results.send(result);
+        // Permit is automatically released when _permit is dropped
+    }
 
-        pb.finish_and_clear();
+    /// Gather the port results of one host until its last probe is done
+    ///
+    /// Results are sorted by port so scripts see the same order whatever order the ports
+    /// were probed in.
+    async fn collect_results(
+        mut results: UnboundedReceiver<PortResult>,
+        done: Vec<PortResult>,
+    ) -> SynScanOutcome {
+        let mut scan_result = SynScanResult {
+            open_ports: Vec::new(),
+            closed_ports: Vec::new(),
+            filtered_ports: Vec::new(),
+        };
+
+        while let Some(result) = results.recv().await {
+            scan_result.push(result);
+        }
+        // Ports probed before the scan was interrupted
+        for result in done {
+            scan_result.push(result);
+        }
 
-        Ok(SynScanResult {
-            open_ports,
-            closed_ports,
-            filtered_ports,
-        })
+        for ports in [
+            &mut scan_result.open_ports,
+            &mut scan_result.closed_ports,
+            &mut scan_result.filtered_ports,
+        ] {
+            ports.sort_by_key(|result| result.port.parse::<u16>().unwrap_or(u16::MAX));
+        }
+        Ok(scan_result)
     }
 
     /// Execute the TCP scanner
@@ -220,14 +409,17 @@ impl TCPScanner {
     ///
     /// # Returns
     ///
-    /// A `Result` containing the scan results, or an error if the scan fails
-    pub async fn exec(&self) -> Result<(), Box<dyn Error>> {
+    /// A `Result` containing a report for every scanned host, or an error if the scan fails
+    pub async fn exec(&self) -> Result<Vec<HostReport>, Box<dyn Error>> {
         let target = &self.config.target;
         let ports = &self.config.ports;
         let timeout = self.config.timeout;
+        let min_rtt_timeout = self.config.min_rtt_timeout;
+        let max_rtt_timeout = self.config.max_rtt_timeout;
         let threads = self.config.threads;
-        let json_output = &self.config.json;
-        let verbose = self.config.verbose;
+        let interactive = self.interactive;
+        // Progress messages would corrupt an event stream on stdout
+        let verbose = interactive && self.config.verbose && !self.config.streams_to_stdout();
 
         if verbose {
             println!(
@@ -237,29 +429,56 @@ impl TCPScanner {
             );
             println!("Target(s): {:?}", target);
             println!("Ports: {}", ports);
-            println!("Timeout: {}ms", timeout);
+            println!(
+                "Timeout: {}ms, adapting between {}ms and {}ms, up to {} retr{}",
+                timeout,
+                min_rtt_timeout,
+                max_rtt_timeout,
+                self.config.max_retries,
+                if self.config.max_retries == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            );
+            match self.config.seed.filter(|_| !self.config.ordered) {
+                Some(seed) => println!("Probe order: random, seed {}", seed),
+                None => println!("Probe order: as given"),
+            }
         }
 
         // resolve targets to IP, and validate them
-        // Keep track of original target names for display
-        let mut target_mapping: HashMap<String, String> = HashMap::new();
+        // Keep track of original target names for display, the same address may be
+        // reached through several names
+        let mut target_mapping: Vec<(String, String)> = Vec::new();
         let mut targets: Vec<String> = Vec::new();
 
         for original_target in target {
             if valid_ip(original_target) {
                 targets.push(original_target.to_string());
-                target_mapping.insert(original_target.to_string(), original_target.to_string());
+                target_mapping.push((original_target.to_string(), original_target.to_string()));
             } else {
-                let ips_str = self.dns.resolve_to_ip(original_target).await?;
+                // A target that does not resolve is reported and the others are still scanned
+                let ips_str = match self.dns.resolve_to_ip(original_target).await {
+                    Ok(ips_str) => ips_str,
+                    Err(e) => {
+                        self.observers.error(
+                            original_target,
+                            "",
+                            format!("Error resolving target {}: {}", original_target, e),
+                        );
+                        continue;
+                    }
+                };
                 // DNS resolver returns comma-separated IPs, split them
                 let resolved_ips: Vec<&str> = ips_str.split(", ").collect();
 
-                if resolved_ips.len() > 0 {
+                if !resolved_ips.is_empty() {
                     // Single IP resolution - use original target name
                     let ip = resolved_ips[0].trim();
                     if valid_ip(ip) {
                         targets.push(ip.to_string());
-                        target_mapping.insert(ip.to_string(), original_target.to_string());
+                        target_mapping.push((ip.to_string(), original_target.to_string()));
                     }
                 }
             }
@@ -269,154 +488,344 @@ impl TCPScanner {
             println!("Resolved targets: {:?}", targets);
         }
 
-        // spawn processes to conduct syn scan with global thread limiting
-        let mut handles = vec![];
-
         // Create global semaphore to limit concurrent connections across all targets and ports
         let global_semaphore = Arc::new(Semaphore::new(threads as usize));
+        let port_list = parse_ports(ports);
 
-        for target in &targets {
-            let target_clone = target.clone();
-            let ports_clone = ports.clone();
-            let sem_clone = global_semaphore.clone();
-
-            let config_clone = self.config.clone();
-            let handle = tokio::spawn(async move {
-                let result = Self::syn_scan(
-                    &target_clone,
-                    &ports_clone,
-                    timeout,
-                    sem_clone,
-                    &config_clone,
-                )
-                .await;
-                (target_clone, result)
+        // One task per host collects its results, the probes of every host are sent by a
+        // single dispatcher so they can be interleaved
+        let mut handles = vec![];
+        let mut probes = vec![];
+
+        for (target, display_target) in target_mapping {
+            // Hosts that were done before the scan was interrupted are not probed again
+            let resume = self.resume.as_deref();
+            if let Some(host) = resume
+                .and_then(|resume| resume.finished_host(&display_target, &target, Protocol::Tcp))
+            {
+                handles.push(HostTask::Resumed(host.clone()));
+                probes.push(None);
+                continue;
+            }
+            let done = resume
+                .map(|resume| resume.done_ports(&display_target, &target, Protocol::Tcp))
+                .unwrap_or_default();
+            let done_ports: HashSet<u16> = done
+                .iter()
+                .filter_map(|result| result.port.parse().ok())
+                .collect();
+            let remaining = port_list
+                .iter()
+                .filter(|port| !done_ports.contains(port))
+                .count();
+
+            let (sender, receiver) = mpsc::unbounded_channel();
+            handles.push(HostTask::Running {
+                address: target.clone(),
+                display_target: display_target.clone(),
+                handle: tokio::spawn(Self::collect_results(receiver, done)),
             });
-            handles.push(handle);
+
+            probes.push(Some(HostProbes {
+                target: Arc::new(ProbeTarget {
+                    progress: HostProgress::new(&display_target, &target, Protocol::Tcp, remaining),
+                    address: target,
+                    display_target,
+                    timing: HostTiming::new(&self.config, self.rate.clone()),
+                    host_up: AtomicBool::new(false),
+                }),
+                done_ports,
+                remaining,
+                // Hosts without ports left are done right away
+                results: (remaining > 0).then_some(sender),
+            }));
         }
 
-        // Collect and display results
-        let output_handler = OutputHandler::new();
+        let order = ProbeOrder::new(
+            probes.len(),
+            port_list.len(),
+            self.config.seed.filter(|_| !self.config.ordered),
+        );
+        tokio::spawn(Self::dispatch(
+            probes,
+            port_list,
+            order,
+            global_semaphore,
+            self.rate.clone(),
+            self.observers.clone(),
+            self.cancel.clone(),
+        ));
+
+        // Collect results
+        let mut hosts = Vec::new();
 
         for handle in handles {
-            if let Ok((target, result)) = handle.await {
-                match result {
-                    // if scan is ok, output and run scripts
-                    Ok(scan_result) => {
-                        // Convert scan results to HashMap format for OutputHandler
-                        let mut ports_map = HashMap::new();
-
-                        // Process all port results in a single unified approach
-                        let all_ports = scan_result
-                            .open_ports
-                            .iter()
-                            .chain(scan_result.closed_ports.iter())
-                            .chain(scan_result.filtered_ports.iter());
-
-                        for port_result in all_ports {
-                            ports_map.insert(port_result.port.clone(), port_result.state.clone());
+            let (target, display_target, handle) = match handle {
+                HostTask::Running {
+                    address,
+                    display_target,
+                    handle,
+                } => (address, display_target, handle),
+                HostTask::Resumed(host) => {
+                    hosts.push(host);
+                    continue;
+                }
+            };
+            let result = match handle.await {
+                Ok(result) => result,
+                Err(e) => {
+                    self.observers.error(
+                        &display_target,
+                        &target,
+                        format!("Error scanning target {}: {}", target, e),
+                    );
+                    continue;
+                }
+            };
+            match result {
+                // if scan is ok, run scripts
+                Ok(scan_result) => {
+                    // Process all port results in a single unified approach
+                    let all_ports = scan_result
+                        .open_ports
+                        .iter()
+                        .chain(scan_result.closed_ports.iter())
+                        .chain(scan_result.filtered_ports.iter());
+
+                    let mut port_reports = Vec::new();
+                    for port_result in all_ports {
+                        if let Ok(port) = port_result.port.parse::<u16>() {
+                            port_reports.push(PortReport {
+                                protocol: Protocol::Tcp,
+                                port,
+                                state: port_result.state,
+                                reason: port_result.reason,
+                                attempts: port_result.attempts,
+                                service: Some(ServiceInfo::from_port_table(&port_result.port)),
+                                scripts: Vec::new(),
+                            });
                         }
+                    }
 
-                        // Display results using OutputHandler
-                        if !ports_map.is_empty() {
-                            // Check if JSON output is requested
-                            if let Some(json_file) = json_output {
-                                // Output to JSON file
-                                if let Err(e) = output_handler.out_json(
-                                    ports_map,
-                                    "TCP".to_string(),
-                                    json_file,
-                                    &target,
-                                ) {
-                                    eprintln!("Error writing JSON output: {}", e);
-                                }
-                            } else {
-                                // Normal table output - use original target name if available
-                                output_handler.out_results_with_ports_info(ports_map, "TCP".to_string());
-                            }
-                        } else {
-                            if json_output.is_none() {
-                                println!("\nTarget {}: No ports to display", target);
-                            }
-                        }
+                    // Hosts the scan never got to are left out of a cancelled scan
+                    if port_reports.is_empty() && self.cancel.is_cancelled() {
+                        continue;
+                    }
 
-                        // Execute Lua scripts if specified
-                        if let Some(lua_script) = &self.config.lua_script {
-                            let display_target = target_mapping.get(&target).unwrap_or(&target);
-                            self.execute_lua_scripts(
+                    let mut host = HostReport::new(&display_target, &target);
+                    // Script and web requests are paced like the probes of the host
+                    let timing = Arc::new(HostTiming::new(&self.config, self.rate.clone()));
+
+                    if let Some(prober) = &self.prober
+                        && !self.cancel.is_cancelled()
+                    {
+                        prober
+                            .detect_services(
+                                &display_target,
+                                &target,
+                                &mut port_reports,
+                                &timing,
+                                &self.observers,
+                            )
+                            .await;
+                    }
+
+                    // Execute Lua scripts if specified, port scripts are kept with their port
+                    if let Some(lua_script) = &self.config.lua_script
+                        && !self.cancel.is_cancelled()
+                    {
+                        let script_results = self
+                            .execute_lua_scripts(
                                 lua_script,
-                                display_target,
+                                &display_target,
+                                &target,
                                 &scan_result,
-                                &output_handler,
+                                &timing,
                             )
                             .await;
+                        for script_result in script_results {
+                            match port_reports
+                                .iter_mut()
+                                .find(|port| Some(port.port) == script_result.port)
+                            {
+                                Some(port) => port.scripts.push(script_result),
+                                None => host.scripts.push(script_result),
+                            }
                         }
                     }
-                    Err(e) => {
-                        eprintln!("Error scanning target {}: {}", target, e);
+
+                    // Brute-force content on web services if requested
+                    if self.config.web_enum && !self.cancel.is_cancelled() {
+                        host.web = self
+                            .execute_web_enum(&display_target, &target, &scan_result, &timing)
+                            .await;
                     }
+
+                    self.observers.emit(ScanEvent::HostFinished {
+                        target: display_target.clone(),
+                        address: target.clone(),
+                        protocol: Protocol::Tcp,
+                        open_ports: scan_result.open_ports.len(),
+                    });
+
+                    host.add_ports(port_reports);
+                    hosts.push(host);
                 }
+                Err(e) => self.observers.error(
+                    &display_target,
+                    &target,
+                    format!("Error scanning target {}: {}", target, e),
+                ),
             }
         }
 
-        Ok(())
+        Ok(hosts)
     }
 
-    /// Execute Lua scripts against a target and its open ports
+    /// Execute the selected Lua scripts against a target and its open ports
     async fn execute_lua_scripts(
         &self,
         lua_script: &str,
         display_target: &str,
+        address: &str,
         scan_result: &SynScanResult,
-        output_handler: &OutputHandler,
-    ) {
-        println!("\nExecuting Lua Script: {}", lua_script);
-        println!("------------------------------------------------------------");
-
-        match LuaScriptRunner::new() {
-            Ok(script_runner) => {
-                // Execute script against the host
-                match script_runner
-                    .run_script(lua_script, display_target, None)
-                    .await
-                {
-                    Ok(script_result) => {
-                        output_handler.out_script_result(&script_result);
-                    }
-                    Err(e) => {
-                        eprintln!("Error executing script '{}': {}", lua_script, e);
-                    }
-                }
+        timing: &Arc<HostTiming>,
+    ) -> Vec<ScriptResult> {
+        let mut script_results = Vec::new();
+        let emit = |script_result: &ScriptResult| {
+            self.observers.emit(ScanEvent::ScriptResult {
+                target: display_target.to_string(),
+                address: address.to_string(),
+                result: script_result.clone(),
+            });
+        };
 
-                // Also execute scripts for each open port
-                for port_result in &scan_result.open_ports {
-                    if let Ok(port_num) = port_result.port.parse::<u16>() {
-                        match script_runner
-                            .run_script(lua_script, display_target, Some(port_num))
-                            .await
-                        {
-                            Ok(script_result) => {
-                                if script_result.success
-                                    && (!script_result.output.is_empty()
-                                        || !script_result.data.is_empty())
-                                {
-                                    println!("\nPort {} Script Results:", port_num);
-                                    output_handler.out_script_result(&script_result);
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!(
-                                    "Error executing script '{}' on port {}: {}",
-                                    lua_script, port_num, e
-                                );
-                            }
-                        }
-                    }
+        let mut script_runner = match LuaScriptRunner::new() {
+            Ok(script_runner) => script_runner,
+            Err(e) => {
+                self.observers.error(
+                    display_target,
+                    address,
+                    format!("Error initializing Lua script runner: {}", e),
+                );
+                return script_results;
+            }
+        };
+        script_runner.set_scripts_dir(&self.config.scripts_dir);
+        script_runner.set_script_args(self.config.script_args.clone());
+        script_runner.set_log_to_stderr(self.config.streams_to_stdout());
+        script_runner.set_quiet(!self.interactive);
+        script_runner.set_cancellation_token(self.cancel.clone());
+        script_runner.set_timing(timing.clone());
+
+        let scripts = match script_runner.select_scripts(lua_script) {
+            Ok(scripts) => scripts,
+            Err(e) => {
+                self.observers.error(
+                    display_target,
+                    address,
+                    format!("Error selecting scripts '{}': {}", lua_script, e),
+                );
+                return script_results;
+            }
+        };
+
+        for script in &scripts {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
+            // Execute script against the host
+            let script_result = script_runner
+                .run_script_file(&script.path, display_target, None)
+                .await
+                .unwrap_or_else(|e| ScriptResult::failed(&script.name, display_target, None, e));
+            emit(&script_result);
+            script_results.push(script_result);
+
+            // Also execute scripts for each open port
+            for port_result in &scan_result.open_ports {
+                if self.cancel.is_cancelled() {
+                    break;
+                }
+                if let Ok(port_num) = port_result.port.parse::<u16>() {
+                    let script_result = script_runner
+                        .run_script_file(&script.path, display_target, Some(port_num))
+                        .await
+                        .unwrap_or_else(|e| {
+                            ScriptResult::failed(&script.name, display_target, Some(port_num), e)
+                        });
+                    emit(&script_result);
+                    script_results.push(script_result);
                 }
             }
+        }
+
+        script_results
+    }
+
+    /// Run web content discovery against every open port that answers HTTP(S)
+    async fn execute_web_enum(
+        &self,
+        display_target: &str,
+        target: &str,
+        scan_result: &SynScanResult,
+        timing: &Arc<HostTiming>,
+    ) -> Vec<WebEnumResult> {
+        let mut web_results = Vec::new();
+
+        let words = match web::load_wordlists(&self.config.wordlists) {
+            Ok(words) => words,
             Err(e) => {
-                eprintln!("Error initializing Lua script runner: {}", e);
+                self.observers.error(
+                    display_target,
+                    target,
+                    format!("Error loading wordlists: {}", e),
+                );
+                return web_results;
+            }
+        };
+
+        let options = WebEnumOptions {
+            extensions: self.config.web_extensions.clone(),
+            status_codes: self.config.web_status_codes.clone(),
+            exclude_sizes: self.config.web_exclude_sizes.clone(),
+            threads: self.config.web_threads as usize,
+            timeout: Duration::from_millis(self.config.timeout),
+        };
+
+        for port_result in &scan_result.open_ports {
+            if self.cancel.is_cancelled() {
+                break;
             }
+            let Ok(port) = port_result.port.parse::<u16>() else {
+                continue;
+            };
+            let service = OutputHandler::get_service_for_port(&port_result.port);
+            let Some(scheme) =
+                web::detect_scheme(target, port, service, options.timeout, timing).await
+            else {
+                continue;
+            };
+
+            let result = web::discover(
+                target,
+                port,
+                &scheme,
+                &words,
+                &options,
+                timing,
+                &self.cancel,
+            )
+            .await;
+            self.observers.emit(ScanEvent::WebResult {
+                target: display_target.to_string(),
+                address: target.to_string(),
+                result: result.clone(),
+            });
+            web_results.push(result);
         }
+
+        web_results
     }
 }
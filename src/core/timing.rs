@@ -0,0 +1,334 @@
+use super::rate::RateLimiter;
+use crate::args::Config;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Nmap-style timing template, from `paranoid` (T0) to `insane` (T5)
+///
+/// A template sets the number of probes in flight, the initial, minimum and maximum probe
+/// timeouts and the number of retries for probes that get no answer:
+///
+/// | Template       | Parallelism | Initial | Min    | Max     | Retries |
+/// |----------------|-------------|---------|--------|---------|---------|
+/// | T0 paranoid    | 1           | 5000ms  | 100ms  | 10000ms | 10      |
+/// | T1 sneaky      | 1           | 3000ms  | 100ms  | 10000ms | 10      |
+/// | T2 polite      | 10          | 2000ms  | 100ms  | 10000ms | 5       |
+/// | T3 normal      | CPU count   | 2000ms  | 100ms  | 10000ms | 2       |
+/// | T4 aggressive  | 500         | 500ms   | 100ms  | 1250ms  | 1       |
+/// | T5 insane      | 1000        | 250ms   | 50ms   | 300ms   | 0       |
+///
+/// `normal` matches the defaults of [`Config`]. Config files may give the template by number or
+/// by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimingTemplate {
+    Paranoid,
+    Sneaky,
+    Polite,
+    Normal,
+    Aggressive,
+    Insane,
+}
+
+impl TimingTemplate {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimingTemplate::Paranoid => "paranoid",
+            TimingTemplate::Sneaky => "sneaky",
+            TimingTemplate::Polite => "polite",
+            TimingTemplate::Normal => "normal",
+            TimingTemplate::Aggressive => "aggressive",
+            TimingTemplate::Insane => "insane",
+        }
+    }
+
+    /// Set parallelism, timeouts and retries of `config` to the template's values
+    ///
+    /// Returns the keys of the settings the template changed.
+    pub fn apply(&self, config: &mut Config) -> Vec<&'static str> {
+        self.apply_unless(config, |_| false)
+    }
+
+    /// Like [`TimingTemplate::apply`], but keep the settings whose key `keep` returns true for
+    pub fn apply_unless(
+        &self,
+        config: &mut Config,
+        keep: impl Fn(&str) -> bool,
+    ) -> Vec<&'static str> {
+        let (threads, timeout, min_rtt_timeout, max_rtt_timeout, max_retries) = match self {
+            TimingTemplate::Paranoid => (1, 5000, 100, 10000, 10),
+            TimingTemplate::Sneaky => (1, 3000, 100, 10000, 10),
+            TimingTemplate::Polite => (10, 2000, 100, 10000, 5),
+            TimingTemplate::Normal => (num_cpus::get() as u64, 2000, 100, 10000, 2),
+            TimingTemplate::Aggressive => (500, 500, 100, 1250, 1),
+            TimingTemplate::Insane => (1000, 250, 50, 300, 0),
+        };
+        config.timing = Some(*self);
+        let mut changed = Vec::new();
+        if !keep("threads") {
+            config.threads = threads;
+            changed.push("threads");
+        }
+        if !keep("timeout") {
+            config.timeout = timeout;
+            changed.push("timeout");
+        }
+        if !keep("min_rtt_timeout") {
+            config.min_rtt_timeout = min_rtt_timeout;
+            changed.push("min_rtt_timeout");
+        }
+        if !keep("max_rtt_timeout") {
+            config.max_rtt_timeout = max_rtt_timeout;
+            changed.push("max_rtt_timeout");
+        }
+        if !keep("max_retries") {
+            config.max_retries = max_retries;
+            changed.push("max_retries");
+        }
+        changed
+    }
+}
+
+impl fmt::Display for TimingTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TimingTemplate {
+    type Err = String;
+
+    /// Accepts the template number (`0` to `5`) or its name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "0" | "paranoid" => Ok(TimingTemplate::Paranoid),
+            "1" | "sneaky" => Ok(TimingTemplate::Sneaky),
+            "2" | "polite" => Ok(TimingTemplate::Polite),
+            "3" | "normal" => Ok(TimingTemplate::Normal),
+            "4" | "aggressive" => Ok(TimingTemplate::Aggressive),
+            "5" | "insane" => Ok(TimingTemplate::Insane),
+            _ => Err(format!(
+                "unknown timing template '{}', expected 0-5 or paranoid, sneaky, polite, normal, aggressive, insane",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TimingTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TemplateVisitor;
+
+        impl Visitor<'_> for TemplateVisitor {
+            type Value = TimingTemplate;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a timing template, 0-5 or its name")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<TimingTemplate, E> {
+                value.to_string().parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<TimingTemplate, E> {
+                value.to_string().parse().map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<TimingTemplate, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(TemplateVisitor)
+    }
+}
+
+/// Probe pacing, timeout and retries of one host, adapted to the round trip times measured
+///
+/// The timeout follows RFC 6298: the smoothed round trip time plus four times its variance,
+/// kept between the minimum and maximum RTT timeouts. Until the first answer arrives the
+/// initial timeout is used.
+///
+/// Probes wait for the per-host rate limit and then for the scan-wide one. A probe that is only
+/// answered after being sent again means an earlier one was dropped, by ICMP rate limiting on
+/// the host or by a congested link, so both rates back off and grow back while probes are
+/// answered the first time. Probes of a host that answered before which get no answer at all
+/// count as dropped too.
+pub struct HostTiming {
+    min_timeout: Duration,
+    max_timeout: Duration,
+    max_retries: u32,
+    /// Some probe was answered, so probes without an answer may have been dropped
+    answered: AtomicBool,
+    rtt: Mutex<RttEstimate>,
+    host_rate: RateLimiter,
+    scan_rate: Arc<RateLimiter>,
+}
+
+struct RttEstimate {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    timeout: Duration,
+}
+
+impl HostTiming {
+    /// Timing for a new host, sharing the scan-wide rate limit `scan_rate`
+    pub fn new(config: &Config, scan_rate: Arc<RateLimiter>) -> HostTiming {
+        let min_timeout = Duration::from_millis(config.min_rtt_timeout);
+        // A maximum below the minimum is rejected when the scanner is built
+        let max_timeout = Duration::from_millis(config.max_rtt_timeout).max(min_timeout);
+        HostTiming {
+            min_timeout,
+            max_timeout,
+            max_retries: config.max_retries,
+            answered: AtomicBool::new(false),
+            rtt: Mutex::new(RttEstimate {
+                srtt: None,
+                rttvar: Duration::ZERO,
+                timeout: Duration::from_millis(config.timeout).clamp(min_timeout, max_timeout),
+            }),
+            host_rate: RateLimiter::new(None, config.max_host_rate),
+            scan_rate,
+        }
+    }
+
+    /// Wait until the next probe to this host may be sent
+    pub async fn wait_turn(&self) {
+        self.host_rate.acquire().await;
+        self.scan_rate.acquire().await;
+    }
+
+    /// How long to wait for an answer to a probe sent again `retries` times
+    ///
+    /// Every retry doubles the timeout, up to the maximum RTT timeout, so a slow answer is not
+    /// mistaken for a lost one again.
+    pub fn timeout(&self, retries: u32) -> Duration {
+        let timeout = self.estimate().timeout;
+        timeout
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max_timeout.max(timeout))
+    }
+
+    /// How often a probe without an answer is sent again
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Record a probe answered after `rtt`, on its first attempt or after `retries` retries
+    pub fn record_answer(&self, rtt: Duration, retries: u32) {
+        self.answered.store(true, Ordering::Relaxed);
+        if retries > 0 {
+            self.host_rate.backoff();
+            self.scan_rate.backoff();
+        } else {
+            self.host_rate.recover();
+            self.scan_rate.recover();
+        }
+
+        let mut estimate = self.estimate();
+        let (srtt, rttvar) = match estimate.srtt {
+            None => (rtt, rtt / 2),
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                (srtt * 7 / 8 + rtt / 8, estimate.rttvar * 3 / 4 + delta / 4)
+            }
+        };
+        estimate.srtt = Some(srtt);
+        estimate.rttvar = rttvar;
+        estimate.timeout = (srtt + rttvar * 4).clamp(self.min_timeout, self.max_timeout);
+    }
+
+    /// Record a probe that got no answer after every retry
+    ///
+    /// Hosts that never answered are more likely down or filtered than dropping probes, so
+    /// their rates are left alone.
+    pub fn record_timeout(&self) {
+        if self.answered.load(Ordering::Relaxed) {
+            self.host_rate.backoff();
+            self.scan_rate.backoff();
+        }
+    }
+
+    fn estimate(&self) -> std::sync::MutexGuard<'_, RttEstimate> {
+        match self.rtt.lock() {
+            Ok(estimate) => estimate,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(timeout: u64, min: u64, max: u64) -> HostTiming {
+        let config = Config {
+            timeout,
+            min_rtt_timeout: min,
+            max_rtt_timeout: max,
+            ..Config::default()
+        };
+        HostTiming::new(&config, Arc::new(RateLimiter::default()))
+    }
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn initial_timeout_is_clamped() {
+        assert_eq!(timing(5000, 100, 1250).timeout(0), millis(1250));
+        assert_eq!(timing(10, 100, 1250).timeout(0), millis(100));
+        assert_eq!(timing(500, 100, 1250).timeout(0), millis(500));
+        // A maximum below the minimum is raised to it
+        assert_eq!(timing(500, 100, 50).timeout(0), millis(100));
+    }
+
+    #[test]
+    fn measured_timeout_is_clamped() {
+        let fast = timing(1000, 100, 1250);
+        fast.record_answer(millis(10), 0);
+        assert_eq!(fast.timeout(0), millis(100));
+
+        let slow = timing(1000, 100, 1250);
+        slow.record_answer(millis(200), 0);
+        // 200ms plus four times half of it
+        assert_eq!(slow.timeout(0), millis(600));
+        slow.record_answer(millis(2000), 0);
+        assert_eq!(slow.timeout(0), millis(1250));
+    }
+
+    #[test]
+    fn retries_double_the_timeout_up_to_the_maximum() {
+        let timing = timing(300, 100, 1000);
+        let timeouts: Vec<_> = (0..4).map(|retries| timing.timeout(retries)).collect();
+        assert_eq!(
+            timeouts,
+            [millis(300), millis(600), millis(1000), millis(1000)]
+        );
+        assert_eq!(timing.timeout(u32::MAX), millis(1000));
+    }
+
+    #[test]
+    fn timeouts_back_off_only_after_an_answer() {
+        let scan_rate = Arc::new(RateLimiter::new(None, Some(100.0)));
+        let config = Config {
+            max_host_rate: Some(100.0),
+            ..Config::default()
+        };
+        let timing = HostTiming::new(&config, scan_rate.clone());
+        timing.record_timeout();
+        assert_eq!(timing.host_rate.rate(), Some(100.0));
+        assert_eq!(scan_rate.rate(), Some(100.0));
+
+        timing.record_answer(millis(10), 0);
+        timing.record_timeout();
+        assert_eq!(timing.host_rate.rate(), Some(50.0));
+        assert_eq!(scan_rate.rate(), Some(50.0));
+    }
+}
@@ -1,132 +1,195 @@
-use crate::args::{Config, get_config};
-use crate::core::PortState;
-use crate::output::OutputHandler;
-use crate::utils::valid_ip;
+use crate::args::Config;
+use crate::checkpoint::Checkpoint;
+use crate::core::cancel::CancellationToken;
+use crate::core::order::ProbeOrder;
+use crate::core::probe::Prober;
+use crate::core::rate::RateLimiter;
+use crate::core::timing::HostTiming;
+use crate::core::{PortReason, PortResult, PortState, ServiceInfo};
 use crate::dns::DNSResolver;
-use std::collections::HashMap;
+use crate::events::{HostProgress, Observers, ScanEvent};
+use crate::report::{HostReport, PortReport, Protocol};
+use crate::utils::{parse_ports, valid_ip};
+use std::collections::HashSet;
 use std::error::Error;
+use std::io::ErrorKind;
 use std::net::IpAddr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+use tokio::io::Interest;
 use tokio::net::UdpSocket;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::timeout;
-use indicatif::{ProgressBar, ProgressStyle};
 
 pub struct UDPScanner {
     pub config: Config,
     pub dns: DNSResolver,
+    /// Receivers of progress and result events
+    pub observers: Observers,
+    /// Stops the scan early, results gathered so far are still returned
+    pub cancel: CancellationToken,
+    /// Results of an earlier run of this scan, done hosts and ports are not probed again
+    pub resume: Option<Arc<Checkpoint>>,
+    /// Rate limit shared by every host and protocol of the scan
+    pub rate: Arc<RateLimiter>,
+    /// Detects the versions of services on open ports, when `service_version` is set
+    pub prober: Option<Arc<Prober>>,
 }
 
-#[derive(Debug, Clone)]
-pub enum UdpPortState {
-    Open,
-    Closed,
+/// A host being probed, shared by its probes
+struct ProbeTarget {
+    ip_addr: IpAddr,
+    target: String,
+    address: String,
+    timing: HostTiming,
+    host_up: AtomicBool,
+    progress: HostProgress,
 }
 
-pub type UDPScanResult = HashMap<u16, UdpPortState>;
+/// A host being probed and the results gathered so far
+struct UdpHost {
+    probe: Arc<ProbeTarget>,
+    /// Ports probed before the scan was interrupted
+    done_ports: HashSet<u16>,
+    results: Vec<PortResult>,
+}
 
-impl UDPScanner {
-    pub fn new() -> UDPScanner {
-        let config = get_config();
-        UDPScanner { config, dns: DNSResolver::new() }
-    }
+/// A host of the scan, either being probed or taken as it is from a checkpoint
+enum HostTask {
+    Probing(Box<UdpHost>),
+    Resumed(HostReport),
+}
 
-    // Parse port ranges and individual ports (similar to TCP scanner)
-    fn parse_ports(ports_str: &str) -> Vec<u16> {
-        let mut ports = Vec::new();
-
-        for part in ports_str.split(',') {
-            let part = part.trim();
-            if part.contains('-') {
-                // Handle port range
-                let range_parts: Vec<&str> = part.split('-').collect();
-                if range_parts.len() == 2 {
-                    if let (Ok(start), Ok(end)) =
-                        (range_parts[0].parse::<u16>(), range_parts[1].parse::<u16>())
-                    {
-                        for port in start..=end {
-                            ports.push(port);
-                        }
-                    }
-                }
-            } else {
-                // Handle individual port
-                if let Ok(port) = part.parse::<u16>() {
-                    ports.push(port);
-                }
-            }
+impl UDPScanner {
+    pub fn new(config: Config) -> UDPScanner {
+        UDPScanner {
+            config,
+            dns: DNSResolver::new(),
+            observers: Observers::default(),
+            cancel: CancellationToken::new(),
+            resume: None,
+            rate: Arc::new(RateLimiter::default()),
+            prober: None,
         }
-
-        ports
     }
 
-    pub async fn fire_and_forget(&self, target: IpAddr, ports: Vec<u16>) -> Result<UDPScanResult, Box<dyn Error>> {
-        let mut results = UDPScanResult::new();
-        
-        // Print scan message
-        println!("\x1b[36mrunning UDP scan\x1b[0m");
-        
-        // Create progress bar
-        let pb = ProgressBar::new(ports.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{percent:>3}%|{bar:25.cyan/blue}| {pos}/{len} [{elapsed_precise}<{eta_precise}, {per_sec}]")
-                .unwrap()
-                .progress_chars("█▉▊▋▌▍▎▏ "),
+    /// Probe one port and report the result, `None` if the scan was cancelled first
+    async fn probe_port(
+        host: Arc<ProbeTarget>,
+        port: u16,
+        _permit: Option<OwnedSemaphorePermit>,
+        observers: Observers,
+        cancel: CancellationToken,
+    ) -> Option<PortResult> {
+        let (state, reason, attempts) =
+            Self::scan_udp_port(host.ip_addr, port, &host.timing, &cancel).await?;
+        let result = PortResult {
+            port: port.to_string(),
+            state,
+            reason,
+            attempts,
+        };
+        observers.port_result(
+            &host.target,
+            &host.address,
+            Protocol::Udp,
+            &result,
+            &host.host_up,
         );
-        
-        for port in ports {
-            let port_state = self.scan_udp_port(target, port).await;
-            results.insert(port, port_state);
-            pb.inc(1);
-        }
-        
-        pb.finish_and_clear();
-        
-        Ok(results)
+        host.progress.advance(&observers);
+        Some(result)
     }
 
-    async fn scan_udp_port(&self, target: IpAddr, port: u16) -> UdpPortState {
-        let timeout_duration = Duration::from_millis(self.config.timeout);
-        
+    /// Probe one port, with the number of probes sent
+    ///
+    /// `None` if the scan was cancelled before an answer arrived.
+    async fn scan_udp_port(
+        target: IpAddr,
+        port: u16,
+        timing: &HostTiming,
+        cancel: &CancellationToken,
+    ) -> Option<(PortState, PortReason, u32)> {
         // Create a UDP socket
         let socket = match UdpSocket::bind("0.0.0.0:0").await {
             Ok(socket) => socket,
-            Err(_) => return UdpPortState::Closed,
+            Err(_) => return Some((PortState::Closed, PortReason::Error, 0)),
         };
 
         let target_addr = format!("{}:{}", target, port);
-        
+
         // Connect to target (this is just for convenience, doesn't actually connect)
         if socket.connect(&target_addr).await.is_err() {
-            return UdpPortState::Closed;
+            return Some((PortState::Closed, PortReason::Error, 0));
         }
 
-        // Send a UDP probe packet
-        let probe_data = self.get_probe_data_for_port(port);
-        if socket.send(&probe_data).await.is_err() {
-            return UdpPortState::Closed;
-        }
+        // Probes without an answer are sent again with a doubled timeout, answers adapt it
+        let probe_data = Self::get_probe_data_for_port(port);
+        let mut retries = 0;
+        loop {
+            tokio::select! {
+                _ = timing.wait_turn() => {}
+                _ = cancel.cancelled() => return None,
+            }
 
-        // Try to receive a response with timeout
-        let mut buffer = [0u8; 1024];
-        match timeout(timeout_duration, socket.recv(&mut buffer)).await {
-            Ok(Ok(_)) => {
-                // Received a response - port is open
-                UdpPortState::Open
+            // Send a UDP probe packet
+            let started = Instant::now();
+            if let Err(e) = socket.send(&probe_data).await {
+                // An ICMP port unreachable for an earlier probe can be reported on the next send,
+                // which is then not sent
+                if e.kind() == ErrorKind::ConnectionRefused {
+                    return Some((PortState::Closed, PortReason::PortUnreach, retries));
+                }
+                return Some((PortState::Closed, PortReason::Error, retries));
             }
-            Ok(Err(_)) => {
-                // Error receiving - port likely closed
-                UdpPortState::Closed
+
+            // Try to receive a response with timeout, an ICMP error for the probe only wakes
+            // the socket's error readiness and not a pending receive
+            let mut buffer = [0u8; 1024];
+            let answer = async {
+                tokio::select! {
+                    received = socket.recv(&mut buffer) => received.map(|_| ()),
+                    ready = socket.ready(Interest::ERROR) => match ready {
+                        Ok(_) => Err(socket
+                            .take_error()
+                            .ok()
+                            .flatten()
+                            .unwrap_or_else(|| ErrorKind::ConnectionRefused.into())),
+                        Err(e) => Err(e),
+                    },
+                }
+            };
+            let outcome = tokio::select! {
+                outcome = timeout(timing.timeout(retries), answer) => outcome,
+                _ = cancel.drained() => return None,
+            };
+            let (state, reason) = match outcome {
+                // Received a response - port is open
+                Ok(Ok(_)) => (PortState::Open, PortReason::UdpResponse),
+                // Error receiving - ICMP port unreachable was reported for the port
+                Ok(Err(_)) => (PortState::Closed, PortReason::PortUnreach),
+                // Timeout - the port is open with a silent service, or filtered
+                Err(_) => (PortState::OpenFiltered, PortReason::NoResponse),
+            };
+
+            if reason.is_reply() {
+                timing.record_answer(started.elapsed(), retries);
             }
-            Err(_) => {
-                // Timeout - port is likely filtered (open but no response)
-                // For UDP, this is typically considered open|filtered
-                UdpPortState::Open
+            if reason != PortReason::NoResponse
+                || retries >= timing.max_retries()
+                || cancel.is_cancelled()
+            {
+                if reason == PortReason::NoResponse && !cancel.is_cancelled() {
+                    timing.record_timeout();
+                }
+                return Some((state, reason, retries + 1));
             }
+            retries += 1;
         }
     }
 
-    fn get_probe_data_for_port(&self, port: u16) -> Vec<u8> {
+    fn get_probe_data_for_port(port: u16) -> Vec<u8> {
         match port {
             53 => {
                 // DNS query for "google.com"
@@ -157,19 +220,18 @@ impl UDPScanner {
                     0x02, 0x01, 0x00, // error-index: 0
                     0x30, 0x0e, // variable-bindings
                     0x30, 0x0c, // VarBind
-                    0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, // OID: 1.3.6.1.2.1.1.1.0
+                    0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01,
+                    0x00, // OID: 1.3.6.1.2.1.1.1.0
                     0x05, 0x00, // NULL
                 ]
             }
             123 => {
                 // NTP request
                 vec![
-                    0x1b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x1b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                 ]
             }
             _ => {
@@ -179,68 +241,211 @@ impl UDPScanner {
         }
     }
 
-    async fn udp_scan(&self, targets: Vec<String>, ports: Vec<u16>) -> Result<UDPScanResult, Box<dyn Error>> {
-        let mut combined_results = UDPScanResult::new();
-        
+    async fn udp_scan(
+        &self,
+        targets: Vec<String>,
+        ports: Vec<u16>,
+    ) -> Result<Vec<HostReport>, Box<dyn Error>> {
+        // Every target is resolved first so probes can be interleaved across hosts
+        let mut tasks = Vec::new();
+
         for target in targets {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
             // Check if target is already an IP address
             let ip_addr = if valid_ip(&target) {
                 target.parse::<IpAddr>()?
             } else {
-                // Resolve domain name to IP
-                let resolved_ip = self.dns.resolve_to_ip(&target).await?;
-                // Take the first IP if multiple are returned
-                let first_ip = resolved_ip.split(", ").next().unwrap_or(&resolved_ip);
-                first_ip.parse::<IpAddr>()?
+                // A target that does not resolve is reported and the others are still scanned
+                let resolved = match self.dns.resolve_to_ip(&target).await {
+                    Ok(resolved_ip) => {
+                        // Take the first IP if multiple are returned
+                        let first_ip = resolved_ip.split(", ").next().unwrap_or(&resolved_ip);
+                        first_ip.parse::<IpAddr>().map_err(|e| e.to_string())
+                    }
+                    Err(e) => Err(e.to_string()),
+                };
+                match resolved {
+                    Ok(ip_addr) => ip_addr,
+                    Err(e) => {
+                        self.observers.error(
+                            &target,
+                            "",
+                            format!("Error resolving target {}: {}", target, e),
+                        );
+                        continue;
+                    }
+                }
+            };
+
+            let address = ip_addr.to_string();
+
+            // Hosts that were done before the scan was interrupted are not probed again
+            let resume = self.resume.as_deref();
+            if let Some(host) =
+                resume.and_then(|resume| resume.finished_host(&target, &address, Protocol::Udp))
+            {
+                tasks.push(HostTask::Resumed(host.clone()));
+                continue;
+            }
+            let results = resume
+                .map(|resume| resume.done_ports(&target, &address, Protocol::Udp))
+                .unwrap_or_default();
+            let done_ports: HashSet<u16> = results
+                .iter()
+                .filter_map(|result| result.port.parse().ok())
+                .collect();
+            let remaining = ports
+                .iter()
+                .filter(|port| !done_ports.contains(port))
+                .count();
+
+            tasks.push(HostTask::Probing(Box::new(UdpHost {
+                probe: Arc::new(ProbeTarget {
+                    ip_addr,
+                    progress: HostProgress::new(&target, &address, Protocol::Udp, remaining),
+                    timing: HostTiming::new(&self.config, self.rate.clone()),
+                    host_up: AtomicBool::new(false),
+                    target,
+                    address,
+                }),
+                done_ports,
+                results,
+            })));
+        }
+
+        let order = ProbeOrder::new(
+            tasks.len(),
+            ports.len(),
+            self.config.seed.filter(|_| !self.config.ordered),
+        );
+
+        // Probes are sent in the scan order, at most `threads` of them at a time unless more are
+        // needed to keep up the minimum rate, like TCP
+        let semaphore = Arc::new(Semaphore::new(self.config.threads as usize));
+        let mut probes = JoinSet::new();
+        let mut finished = Vec::new();
+        for (host_index, port_index) in order {
+            let HostTask::Probing(host) = &tasks[host_index] else {
+                continue;
+            };
+            let port = ports[port_index];
+            if host.done_ports.contains(&port) {
+                continue;
+            }
+
+            // Queued probes are not sent once the scan is cancelled
+            let permit = tokio::select! {
+                permit = self.rate.permit(&semaphore) => permit,
+                _ = self.cancel.cancelled() => break,
+            };
+            if self.cancel.is_cancelled() {
+                break;
+            }
+
+            let probe = Self::probe_port(
+                host.probe.clone(),
+                port,
+                permit,
+                self.observers.clone(),
+                self.cancel.clone(),
+            );
+            probes.spawn(async move { probe.await.map(|result| (host_index, result)) });
+            while let Some(done) = probes.try_join_next() {
+                finished.push(done?);
+            }
+        }
+        while let Some(done) = probes.join_next().await {
+            finished.push(done?);
+        }
+        for (host_index, result) in finished.into_iter().flatten() {
+            if let HostTask::Probing(host) = &mut tasks[host_index] {
+                host.results.push(result);
+            }
+        }
+
+        let mut hosts = Vec::new();
+        for task in tasks {
+            let (probe, results) = match task {
+                HostTask::Probing(host) => (host.probe, host.results),
+                HostTask::Resumed(host) => {
+                    hosts.push(host);
+                    continue;
+                }
             };
-            
-            // Launch fire_and_forget for this resolved IP
-            let scan_results = self.fire_and_forget(ip_addr, ports.clone()).await?;
-            
-            // Merge results into combined results
-            for (port, state) in scan_results {
-                combined_results.insert(port, state);
+
+            // Hosts the scan never got to are left out of a cancelled scan
+            if results.is_empty() && self.cancel.is_cancelled() {
+                continue;
             }
+
+            let open_ports = results
+                .iter()
+                .filter(|result| result.state == PortState::Open)
+                .count();
+            let mut port_reports: Vec<PortReport> = results
+                .into_iter()
+                .filter_map(|result| {
+                    Some(PortReport {
+                        protocol: Protocol::Udp,
+                        port: result.port.parse().ok()?,
+                        state: result.state,
+                        reason: result.reason,
+                        attempts: result.attempts,
+                        service: Some(ServiceInfo::from_port_table(&result.port)),
+                        scripts: Vec::new(),
+                    })
+                })
+                .collect();
+
+            if let Some(prober) = &self.prober
+                && !self.cancel.is_cancelled()
+            {
+                prober
+                    .detect_services(
+                        &probe.target,
+                        &probe.address,
+                        &mut port_reports,
+                        &probe.timing,
+                        &self.observers,
+                    )
+                    .await;
+            }
+
+            self.observers.emit(ScanEvent::HostFinished {
+                target: probe.target.clone(),
+                address: probe.address.clone(),
+                protocol: Protocol::Udp,
+                open_ports,
+            });
+
+            let mut report = HostReport::new(&probe.target, &probe.address);
+            report.add_ports(port_reports);
+            hosts.push(report);
         }
-        
-        Ok(combined_results)
+
+        Ok(hosts)
     }
 
-    pub async fn exec(&self) -> Result<(), Box<dyn Error>> {
+    /// Execute the UDP scanner
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a report for every scanned host, or an error if the scan fails
+    pub async fn exec(&self) -> Result<Vec<HostReport>, Box<dyn Error>> {
         let targets = &self.config.target;
         let ports_str = &self.config.ports;
-        
+
         // Parse ports from string format
-        let ports = Self::parse_ports(ports_str);
-        
+        let ports = parse_ports(ports_str);
+
         if ports.is_empty() {
-            eprintln!("No valid ports specified for UDP scan");
-            return Ok(());
+            return Err("No valid ports specified for UDP scan".into());
         }
-        
+
         // Perform UDP scan on all targets
-        let results = self.udp_scan(targets.clone(), ports).await?;
-        
-        // Display results
-        if results.is_empty() {
-            println!("No UDP ports found");
-        } else {
-            let output_handler = OutputHandler::new();
-            
-            // Convert UDP results to the format expected by OutputHandler
-            let mut ports_map = HashMap::new();
-            for (port, udp_state) in results {
-                let port_state = match udp_state {
-                    UdpPortState::Open => PortState::Open,
-                    UdpPortState::Closed => PortState::Closed,
-                };
-                ports_map.insert(port.to_string(), port_state);
-            }
-            
-            // Use the OutputHandler to display results
-            output_handler.out_results_with_ports_info(ports_map, "UDP".to_string());
-        }
-        
-        Ok(())
+        self.udp_scan(targets.clone(), ports).await
     }
 }
@@ -0,0 +1,466 @@
+use crate::core::cancel::CancellationToken;
+use crate::core::timing::HostTiming;
+use crate::net::http::{self, HttpRequest, HttpResponse};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fs;
+use std::future;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Settings for web content discovery
+#[derive(Debug, Clone)]
+pub struct WebEnumOptions {
+    /// Extensions appended to every word in addition to the bare word
+    pub extensions: Vec<String>,
+    /// Status codes reported as findings
+    pub status_codes: Vec<u16>,
+    /// Response sizes (in bytes) that are never reported
+    pub exclude_sizes: Vec<usize>,
+    /// Maximum concurrent requests per service
+    pub threads: usize,
+    pub timeout: Duration,
+}
+
+/// A path that answered with one of the reported status codes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebFinding {
+    pub path: String,
+    pub url: String,
+    pub status: u16,
+    pub size: usize,
+    pub redirect: Option<String>,
+}
+
+/// Discovery results for one HTTP(S) service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebEnumResult {
+    pub host: String,
+    pub port: u16,
+    pub scheme: String,
+    /// Whether the service answers non-existent paths with a success (soft-404)
+    pub wildcard: bool,
+    pub requests: usize,
+    pub findings: Vec<WebFinding>,
+}
+
+/// Response to a path that cannot exist, used to recognise soft-404 pages
+#[derive(Debug, Clone)]
+struct Baseline {
+    status: u16,
+    size: usize,
+    words: usize,
+    lines: usize,
+    redirect: Option<String>,
+}
+
+impl Baseline {
+    /// Whether a response to `path` looks like the not-found response
+    fn matches(&self, path: &str, response: &HttpResponse, probe_path: &str) -> bool {
+        if response.status != self.status {
+            return false;
+        }
+
+        // Pages often echo the requested path, so compare with and without it removed.
+        // Dynamic pages (timestamps, counters) change size but rarely their word and
+        // line counts.
+        let size_matches = [body_metrics(response, ""), body_metrics(response, path)]
+            .into_iter()
+            .any(|(size, words, lines)| {
+                size.abs_diff(self.size) <= 2 || (words == self.words && lines == self.lines)
+            });
+
+        let redirect_matches = match (&self.redirect, response.header("location")) {
+            (Some(expected), Some(location)) => location.replace(path, probe_path) == *expected,
+            (None, None) => true,
+            _ => false,
+        };
+
+        size_matches && redirect_matches
+    }
+}
+
+/// Size, word count and line count of a body with echoes of `path` removed
+fn body_metrics(response: &HttpResponse, path: &str) -> (usize, usize, usize) {
+    let body = String::from_utf8_lossy(&response.body);
+    let body = if path.is_empty() {
+        body.to_string()
+    } else {
+        body.replace(path, "")
+    };
+    (
+        body.len(),
+        body.split_whitespace().count(),
+        body.lines().count(),
+    )
+}
+
+/// Load and combine wordlists, skipping comments, blank lines and duplicates
+pub fn load_wordlists(paths: &[String]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+
+    for path in paths {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read wordlist '{}': {}", path, e))?;
+        for line in content.lines() {
+            let word = line.trim().trim_start_matches('/');
+            if word.is_empty() || word.starts_with('#') {
+                continue;
+            }
+            if seen.insert(word.to_string()) {
+                words.push(word.to_string());
+            }
+        }
+    }
+
+    Ok(words)
+}
+
+/// Expand every word with the configured extensions, as the requests need them
+fn build_candidates<'a>(
+    words: &'a [String],
+    extensions: &'a [String],
+) -> impl Iterator<Item = String> + 'a {
+    words.iter().flat_map(move |word| {
+        std::iter::once(word.clone()).chain(
+            extensions
+                .iter()
+                .map(move |extension| format!("{}.{}", word, extension.trim_start_matches('.'))),
+        )
+    })
+}
+
+fn random_token() -> String {
+    format!("{:016x}", RandomState::new().hash_one(std::process::id()))
+}
+
+fn fetch(base_url: &str, path: &str, timeout: Duration) -> Option<HttpResponse> {
+    let mut request = HttpRequest::new("GET", &format!("{}/{}", base_url, path));
+    request.timeout = timeout;
+    http::send(&request).ok()
+}
+
+/// Whether a plain HTTP response says the port only speaks HTTPS
+///
+/// nginx and Apache answer plain requests to a TLS port with a 400 page saying so.
+fn expects_tls(response: &HttpResponse) -> bool {
+    let body = String::from_utf8_lossy(&response.body).to_lowercase();
+    response.status == 400
+        && (body.contains("plain http request was sent to https port")
+            || body.contains("speaking plain http to an ssl-enabled server"))
+}
+
+/// Determine whether an open port speaks HTTP or HTTPS
+///
+/// HTTPS is tried first on ports usually serving it, 443 and 8443 or a `service` name ending
+/// in `https` or `ssl`, and plain HTTP first elsewhere. Requests wait for the rate limits of
+/// `timing`.
+///
+/// # Returns
+///
+/// `Some("http")` or `Some("https")` when the service answered an HTTP request, `None`
+/// otherwise
+pub async fn detect_scheme(
+    host: &str,
+    port: u16,
+    service: &str,
+    timeout: Duration,
+    timing: &HostTiming,
+) -> Option<String> {
+    let tls_first =
+        matches!(port, 443 | 8443) || service.ends_with("https") || service.ends_with("ssl");
+    let schemes = if tls_first {
+        ["https", "http"]
+    } else {
+        ["http", "https"]
+    };
+    for scheme in schemes {
+        let base_url = format!("{}://{}:{}", scheme, url_host(host), port);
+        timing.wait_turn().await;
+        let response = tokio::task::spawn_blocking(move || fetch(&base_url, "", timeout))
+            .await
+            .ok()
+            .flatten();
+        match response {
+            Some(response) if scheme == "http" && expects_tls(&response) => continue,
+            Some(_) => return Some(scheme.to_string()),
+            None => {}
+        }
+    }
+    None
+}
+
+fn url_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+/// Brute-force directories and files on an HTTP(S) service
+///
+/// # Arguments
+///
+/// * `host` - The host to enumerate
+/// * `port` - The port of the web service
+/// * `scheme` - `http` or `https`, as returned by [`detect_scheme`]
+/// * `words` - Candidate paths loaded with [`load_wordlists`]
+/// * `options` - Filters, extensions and concurrency settings
+/// * `timing` - Rate limits every request waits for, like the probes of the host
+/// * `cancel` - Stops sending requests, findings so far are still returned
+///
+/// # Returns
+///
+/// The findings that passed the status, size and soft-404 filters, sorted by path
+pub async fn discover(
+    host: &str,
+    port: u16,
+    scheme: &str,
+    words: &[String],
+    options: &WebEnumOptions,
+    timing: &Arc<HostTiming>,
+    cancel: &CancellationToken,
+) -> WebEnumResult {
+    let base_url = format!("{}://{}:{}", scheme, url_host(host), port);
+    let timeout = options.timeout;
+
+    // Probe paths that cannot exist to learn how the server answers missing content
+    let token = random_token();
+    let mut baselines = Vec::new();
+    for probe_path in [format!("rmap-{}", token), format!("rmap-{}/", token)] {
+        tokio::select! {
+            _ = timing.wait_turn() => {}
+            _ = cancel.cancelled() => break,
+        }
+        if cancel.is_cancelled() {
+            break;
+        }
+        let url = base_url.clone();
+        let path = probe_path.clone();
+        if let Ok(Some(response)) =
+            tokio::task::spawn_blocking(move || fetch(&url, &path, timeout)).await
+        {
+            let (size, words, lines) = body_metrics(&response, &probe_path);
+            baselines.push((
+                probe_path.clone(),
+                Baseline {
+                    status: response.status,
+                    size,
+                    words,
+                    lines,
+                    redirect: response.header("location").map(str::to_string),
+                },
+            ));
+        }
+    }
+    let wildcard = baselines
+        .iter()
+        .any(|(_, baseline)| options.status_codes.contains(&baseline.status));
+
+    // A fixed number of requests is in flight, candidates are only built as they are sent
+    let results: Vec<_> = stream::iter(build_candidates(words, &options.extensions))
+        .take_while(|_| future::ready(!cancel.is_cancelled()))
+        .map(|path| check_path(&base_url, path, &baselines, options, timing, cancel))
+        .buffer_unordered(options.threads.max(1))
+        .collect()
+        .await;
+
+    // `None` for requests never sent and `Some(None)` for requests without a finding
+    let requests = results.iter().filter(|result| result.is_some()).count();
+    let mut findings: Vec<WebFinding> = results.into_iter().flatten().flatten().collect();
+    findings.sort_by(|a, b| a.path.cmp(&b.path));
+
+    WebEnumResult {
+        host: host.to_string(),
+        port,
+        scheme: scheme.to_string(),
+        wildcard,
+        requests,
+        findings,
+    }
+}
+
+/// Request `path` and check the response against the filters of `options`
+///
+/// # Returns
+///
+/// `None` when the request was not sent because the scan was cancelled, otherwise the
+/// finding if the response passed the filters
+async fn check_path(
+    base_url: &str,
+    path: String,
+    baselines: &[(String, Baseline)],
+    options: &WebEnumOptions,
+    timing: &HostTiming,
+    cancel: &CancellationToken,
+) -> Option<Option<WebFinding>> {
+    // Queued requests are not sent once the scan is cancelled
+    tokio::select! {
+        _ = timing.wait_turn() => {}
+        _ = cancel.cancelled() => return None,
+    }
+    if cancel.is_cancelled() {
+        return None;
+    }
+    let url = base_url.to_string();
+    let request_path = path.clone();
+    let timeout = options.timeout;
+    let response = tokio::task::spawn_blocking(move || fetch(&url, &request_path, timeout))
+        .await
+        .ok()
+        .flatten();
+    let Some(response) = response else {
+        return Some(None);
+    };
+
+    if !options.status_codes.contains(&response.status)
+        || options.exclude_sizes.contains(&response.body.len())
+        || baselines
+            .iter()
+            .any(|(probe_path, baseline)| baseline.matches(&path, &response, probe_path))
+    {
+        return Some(None);
+    }
+
+    Some(Some(WebFinding {
+        url: format!("{}/{}", base_url, path),
+        path: format!("/{}", path),
+        status: response.status,
+        size: response.body.len(),
+        redirect: response.header("location").map(str::to_string),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, body: &str, location: Option<&str>) -> HttpResponse {
+        HttpResponse {
+            status,
+            reason: String::new(),
+            version: "HTTP/1.1".to_string(),
+            headers: location
+                .map(|location| vec![("location".to_string(), location.to_string())])
+                .unwrap_or_default(),
+            body: body.as_bytes().to_vec(),
+            url: String::new(),
+        }
+    }
+
+    fn baseline(probe_path: &str, response: &HttpResponse) -> Baseline {
+        let (size, words, lines) = body_metrics(response, probe_path);
+        Baseline {
+            status: response.status,
+            size,
+            words,
+            lines,
+            redirect: response.header("location").map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn baseline_matches_soft_404_pages() {
+        let probe = "rmap-0123456789abcdef";
+        let not_found = baseline(
+            probe,
+            &response(200, &format!("<h1>Not found: /{}</h1>", probe), None),
+        );
+
+        // The same page echoing another path
+        assert!(not_found.matches(
+            "admin",
+            &response(200, "<h1>Not found: /admin</h1>", None),
+            probe
+        ));
+        // A dynamic page that changes size but not its word and line counts
+        assert!(not_found.matches(
+            "x",
+            &response(200, "<h1>Not found: /a-much-longer-echo</h1>", None),
+            probe
+        ));
+        // Real content, or another status
+        assert!(!not_found.matches(
+            "admin",
+            &response(200, "<h1>Admin</h1>\n<p>Log in to continue</p>", None),
+            probe
+        ));
+        assert!(!not_found.matches(
+            "admin",
+            &response(403, "<h1>Not found: /admin</h1>", None),
+            probe
+        ));
+    }
+
+    #[test]
+    fn baseline_compares_redirects_without_the_path() {
+        let probe = "rmap-0123456789abcdef";
+        let login = baseline(
+            probe,
+            &response(302, "", Some(&format!("/login?next={}", probe))),
+        );
+
+        assert!(login.matches(
+            "admin",
+            &response(302, "", Some("/login?next=admin")),
+            probe
+        ));
+        assert!(!login.matches("admin", &response(302, "", Some("/admin/")), probe));
+        assert!(!login.matches("admin", &response(302, "", None), probe));
+    }
+
+    #[test]
+    fn candidates_are_words_then_their_extensions() {
+        let words = vec!["admin".to_string(), "index".to_string()];
+        let extensions = vec!["php".to_string(), ".bak".to_string()];
+        let candidates: Vec<String> = build_candidates(&words, &extensions).collect();
+        assert_eq!(
+            candidates,
+            [
+                "admin",
+                "admin.php",
+                "admin.bak",
+                "index",
+                "index.php",
+                "index.bak"
+            ]
+        );
+        assert_eq!(build_candidates(&words, &[]).count(), 2);
+        assert_eq!(build_candidates(&[], &extensions).count(), 0);
+    }
+
+    #[test]
+    fn wordlists_skip_comments_blanks_and_duplicates() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("rmap-words-1-{}.txt", std::process::id()));
+        let second = dir.join(format!("rmap-words-2-{}.txt", std::process::id()));
+        fs::write(&first, "# common paths\nadmin\n\n  /login  \nadmin\n").unwrap();
+        fs::write(&second, "login\n#admin\nbackup\n").unwrap();
+        let paths = [&first, &second].map(|path| path.to_string_lossy().to_string());
+
+        let words = load_wordlists(&paths);
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+        assert_eq!(words.unwrap(), ["admin", "login", "backup"]);
+
+        let error = load_wordlists(&["/nonexistent/words.txt".to_string()]).unwrap_err();
+        assert!(error.to_string().contains("/nonexistent/words.txt"));
+    }
+
+    #[test]
+    fn tls_is_expected_from_nginx_and_apache_errors() {
+        let nginx = "<html><head><title>400 The plain HTTP request was sent to HTTPS port</title>";
+        let apache = "<p>Your browser sent a request that this server could not understand.<br />\n\
+                      Reason: You're speaking plain HTTP to an SSL-enabled server port.<br />";
+        assert!(expects_tls(&response(400, nginx, None)));
+        assert!(expects_tls(&response(400, apache, None)));
+        assert!(!expects_tls(&response(400, "Bad Request", None)));
+        assert!(!expects_tls(&response(200, nginx, None)));
+    }
+}
@@ -0,0 +1,413 @@
+//! Differences between two scan reports
+//!
+//! Hosts are matched by target and address, ports by protocol and port number. A host that
+//! was not up counts as absent, and so does a port that was not open.
+
+use crate::core::{PortState, ServiceInfo};
+use crate::report::{HostReport, HostStatus, PortReport, Protocol, ScanReport};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One difference between the old and the new report
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    /// The host is up in the new report but was not in the old one
+    HostAdded { target: String, address: String },
+    /// The host was up in the old report but is not in the new one
+    HostRemoved { target: String, address: String },
+    /// The port is open in the new report but was not in the old one
+    PortOpened {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        port: u16,
+        /// State in the old report, `None` when the port was not scanned
+        previous: Option<PortState>,
+        service: Option<ServiceInfo>,
+    },
+    /// The port was open in the old report but is not in the new one
+    PortClosed {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        port: u16,
+        /// State in the new report, `None` when the port was not scanned
+        current: Option<PortState>,
+        service: Option<ServiceInfo>,
+    },
+    /// The port is open in both reports but the identified service differs
+    ServiceChanged {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        port: u16,
+        previous: Option<ServiceInfo>,
+        current: Option<ServiceInfo>,
+    },
+}
+
+impl Change {
+    /// Short label of the change kind, as used in the table output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::HostAdded { .. } => "host added",
+            Change::HostRemoved { .. } => "host removed",
+            Change::PortOpened { .. } => "port opened",
+            Change::PortClosed { .. } => "port closed",
+            Change::ServiceChanged { .. } => "service changed",
+        }
+    }
+}
+
+/// The differences between two reports, in host and port order
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanDiff {
+    pub old_start_time: DateTime<Utc>,
+    pub new_start_time: DateTime<Utc>,
+    pub changes: Vec<Change>,
+}
+
+impl ScanDiff {
+    /// Compare `old` against `new`
+    pub fn new(old: &ScanReport, new: &ScanReport) -> ScanDiff {
+        let old_hosts = hosts_up(old);
+        let new_hosts = hosts_up(new);
+        let keys: BTreeSet<_> = old_hosts.keys().chain(new_hosts.keys()).collect();
+
+        let mut changes = Vec::new();
+        for key in keys {
+            let (target, address) = key.clone();
+            let old_host = old_hosts.get(key).copied();
+            let new_host = new_hosts.get(key).copied();
+
+            match (old_host, new_host) {
+                (None, Some(_)) => changes.push(Change::HostAdded {
+                    target: target.clone(),
+                    address: address.clone(),
+                }),
+                (Some(_), None) => changes.push(Change::HostRemoved {
+                    target: target.clone(),
+                    address: address.clone(),
+                }),
+                _ => {}
+            }
+
+            let old_ports = ports_by_key(old_host);
+            let new_ports = ports_by_key(new_host);
+            let port_keys: BTreeSet<_> = old_ports.keys().chain(new_ports.keys()).collect();
+
+            for &(protocol, port) in port_keys {
+                let old_port = old_ports.get(&(protocol, port));
+                let new_port = new_ports.get(&(protocol, port));
+                let was_open = old_port.is_some_and(|p| p.state == PortState::Open);
+                let is_open = new_port.is_some_and(|p| p.state == PortState::Open);
+
+                let change = match (was_open, is_open) {
+                    (false, true) => Change::PortOpened {
+                        target: target.clone(),
+                        address: address.clone(),
+                        protocol,
+                        port,
+                        previous: old_port.map(|p| p.state),
+                        service: new_port.and_then(|p| p.service.clone()),
+                    },
+                    (true, false) => Change::PortClosed {
+                        target: target.clone(),
+                        address: address.clone(),
+                        protocol,
+                        port,
+                        current: new_port.map(|p| p.state),
+                        service: old_port.and_then(|p| p.service.clone()),
+                    },
+                    (true, true) => {
+                        let previous = old_port.and_then(|p| p.service.clone());
+                        let current = new_port.and_then(|p| p.service.clone());
+                        if same_service(previous.as_ref(), current.as_ref()) {
+                            continue;
+                        }
+                        Change::ServiceChanged {
+                            target: target.clone(),
+                            address: address.clone(),
+                            protocol,
+                            port,
+                            previous,
+                            current,
+                        }
+                    }
+                    (false, false) => continue,
+                };
+                changes.push(change);
+            }
+        }
+
+        ScanDiff {
+            old_start_time: old.metadata.start_time,
+            new_start_time: new.metadata.start_time,
+            changes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Hosts that were up, keyed by target and address
+fn hosts_up(report: &ScanReport) -> BTreeMap<(String, String), &HostReport> {
+    report
+        .hosts
+        .iter()
+        .filter(|host| host.status == HostStatus::Up)
+        .map(|host| ((host.target.clone(), host.address().to_string()), host))
+        .collect()
+}
+
+fn ports_by_key(host: Option<&HostReport>) -> BTreeMap<(Protocol, u16), &PortReport> {
+    host.map(|host| {
+        host.ports
+            .iter()
+            .map(|port| ((port.protocol, port.port), port))
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Services are the same when name, product, version and extra info all match
+fn same_service(a: Option<&ServiceInfo>, b: Option<&ServiceInfo>) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) => {
+            a.name == b.name
+                && a.product == b.product
+                && a.version == b.version
+                && a.extra_info == b.extra_info
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Config;
+    use crate::core::PortReason;
+
+    fn port(protocol: Protocol, port: u16, state: PortState, service: &str) -> PortReport {
+        let reason = match state {
+            PortState::Open => PortReason::SynAck,
+            PortState::Closed => PortReason::ConnRefused,
+            _ => PortReason::NoResponse,
+        };
+        PortReport {
+            protocol,
+            port,
+            state,
+            reason,
+            attempts: 1,
+            service: Some(ServiceInfo {
+                name: service.to_string(),
+                version: None,
+                product: None,
+                extra_info: None,
+                cpe: Vec::new(),
+                confidence: None,
+            }),
+            scripts: Vec::new(),
+        }
+    }
+
+    fn host(address: &str, ports: Vec<PortReport>) -> HostReport {
+        let mut host = HostReport::new(address, address);
+        host.add_ports(ports);
+        host
+    }
+
+    fn report(hosts: Vec<HostReport>) -> ScanReport {
+        let mut report = ScanReport::new(&Config::default());
+        report.hosts = hosts;
+        report
+    }
+
+    fn kinds(diff: &ScanDiff) -> Vec<&'static str> {
+        diff.changes.iter().map(Change::kind).collect()
+    }
+
+    #[test]
+    fn identical_reports_have_no_changes() {
+        let hosts = || {
+            vec![host(
+                "10.0.0.1",
+                vec![
+                    port(Protocol::Tcp, 22, PortState::Open, "ssh"),
+                    port(Protocol::Tcp, 23, PortState::Closed, "telnet"),
+                ],
+            )]
+        };
+        assert!(ScanDiff::new(&report(hosts()), &report(hosts())).is_empty());
+    }
+
+    #[test]
+    fn hosts_added_and_removed() {
+        let old = report(vec![host(
+            "10.0.0.1",
+            vec![port(Protocol::Tcp, 22, PortState::Open, "ssh")],
+        )]);
+        let new = report(vec![host(
+            "10.0.0.2",
+            vec![port(Protocol::Tcp, 80, PortState::Closed, "http")],
+        )]);
+        let diff = ScanDiff::new(&old, &new);
+        assert_eq!(kinds(&diff), ["host removed", "port closed", "host added"]);
+        assert!(matches!(
+            &diff.changes[0],
+            Change::HostRemoved { address, .. } if address == "10.0.0.1"
+        ));
+        assert!(matches!(
+            &diff.changes[1],
+            Change::PortClosed {
+                port: 22,
+                current: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &diff.changes[2],
+            Change::HostAdded { address, .. } if address == "10.0.0.2"
+        ));
+    }
+
+    #[test]
+    fn hosts_that_were_down_count_as_absent() {
+        // No port was answered, so the host is not up
+        let down = host(
+            "10.0.0.1",
+            vec![port(Protocol::Tcp, 22, PortState::Filtered, "ssh")],
+        );
+        assert_eq!(down.status, HostStatus::Unknown);
+        let up = host(
+            "10.0.0.1",
+            vec![port(Protocol::Tcp, 22, PortState::Open, "ssh")],
+        );
+
+        let diff = ScanDiff::new(&report(vec![down.clone()]), &report(vec![up.clone()]));
+        assert_eq!(kinds(&diff), ["host added", "port opened"]);
+        // The ports of a host that was down are not compared
+        assert!(matches!(
+            &diff.changes[1],
+            Change::PortOpened {
+                port: 22,
+                previous: None,
+                ..
+            }
+        ));
+
+        let diff = ScanDiff::new(&report(vec![up]), &report(vec![down.clone()]));
+        assert_eq!(kinds(&diff), ["host removed", "port closed"]);
+
+        assert!(ScanDiff::new(&report(vec![down]), &report(vec![])).is_empty());
+    }
+
+    #[test]
+    fn ports_opened_and_closed_keep_the_other_state() {
+        let old = report(vec![host(
+            "10.0.0.1",
+            vec![
+                port(Protocol::Tcp, 22, PortState::Open, "ssh"),
+                port(Protocol::Tcp, 80, PortState::Closed, "http"),
+                port(Protocol::Udp, 53, PortState::OpenFiltered, "domain"),
+            ],
+        )]);
+        let new = report(vec![host(
+            "10.0.0.1",
+            vec![
+                port(Protocol::Tcp, 22, PortState::Filtered, "ssh"),
+                port(Protocol::Tcp, 80, PortState::Open, "http"),
+                port(Protocol::Tcp, 443, PortState::Open, "https"),
+                port(Protocol::Udp, 53, PortState::Open, "domain"),
+            ],
+        )]);
+        let diff = ScanDiff::new(&old, &new);
+        assert_eq!(
+            kinds(&diff),
+            ["port closed", "port opened", "port opened", "port opened"]
+        );
+        assert!(matches!(
+            &diff.changes[0],
+            Change::PortClosed {
+                protocol: Protocol::Tcp,
+                port: 22,
+                current: Some(PortState::Filtered),
+                service: Some(_),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &diff.changes[1],
+            Change::PortOpened {
+                port: 80,
+                previous: Some(PortState::Closed),
+                ..
+            }
+        ));
+        assert!(matches!(
+            &diff.changes[2],
+            Change::PortOpened {
+                port: 443,
+                previous: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &diff.changes[3],
+            Change::PortOpened {
+                protocol: Protocol::Udp,
+                port: 53,
+                previous: Some(PortState::OpenFiltered),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn service_changes_on_open_ports() {
+        let with_version = |version: Option<&str>| {
+            let mut port = port(Protocol::Tcp, 22, PortState::Open, "ssh");
+            if let Some(service) = &mut port.service {
+                service.product = Some("OpenSSH".to_string());
+                service.version = version.map(str::to_string);
+            }
+            report(vec![host("10.0.0.1", vec![port])])
+        };
+
+        let same = ScanDiff::new(&with_version(Some("9.6")), &with_version(Some("9.6")));
+        assert!(same.is_empty());
+
+        let diff = ScanDiff::new(&with_version(Some("9.6")), &with_version(Some("9.7")));
+        assert_eq!(kinds(&diff), ["service changed"]);
+        let Change::ServiceChanged {
+            previous, current, ..
+        } = &diff.changes[0]
+        else {
+            unreachable!();
+        };
+        assert_eq!(previous.as_ref().unwrap().version.as_deref(), Some("9.6"));
+        assert_eq!(current.as_ref().unwrap().version.as_deref(), Some("9.7"));
+
+        let diff = ScanDiff::new(&with_version(None), &with_version(Some("9.7")));
+        assert_eq!(kinds(&diff), ["service changed"]);
+
+        // Services of ports that are not open in both reports are not compared
+        let closed = |name: &str| {
+            report(vec![host(
+                "10.0.0.1",
+                vec![
+                    port(Protocol::Tcp, 22, PortState::Open, "ssh"),
+                    port(Protocol::Tcp, 23, PortState::Closed, name),
+                ],
+            )])
+        };
+        assert!(ScanDiff::new(&closed("telnet"), &closed("other")).is_empty());
+    }
+}
@@ -1,12 +1,54 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error::Error;
 use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
 use tokio::net::lookup_host;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::proto::rr::RecordType;
 
-pub struct DNSResolver;
+/// Reverse lookups in flight at once
+const MAX_PARALLEL_LOOKUPS: usize = 32;
+
+/// Which scanned hosts get their names looked up with reverse DNS
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReverseDns {
+    /// Every scanned host, whether it answered or not
+    All,
+    /// Hosts that answered a probe
+    #[default]
+    Live,
+    /// No host
+    Never,
+}
+
+/// Resolves names and addresses, clones share the resolver and the reverse lookup cache
+#[derive(Clone, Default)]
+pub struct DNSResolver {
+    resolver: Arc<OnceLock<TokioAsyncResolver>>,
+    /// Names found by reverse lookups, empty for addresses without a PTR record
+    names: Arc<Mutex<HashMap<IpAddr, Vec<String>>>>,
+}
 
 impl DNSResolver {
     pub fn new() -> DNSResolver {
-        DNSResolver
+        DNSResolver::default()
+    }
+
+    /// The resolver of the system configuration, or public resolvers if it cannot be read
+    fn resolver(&self) -> &TokioAsyncResolver {
+        self.resolver.get_or_init(|| {
+            TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|_| {
+                TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+            })
+        })
     }
 
     /// Resolve a hostname to an IP address
@@ -32,7 +74,7 @@ impl DNSResolver {
         }
     }
 
-    /// Resolve an IP address to a hostname
+    /// Resolve an IP address to its hostnames with a PTR lookup
     ///
     /// # Arguments
     ///
@@ -40,18 +82,83 @@ impl DNSResolver {
     ///
     /// # Returns
     ///
-    /// A `Result` containing the resolved hostname, or an error if the resolution fails
-    pub async fn reverse_resolve(&self, ip: &str) -> Result<String, Box<dyn Error>> {
-        // Parse IP address first to validate it
+    /// A `Result` containing the hostnames without the trailing dot, empty when the address has
+    /// no PTR record, or an error if the lookup fails. Answers are cached, failures are not.
+    pub async fn reverse_resolve(
+        &self,
+        ip: &str,
+    ) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
         let ip_addr: IpAddr = ip.parse()?;
+        if let Some(names) = self.cached(&ip_addr) {
+            return Ok(names);
+        }
 
-        // Use std::net for reverse DNS lookup (this is sync but doesn't create a runtime)
-        match std::net::IpAddr::from(ip_addr) {
-            addr => {
-                // For now, return the IP as-is since reverse DNS is complex
-                // In a full implementation, you'd use a proper async DNS library
-                Ok(addr.to_string())
+        let names = match self.resolver().reverse_lookup(ip_addr).await {
+            Ok(lookup) => lookup
+                .iter()
+                .map(|name| name.to_utf8().trim_end_matches('.').to_string())
+                .collect(),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        if let Ok(mut cache) = self.names.lock() {
+            cache.insert(ip_addr, names.clone());
+        }
+        Ok(names)
+    }
+
+    /// Reverse resolve many addresses concurrently
+    ///
+    /// # Returns
+    ///
+    /// The hostnames of every address that has any, addresses whose lookup failed are left out
+    pub async fn reverse_resolve_all(&self, ips: &[IpAddr]) -> HashMap<IpAddr, Vec<String>> {
+        let semaphore = Arc::new(Semaphore::new(MAX_PARALLEL_LOOKUPS));
+        let mut lookups = JoinSet::new();
+        let mut pending = ips.to_vec();
+        pending.sort();
+        pending.dedup();
+        for ip in pending {
+            let resolver = self.clone();
+            let semaphore = semaphore.clone();
+            lookups.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                (ip, resolver.reverse_resolve(&ip.to_string()).await)
+            });
+        }
+
+        let mut names = HashMap::new();
+        while let Some(lookup) = lookups.join_next().await {
+            if let Ok((ip, Ok(found))) = lookup
+                && !found.is_empty()
+            {
+                names.insert(ip, found);
             }
         }
+        names
+    }
+
+    fn cached(&self, ip: &IpAddr) -> Option<Vec<String>> {
+        self.names.lock().ok()?.get(ip).cloned()
+    }
+
+    /// Query DNS records of a given type for a name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to query
+    /// * `record_type` - The record type, e.g. `A`, `AAAA`, `MX`, `TXT`, `NS` or `PTR`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the record data of every answer, or an error if the query fails
+    pub async fn query(
+        &self,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let record_type = RecordType::from_str(&record_type.to_ascii_uppercase())?;
+        let lookup = self.resolver().lookup(name, record_type).await?;
+        Ok(lookup.iter().map(|rdata| rdata.to_string()).collect())
     }
 }
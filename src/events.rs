@@ -0,0 +1,281 @@
+//! Events emitted while a scan is running
+//!
+//! Events are delivered to every [`ScanObserver`] registered with the scanner. Each result
+//! event is written as one JSON object per line by the NDJSON output, with a `timestamp` and
+//! a `type` field followed by the event's own fields.
+
+use crate::core::web::WebEnumResult;
+use crate::core::{PortReason, PortResult, PortState, ScriptResult, ServiceInfo};
+use crate::report::Protocol;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedSender;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScanEvent {
+    ScanStarted {
+        version: String,
+        command_line: Vec<String>,
+        targets: Vec<String>,
+        ports: String,
+        protocols: Vec<Protocol>,
+    },
+    /// The first reply from a host was received
+    HostUp {
+        target: String,
+        address: String,
+        reason: PortReason,
+    },
+    /// Reverse DNS found a name for a host
+    Hostname {
+        target: String,
+        address: String,
+        name: String,
+    },
+    PortResult {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        port: u16,
+        state: PortState,
+        reason: PortReason,
+        attempts: u32,
+    },
+    Service {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        port: u16,
+        service: ServiceInfo,
+    },
+    /// Probes completed so far for a host and protocol
+    ///
+    /// Sent after every probe. Progress is not written to the NDJSON stream.
+    Progress {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        done: usize,
+        total: usize,
+        /// Probes per second since the first probe of this host
+        rate: f64,
+        /// Estimated seconds until every probe of this host is done
+        eta_seconds: f64,
+    },
+    ScriptResult {
+        target: String,
+        address: String,
+        result: ScriptResult,
+    },
+    WebResult {
+        target: String,
+        address: String,
+        result: WebEnumResult,
+    },
+    /// A target did not resolve, or scanning a host, or running its scripts or web discovery,
+    /// failed. `address` is empty for a target that did not resolve.
+    Error {
+        target: String,
+        address: String,
+        message: String,
+    },
+    /// Every probe, script and web request for a host and protocol has completed
+    HostFinished {
+        target: String,
+        address: String,
+        protocol: Protocol,
+        open_ports: usize,
+    },
+    ScanFinished {
+        hosts: usize,
+        hosts_up: usize,
+        elapsed_seconds: f64,
+        /// The scan was cancelled before every probe was sent
+        partial: bool,
+    },
+}
+
+/// An event with the time it happened
+#[derive(Debug, Clone, Serialize)]
+pub struct TimedEvent {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: ScanEvent,
+}
+
+impl TimedEvent {
+    pub fn now(event: ScanEvent) -> TimedEvent {
+        TimedEvent {
+            timestamp: Utc::now(),
+            event,
+        }
+    }
+}
+
+/// Receives events while a scan runs
+///
+/// Observers are called from the scanning tasks and must not block. Closures taking a
+/// `&ScanEvent` and tokio unbounded senders can be used as observers directly.
+pub trait ScanObserver: Send + Sync {
+    fn on_event(&self, event: &ScanEvent);
+}
+
+impl<F> ScanObserver for F
+where
+    F: Fn(&ScanEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &ScanEvent) {
+        self(event)
+    }
+}
+
+impl ScanObserver for UnboundedSender<ScanEvent> {
+    fn on_event(&self, event: &ScanEvent) {
+        // A dropped receiver only means nobody is listening any more
+        let _ = self.send(event.clone());
+    }
+}
+
+/// The observers of one scan, every event is passed to each of them in order
+#[derive(Clone, Default)]
+pub struct Observers {
+    observers: Vec<Arc<dyn ScanObserver>>,
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("len", &self.observers.len())
+            .finish()
+    }
+}
+
+impl Observers {
+    pub fn add(&mut self, observer: Arc<dyn ScanObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    pub fn emit(&self, event: ScanEvent) {
+        for observer in &self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    /// Emit an error about the host `target` at `address`
+    pub(crate) fn error(&self, target: &str, address: &str, message: String) {
+        self.emit(ScanEvent::Error {
+            target: target.to_string(),
+            address: address.to_string(),
+            message,
+        });
+    }
+
+    /// Emit the events for a finished port probe
+    ///
+    /// `host_up` is shared by all probes of a host so `host_up` is only emitted for the
+    /// first reply.
+    pub(crate) fn port_result(
+        &self,
+        target: &str,
+        address: &str,
+        protocol: Protocol,
+        result: &PortResult,
+        host_up: &AtomicBool,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+        let Ok(port) = result.port.parse::<u16>() else {
+            return;
+        };
+
+        if result.reason.is_reply() && !host_up.swap(true, Ordering::SeqCst) {
+            self.emit(ScanEvent::HostUp {
+                target: target.to_string(),
+                address: address.to_string(),
+                reason: result.reason,
+            });
+        }
+
+        self.emit(ScanEvent::PortResult {
+            target: target.to_string(),
+            address: address.to_string(),
+            protocol,
+            port,
+            state: result.state,
+            reason: result.reason,
+            attempts: result.attempts,
+        });
+
+        if result.state == PortState::Open {
+            self.emit(ScanEvent::Service {
+                target: target.to_string(),
+                address: address.to_string(),
+                protocol,
+                port,
+                service: ServiceInfo::from_port_table(&result.port),
+            });
+        }
+    }
+}
+
+/// Counts completed probes of one host and protocol and reports them as progress events
+pub(crate) struct HostProgress {
+    target: String,
+    address: String,
+    protocol: Protocol,
+    total: usize,
+    done: AtomicUsize,
+    started: Instant,
+}
+
+impl HostProgress {
+    pub(crate) fn new(target: &str, address: &str, protocol: Protocol, total: usize) -> Self {
+        HostProgress {
+            target: target.to_string(),
+            address: address.to_string(),
+            protocol,
+            total,
+            done: AtomicUsize::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    /// Record one completed probe
+    pub(crate) fn advance(&self, observers: &Observers) {
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+        if observers.is_empty() {
+            return;
+        }
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            done as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta_seconds = if rate > 0.0 {
+            self.total.saturating_sub(done) as f64 / rate
+        } else {
+            0.0
+        };
+        observers.emit(ScanEvent::Progress {
+            target: self.target.clone(),
+            address: self.address.clone(),
+            protocol: self.protocol,
+            done,
+            total: self.total,
+            rate,
+            eta_seconds,
+        });
+    }
+}
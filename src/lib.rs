@@ -1,5 +1,10 @@
 pub mod args;
+pub mod checkpoint;
 pub mod core;
+pub mod diff;
 pub mod dns;
+pub mod events;
+pub mod net;
 pub mod output;
+pub mod report;
 pub mod utils;
@@ -1,10 +1,82 @@
-use rmap::{args::get_config, core::Scanner};
+use rmap::{
+    args::{Command, DiffFormat, get_config},
+    core::{LuaScriptRunner, ScannerBuilder},
+    diff::ScanDiff,
+    output::OutputHandler,
+    report::ScanReport,
+};
 use std::error::Error;
+use std::path::Path;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let config = get_config();
-    let scanner = Scanner::new(config);
-    scanner.exec().await?;
+
+    if let Some(Command::Report { input, output }) = &config.command {
+        let report = ScanReport::from_file(input)?;
+        let output = output.clone().unwrap_or_else(|| {
+            Path::new(input)
+                .with_extension("html")
+                .to_string_lossy()
+                .into_owned()
+        });
+        OutputHandler::new().out_html(&report, &output)?;
+        return Ok(());
+    }
+
+    // Exit status follows diff(1): 0 when equal, 1 when different, 2 on errors
+    if let Some(Command::Diff { old, new, format }) = &config.command {
+        let load = |path: &str| {
+            ScanReport::from_file(path).unwrap_or_else(|e| {
+                eprintln!("Error loading report '{}': {}", path, e);
+                std::process::exit(2);
+            })
+        };
+        let diff = ScanDiff::new(&load(old), &load(new));
+        match format {
+            DiffFormat::Table => OutputHandler::new().out_diff(&diff),
+            DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        }
+        if !diff.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if config.list_scripts {
+        let mut script_runner = LuaScriptRunner::new()?;
+        script_runner.set_scripts_dir(&config.scripts_dir);
+        OutputHandler::new().out_script_list(&script_runner.describe_scripts()?);
+        return Ok(());
+    }
+
+    // The configuration is checked after printing it so mistakes can be tracked down
+    if config.print_config {
+        OutputHandler::new().out_config(&config)?;
+        if let Err(e) = config.validate() {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let builder = match config.resume.clone() {
+        Some(path) => ScannerBuilder::resume(&path)
+            .unwrap_or_else(|e| {
+                eprintln!("Error loading checkpoint '{}': {}", path, e);
+                std::process::exit(1);
+            })
+            .script_args(config.script_args),
+        None => ScannerBuilder::from_config(config),
+    };
+    let scanner = builder.build().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let report = scanner.exec().await?;
+    // Same status as a shell reports for a process stopped by SIGINT
+    if report.metadata.partial {
+        std::process::exit(130);
+    }
     Ok(())
 }
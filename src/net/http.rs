@@ -0,0 +1,389 @@
+use super::tls;
+use std::error::Error;
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+/// Upper bound on a response body, larger bodies are truncated
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Upper bound on the status line and headers of a response
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+const USER_AGENT: &str = concat!("rmap/", env!("CARGO_PKG_VERSION"));
+
+/// A parsed `http://` or `https://` URL
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    /// Parse an absolute HTTP(S) URL
+    pub fn parse(url: &str) -> Result<Url, Box<dyn Error + Send + Sync>> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| format!("Invalid URL '{}': missing scheme", url))?;
+        let scheme = scheme.to_ascii_lowercase();
+        let default_port = match scheme.as_str() {
+            "http" => 80,
+            "https" => 443,
+            _ => return Err(format!("Unsupported URL scheme '{}'", scheme).into()),
+        };
+
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], rest[i..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+        let path = if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path
+        };
+
+        // Bracketed IPv6 literals carry colons, so only split on a port after the bracket
+        let (host, port) = if let Some(stripped) = authority.strip_prefix('[') {
+            let (host, after) = stripped
+                .split_once(']')
+                .ok_or_else(|| format!("Invalid URL '{}': unterminated IPv6 address", url))?;
+            let port = match after.strip_prefix(':') {
+                Some(port) => port.parse()?,
+                None => default_port,
+            };
+            (host.to_string(), port)
+        } else {
+            match authority.rsplit_once(':') {
+                Some((host, port)) => (host.to_string(), port.parse()?),
+                None => (authority.to_string(), default_port),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(format!("Invalid URL '{}': missing host", url).into());
+        }
+
+        Ok(Url {
+            scheme,
+            host,
+            port,
+            path,
+        })
+    }
+
+    /// Resolve a `Location` header value relative to this URL
+    pub fn join(&self, location: &str) -> Result<Url, Box<dyn Error + Send + Sync>> {
+        if location.contains("://") {
+            return Url::parse(location);
+        }
+        if let Some(rest) = location.strip_prefix("//") {
+            return Url::parse(&format!("{}://{}", self.scheme, rest));
+        }
+
+        let path = if location.starts_with('/') {
+            location.to_string()
+        } else {
+            let base = self.path.split('?').next().unwrap_or("/");
+            let dir = &base[..base.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", if dir.is_empty() { "/" } else { dir }, location)
+        };
+
+        Ok(Url {
+            path,
+            ..self.clone()
+        })
+    }
+
+    fn default_port(&self) -> bool {
+        (self.scheme == "http" && self.port == 80) || (self.scheme == "https" && self.port == 443)
+    }
+
+    fn host_header(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        if self.default_port() {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+}
+
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}://{}{}", self.scheme, self.host_header(), self.path)
+    }
+}
+
+/// An HTTP request to send with [`send`]
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Number of redirects to follow, 0 returns the redirect response itself
+    pub max_redirects: usize,
+    pub timeout: Duration,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_ascii_uppercase(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            max_redirects: 0,
+            timeout: Duration::from_millis(5000),
+        }
+    }
+}
+
+/// A received HTTP response
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub reason: String,
+    pub version: String,
+    /// Header names are lowercased
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// The URL that produced this response, after following redirects
+    pub url: String,
+}
+
+impl HttpResponse {
+    /// Get the first value of a header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Send an HTTP request, following redirects up to `request.max_redirects`
+///
+/// Certificates of HTTPS services are not validated. This is a blocking call.
+pub fn send(request: &HttpRequest) -> Result<HttpResponse, Box<dyn Error + Send + Sync>> {
+    let mut url = Url::parse(&request.url)?;
+    let mut method = request.method.clone();
+    let mut body = request.body.clone();
+    let mut redirects = 0;
+
+    loop {
+        let response = send_once(&url, &method, &request.headers, body.as_deref(), request)?;
+
+        let location = match (response.status, response.header("location")) {
+            (301 | 302 | 303 | 307 | 308, Some(location)) => location.to_string(),
+            _ => return Ok(response),
+        };
+        if redirects == request.max_redirects {
+            return Ok(response);
+        }
+        redirects += 1;
+
+        // Follow browser semantics: 303, and 301/302 after a POST, become GETs
+        if response.status == 303 || (matches!(response.status, 301 | 302) && method == "POST") {
+            method = "GET".to_string();
+            body = None;
+        }
+        url = url.join(&location)?;
+    }
+}
+
+fn send_once(
+    url: &Url,
+    method: &str,
+    headers: &[(String, String)],
+    body: Option<&[u8]>,
+    request: &HttpRequest,
+) -> Result<HttpResponse, Box<dyn Error + Send + Sync>> {
+    let mut raw = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\n",
+        method,
+        url.path,
+        url.host_header()
+    );
+    let has_header = |name: &str| headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name));
+    if !has_header("user-agent") {
+        raw.push_str(&format!("User-Agent: {}\r\n", USER_AGENT));
+    }
+    if !has_header("accept") {
+        raw.push_str("Accept: */*\r\n");
+    }
+    for (name, value) in headers {
+        raw.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if let Some(body) = body {
+        raw.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    raw.push_str("Connection: close\r\n\r\n");
+
+    let mut payload = raw.into_bytes();
+    if let Some(body) = body {
+        payload.extend_from_slice(body);
+    }
+
+    let head_only = method == "HEAD";
+    let mut response = if url.scheme == "https" {
+        let mut stream = tls::connect(&url.host, url.port, request.timeout)?;
+        exchange(&mut stream, &payload, head_only)?
+    } else {
+        let mut stream = tls::connect_tcp(&url.host, url.port, request.timeout)?;
+        exchange(&mut stream, &payload, head_only)?
+    };
+    response.url = url.to_string();
+
+    Ok(response)
+}
+
+fn exchange<S: Read + Write>(
+    stream: &mut S,
+    payload: &[u8],
+    head_only: bool,
+) -> Result<HttpResponse, Box<dyn Error + Send + Sync>> {
+    stream.write_all(payload)?;
+    stream.flush()?;
+
+    let mut buf = Vec::new();
+    let mut searched: usize = 0;
+    let header_end = loop {
+        // Only the bytes read since the last search, plus three that may start the separator
+        let start = searched.saturating_sub(3);
+        if let Some(pos) = buf[start..].windows(4).position(|w| w == b"\r\n\r\n") {
+            break start + pos + 4;
+        }
+        if buf.len() > MAX_HEADER_SIZE {
+            return Err(format!("HTTP headers larger than {} bytes", MAX_HEADER_SIZE).into());
+        }
+        searched = buf.len();
+        if fill(stream, &mut buf)? == 0 {
+            return Err("Connection closed before a complete HTTP response was received".into());
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let mut status_parts = status_line.splitn(3, ' ');
+    let version = status_parts.next().unwrap_or_default().to_string();
+    if !version.starts_with("HTTP/") {
+        return Err(format!("Invalid HTTP status line: {}", status_line).into());
+    }
+    let status: u16 = status_parts.next().unwrap_or_default().parse()?;
+    let reason = status_parts.next().unwrap_or_default().to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut body = buf.split_off(header_end);
+    let no_body = head_only || status / 100 == 1 || status == 204 || status == 304;
+
+    if no_body {
+        body.clear();
+    } else if header("transfer-encoding")
+        .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"))
+    {
+        body = read_chunked(stream, body)?;
+    } else if let Some(length) = header("content-length").and_then(|l| l.parse::<usize>().ok()) {
+        let length = length.min(MAX_BODY_SIZE);
+        while body.len() < length {
+            if fill(stream, &mut body)? == 0 {
+                break;
+            }
+        }
+        body.truncate(length);
+    } else {
+        while body.len() < MAX_BODY_SIZE {
+            if fill(stream, &mut body)? == 0 {
+                break;
+            }
+        }
+        body.truncate(MAX_BODY_SIZE);
+    }
+
+    Ok(HttpResponse {
+        status,
+        reason,
+        version,
+        headers,
+        body,
+        url: String::new(),
+    })
+}
+
+/// Decode a chunked body, `pending` holds bytes already read past the headers
+fn read_chunked<S: Read>(
+    stream: &mut S,
+    mut pending: Vec<u8>,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut body = Vec::new();
+
+    loop {
+        let line_end = loop {
+            if let Some(pos) = pending.windows(2).position(|w| w == b"\r\n") {
+                break pos;
+            }
+            if fill(stream, &mut pending)? == 0 {
+                return Ok(body);
+            }
+        };
+
+        let size_line = String::from_utf8_lossy(&pending[..line_end]).to_string();
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|_| format!("Invalid chunk size '{}'", size_hex))?;
+        pending.drain(..line_end + 2);
+
+        if size == 0 || body.len() >= MAX_BODY_SIZE {
+            return Ok(body);
+        }
+        if size > MAX_BODY_SIZE - body.len() {
+            return Err(format!(
+                "Chunk of {} bytes exceeds the {} byte body limit",
+                size, MAX_BODY_SIZE
+            )
+            .into());
+        }
+        // Cannot overflow, the size is below the body limit
+        let chunk_end = size + 2;
+
+        while pending.len() < chunk_end {
+            if fill(stream, &mut pending)? == 0 {
+                body.extend_from_slice(&pending[..pending.len().min(size)]);
+                return Ok(body);
+            }
+        }
+        body.extend_from_slice(&pending[..size]);
+        pending.drain(..chunk_end);
+    }
+}
+
+/// Read more bytes into `buf`, returning 0 once the peer has closed the connection
+fn fill<S: Read>(stream: &mut S, buf: &mut Vec<u8>) -> std::io::Result<usize> {
+    let mut chunk = [0u8; 8192];
+    match stream.read(&mut chunk) {
+        Ok(n) => {
+            buf.extend_from_slice(&chunk[..n]);
+            Ok(n)
+        }
+        // TLS peers often close without a close_notify alert
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(0),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,4 @@
+//! Blocking protocol clients shared by the Lua standard library and built-in scan modes
+
+pub mod http;
+pub mod tls;
@@ -0,0 +1,215 @@
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned,
+    SupportedProtocolVersion,
+};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::parse_x509_certificate;
+
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// Details of a server certificate and the negotiated session
+#[derive(Debug, Clone)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub subject_alt_names: Vec<String>,
+    pub signature_algorithm: String,
+    pub sha256_fingerprint: String,
+    pub protocol: Option<String>,
+    pub cipher_suite: Option<String>,
+}
+
+/// Certificate verifier that accepts any certificate
+///
+/// A scanner needs to talk to self-signed, expired and mismatched services, so the chain
+/// is never validated. Handshake signatures are still checked so the session is sound.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn client_config(
+    versions: &[&'static SupportedProtocolVersion],
+) -> Result<Arc<ClientConfig>, Box<dyn Error + Send + Sync>> {
+    let provider = Arc::new(ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(versions)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Open a TCP connection with connect, read and write timeouts applied
+pub fn connect_tcp(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
+    let mut last_error = None;
+
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(match last_error {
+        Some(e) => e.into(),
+        None => format!("No addresses found for host: {}", host).into(),
+    })
+}
+
+/// Establish a TLS session, completing the handshake before returning
+pub fn connect(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<TlsStream, Box<dyn Error + Send + Sync>> {
+    connect_with_versions(host, port, timeout, rustls::ALL_VERSIONS)
+}
+
+fn connect_with_versions(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+    versions: &[&'static SupportedProtocolVersion],
+) -> Result<TlsStream, Box<dyn Error + Send + Sync>> {
+    let server_name =
+        ServerName::try_from(host.trim_matches(|c| c == '[' || c == ']').to_string())?;
+    let mut connection = ClientConnection::new(client_config(versions)?, server_name)?;
+    let mut socket = connect_tcp(host, port, timeout)?;
+
+    while connection.is_handshaking() {
+        connection.complete_io(&mut socket)?;
+    }
+
+    Ok(StreamOwned::new(connection, socket))
+}
+
+/// Fetch the certificate presented by a TLS service
+pub fn certificate_info(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<CertificateInfo, Box<dyn Error + Send + Sync>> {
+    let stream = connect(host, port, timeout)?;
+    let connection = &stream.conn;
+
+    let der = connection
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or("Server did not present a certificate")?;
+    let (_, cert) = parse_x509_certificate(der.as_ref())?;
+
+    let mut subject_alt_names = Vec::new();
+    if let Ok(Some(san)) = cert.subject_alternative_name() {
+        for name in &san.value.general_names {
+            match name {
+                GeneralName::DNSName(dns) => subject_alt_names.push(dns.to_string()),
+                GeneralName::IPAddress(bytes) => {
+                    if let Ok(octets) = <[u8; 4]>::try_from(*bytes) {
+                        subject_alt_names.push(std::net::Ipv4Addr::from(octets).to_string());
+                    } else if let Ok(octets) = <[u8; 16]>::try_from(*bytes) {
+                        subject_alt_names.push(std::net::Ipv6Addr::from(octets).to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(CertificateInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        serial: cert.raw_serial_as_string(),
+        not_before: cert.validity().not_before.to_string(),
+        not_after: cert.validity().not_after.to_string(),
+        subject_alt_names,
+        signature_algorithm: cert.signature_algorithm.algorithm.to_id_string(),
+        sha256_fingerprint: hex::encode(Sha256::digest(der.as_ref())),
+        protocol: connection.protocol_version().map(protocol_name),
+        cipher_suite: connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite())),
+    })
+}
+
+/// Determine which TLS protocol versions a service accepts
+///
+/// Only TLS 1.2 and TLS 1.3 can be tested since older versions are not implemented by
+/// the TLS library.
+pub fn supported_versions(host: &str, port: u16, timeout: Duration) -> Vec<String> {
+    [&rustls::version::TLS12, &rustls::version::TLS13]
+        .into_iter()
+        .filter(|version| connect_with_versions(host, port, timeout, &[*version]).is_ok())
+        .map(|version| protocol_name(version.version))
+        .collect()
+}
+
+fn protocol_name(version: rustls::ProtocolVersion) -> String {
+    match version {
+        rustls::ProtocolVersion::TLSv1_2 => "TLSv1.2".to_string(),
+        rustls::ProtocolVersion::TLSv1_3 => "TLSv1.3".to_string(),
+        other => format!("{:?}", other),
+    }
+}
@@ -0,0 +1,78 @@
+//! CSV and TSV export with one row per host and port
+
+use crate::report::{HostReport, PortReport, ScanReport};
+
+const HEADER: [&str; 13] = [
+    "target",
+    "address",
+    "hostname",
+    "status",
+    "protocol",
+    "port",
+    "state",
+    "reason",
+    "service",
+    "product",
+    "version",
+    "extra_info",
+    "cpe",
+];
+
+/// Make a value safe to place between delimiters
+///
+/// CSV values are quoted when needed. TSV has no quoting, so tabs and line breaks are
+/// replaced with spaces.
+fn cell(value: &str, delimiter: char) -> String {
+    if delimiter == '\t' {
+        return value.replace(['\t', '\r', '\n'], " ");
+    }
+    if value.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn row(host: &HostReport, port: &PortReport) -> Vec<String> {
+    let service = port.service.as_ref();
+    let optional = |value: Option<&Option<String>>| value.cloned().flatten().unwrap_or_default();
+
+    vec![
+        host.target.clone(),
+        host.address().to_string(),
+        host.hostnames
+            .first()
+            .map(|hostname| hostname.name.clone())
+            .unwrap_or_default(),
+        host.status.as_str().to_string(),
+        port.protocol.as_str().to_string(),
+        port.port.to_string(),
+        port.state.as_str().to_string(),
+        port.reason.as_str().to_string(),
+        service.map(|s| s.name.clone()).unwrap_or_default(),
+        optional(service.map(|s| &s.product)),
+        optional(service.map(|s| &s.version)),
+        optional(service.map(|s| &s.extra_info)),
+        service.map(|s| s.cpe.join(" ")).unwrap_or_default(),
+    ]
+}
+
+/// Render every port of every host, separated by `delimiter`
+pub(super) fn render(report: &ScanReport, delimiter: char) -> String {
+    let separator = delimiter.to_string();
+    let mut out = HEADER.join(&separator);
+    out.push('\n');
+
+    for host in &report.hosts {
+        for port in &host.ports {
+            let cells: Vec<String> = row(host, port)
+                .iter()
+                .map(|value| cell(value, delimiter))
+                .collect();
+            out.push_str(&cells.join(&separator));
+            out.push('\n');
+        }
+    }
+
+    out
+}
@@ -0,0 +1,122 @@
+//! Nmap-style grepable output
+//!
+//! Every host gets a `Status` line and a `Ports` line with tab-separated sections, so results
+//! can be filtered with `grep`, `cut` and `awk`:
+//!
+//! ```text
+//! Host: 10.0.0.1 (router)  Ports: 22/open/tcp//ssh///, 80/open/tcp//http///  Ignored State: closed (998)
+//! ```
+
+use super::{collapsed_states, time_string};
+use crate::report::{HostReport, HostStatus, PortReport, ScanReport};
+use std::fmt::Write;
+
+/// Slashes and commas separate fields, so they cannot appear inside a field
+fn field(value: &str) -> String {
+    value.replace('/', "|").replace(',', ";")
+}
+
+fn host_prefix(host: &HostReport) -> String {
+    let hostname = host
+        .hostnames
+        .first()
+        .map(|hostname| hostname.name.as_str())
+        .unwrap_or_default();
+    format!("Host: {} ({})", host.address(), field(hostname))
+}
+
+fn port_entry(port: &PortReport) -> String {
+    let (service, version) = match &port.service {
+        Some(service) => {
+            let version = [&service.product, &service.version, &service.extra_info]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
+            (service.name.clone(), version)
+        }
+        None => (String::new(), String::new()),
+    };
+    format!(
+        "{}/{}/{}//{}//{}/",
+        port.port,
+        port.state.as_str(),
+        port.protocol.as_str(),
+        field(&service),
+        field(&version)
+    )
+}
+
+/// Render a scan report in grepable format
+pub(super) fn render(report: &ScanReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# rmap {} scan initiated {} as: {}",
+        report.metadata.version,
+        time_string(&report.metadata.start_time),
+        report.metadata.command_line.join(" ")
+    );
+
+    for host in &report.hosts {
+        let prefix = host_prefix(host);
+        let status = match host.status {
+            HostStatus::Up => "Up",
+            HostStatus::Unknown => "Unknown",
+        };
+        let _ = writeln!(out, "{}\tStatus: {}", prefix, status);
+
+        if host.ports.is_empty() {
+            continue;
+        }
+
+        let collapsed = collapsed_states(report, &host.ports);
+        let ports: Vec<String> = host
+            .ports
+            .iter()
+            .filter(|port| !collapsed.contains(&port.state))
+            .map(port_entry)
+            .collect();
+        let _ = write!(out, "{}\tPorts: {}", prefix, ports.join(", "));
+
+        // nmap reports a single ignored state, the most common collapsed one
+        let ignored = collapsed
+            .iter()
+            .map(|state| {
+                let count = host
+                    .ports
+                    .iter()
+                    .filter(|port| port.state == *state)
+                    .count();
+                (*state, count)
+            })
+            .max_by_key(|(_, count)| *count);
+        if let Some((state, count)) = ignored {
+            let _ = write!(out, "\tIgnored State: {} ({})", state.as_str(), count);
+        }
+        out.push('\n');
+    }
+
+    let up = report
+        .hosts
+        .iter()
+        .filter(|host| host.status == HostStatus::Up)
+        .count();
+    let total = report.hosts.len();
+    let _ = writeln!(
+        out,
+        "# rmap done at {} -- {} IP address{} ({} host{} up) scanned in {:.2} seconds",
+        time_string(&report.metadata.end_time),
+        total,
+        if total == 1 { "" } else { "es" },
+        up,
+        if up == 1 { "" } else { "s" },
+        report.metadata.elapsed_seconds
+    );
+    if report.metadata.partial {
+        out.push_str("# Scan interrupted, results are partial\n");
+    }
+
+    out
+}
@@ -0,0 +1,368 @@
+//! Self-contained HTML report
+//!
+//! Styles and the table sorting script are inlined so the file can be mailed or archived
+//! on its own.
+
+use super::{OutputHandler, collapsed_states};
+use crate::core::{PortState, ScriptResult};
+use crate::report::{HostReport, HostStatus, ScanReport};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 0; color: #1f2328; background: #f6f8fa; }
+header { background: #24292f; color: #fff; padding: 24px 40px; }
+header h1 { margin: 0 0 4px 0; font-size: 24px; }
+header p { margin: 0; color: #c9d1d9; font-size: 14px; }
+main { padding: 24px 40px; }
+section { background: #fff; border: 1px solid #d0d7de; border-radius: 6px; padding: 16px 24px; margin-bottom: 24px; }
+h2 { font-size: 18px; margin-top: 0; }
+h3 { font-size: 15px; margin-bottom: 8px; }
+.cards { display: flex; gap: 16px; flex-wrap: wrap; margin-bottom: 24px; }
+.card { background: #fff; border: 1px solid #d0d7de; border-radius: 6px; padding: 16px 24px; min-width: 140px; }
+.card .value { font-size: 28px; font-weight: 600; }
+.card .label { color: #57606a; font-size: 13px; }
+table { border-collapse: collapse; width: 100%; font-size: 14px; }
+th, td { text-align: left; padding: 6px 10px; border-bottom: 1px solid #d8dee4; vertical-align: top; }
+th { background: #f6f8fa; }
+th.sortable { cursor: pointer; user-select: none; }
+th.sortable::after { content: " \2195"; color: #8c959f; }
+.state-open { color: #1a7f37; font-weight: 600; }
+.state-closed { color: #cf222e; }
+.state-filtered, .state-open-filtered { color: #9a6700; }
+.status-up { color: #1a7f37; }
+.status-unknown { color: #57606a; }
+.muted { color: #57606a; font-size: 13px; }
+section.partial { background: #fff8c5; border-color: #d4a72c; }
+pre { background: #f6f8fa; padding: 8px 12px; border-radius: 6px; overflow-x: auto; margin: 4px 0 12px 0; }
+dl.meta { display: grid; grid-template-columns: max-content auto; gap: 4px 16px; margin: 0; font-size: 14px; }
+dl.meta dt { color: #57606a; }
+dl.meta dd { margin: 0; font-family: monospace; word-break: break-all; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th").forEach(function (th, index) {
+    th.classList.add("sortable");
+    th.addEventListener("click", function () {
+      var body = table.tBodies[0];
+      var ascending = th.dataset.order !== "asc";
+      th.dataset.order = ascending ? "asc" : "desc";
+      var rows = Array.prototype.slice.call(body.rows);
+      rows.sort(function (a, b) {
+        var x = a.cells[index].dataset.sort || a.cells[index].textContent;
+        var y = b.cells[index].dataset.sort || b.cells[index].textContent;
+        var nx = parseFloat(x), ny = parseFloat(y);
+        var result = (!isNaN(nx) && !isNaN(ny)) ? nx - ny : x.localeCompare(y);
+        return ascending ? result : -result;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});
+"#;
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn state_class(state: PortState) -> &'static str {
+    match state {
+        PortState::Open => "state-open",
+        PortState::Closed => "state-closed",
+        PortState::Filtered => "state-filtered",
+        PortState::OpenFiltered => "state-open-filtered",
+    }
+}
+
+/// Render a scan report as a standalone HTML page
+pub(super) fn render(report: &ScanReport) -> String {
+    let mut html = String::new();
+    let metadata = &report.metadata;
+
+    let hosts_up = report
+        .hosts
+        .iter()
+        .filter(|host| host.status == HostStatus::Up)
+        .count();
+    let open_ports: Vec<_> = report
+        .hosts
+        .iter()
+        .flat_map(|host| host.ports.iter())
+        .filter(|port| port.state == PortState::Open)
+        .collect();
+
+    let mut service_counts: HashMap<&str, usize> = HashMap::new();
+    for port in &open_ports {
+        let name = port
+            .service
+            .as_ref()
+            .map_or("unknown", |service| service.name.as_str());
+        *service_counts.entry(name).or_default() += 1;
+    }
+    let mut top_services: Vec<(&str, usize)> = service_counts.into_iter().collect();
+    top_services.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    top_services.truncate(10);
+
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>rmap scan report</title>\n<style>{}</style>\n</head>\n<body>\n",
+        STYLE
+    );
+    let _ = write!(
+        html,
+        "<header><h1>rmap scan report</h1><p>{} &ndash; {} UTC, {:.2} seconds</p></header>\n<main>\n",
+        metadata.start_time.format("%Y-%m-%d %H:%M:%S"),
+        metadata.end_time.format("%Y-%m-%d %H:%M:%S"),
+        metadata.elapsed_seconds
+    );
+
+    if metadata.partial {
+        html.push_str(
+            "<section class=\"partial\"><strong>Scan interrupted.</strong> This report only holds the results gathered before the scan was stopped.</section>\n",
+        );
+    }
+
+    // Summary dashboard
+    html.push_str("<div class=\"cards\">\n");
+    for (value, label) in [
+        (report.hosts.len(), "Hosts scanned"),
+        (hosts_up, "Hosts up"),
+        (open_ports.len(), "Open ports"),
+        (
+            report
+                .hosts
+                .iter()
+                .map(|host| {
+                    host.scripts.len()
+                        + host
+                            .ports
+                            .iter()
+                            .map(|port| port.scripts.len())
+                            .sum::<usize>()
+                })
+                .sum(),
+            "Script results",
+        ),
+    ] {
+        let _ = writeln!(
+            html,
+            "<div class=\"card\"><div class=\"value\">{}</div><div class=\"label\">{}</div></div>",
+            value, label
+        );
+    }
+    html.push_str("</div>\n");
+
+    if !top_services.is_empty() {
+        html.push_str("<section><h2>Top services</h2>\n<table class=\"sortable\"><thead><tr><th>Service</th><th>Open ports</th></tr></thead><tbody>\n");
+        for (name, count) in &top_services {
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", escape(name), count);
+        }
+        html.push_str("</tbody></table></section>\n");
+    }
+
+    for host in &report.hosts {
+        render_host(&mut html, report, host);
+    }
+
+    // Scan metadata
+    html.push_str("<section><h2>Scan information</h2>\n<dl class=\"meta\">\n");
+    for (label, value) in [
+        ("rmap version", metadata.version.clone()),
+        ("Command line", metadata.command_line.join(" ")),
+        ("Started", metadata.start_time.to_rfc3339()),
+        ("Finished", metadata.end_time.to_rfc3339()),
+        (
+            "Elapsed",
+            format!("{:.2} seconds", metadata.elapsed_seconds),
+        ),
+        ("Report schema", report.schema_version.clone()),
+    ] {
+        let _ = writeln!(html, "<dt>{}</dt><dd>{}</dd>", label, escape(&value));
+    }
+    html.push_str("</dl>\n");
+    if let Ok(config) = serde_json::to_string_pretty(&metadata.config) {
+        let _ = writeln!(html, "<h3>Configuration</h3><pre>{}</pre>", escape(&config));
+    }
+    html.push_str("</section>\n");
+
+    let _ = write!(
+        html,
+        "</main>\n<script>{}</script>\n</body>\n</html>\n",
+        SCRIPT
+    );
+    html
+}
+
+fn render_host(html: &mut String, report: &ScanReport, host: &HostReport) {
+    let title = if host.target == host.address() {
+        escape(&host.target)
+    } else {
+        format!("{} ({})", escape(&host.target), escape(host.address()))
+    };
+    let status = match host.status {
+        HostStatus::Up => "status-up",
+        HostStatus::Unknown => "status-unknown",
+    };
+    let _ = writeln!(
+        html,
+        "<section><h2>{} <span class=\"{}\">{}</span></h2>",
+        title,
+        status,
+        host.status.as_str()
+    );
+
+    let hostnames: Vec<&str> = host
+        .hostnames
+        .iter()
+        .map(|hostname| hostname.name.as_str())
+        .collect();
+    if !hostnames.is_empty() {
+        let _ = writeln!(
+            html,
+            "<p class=\"muted\">Hostnames: {}</p>",
+            escape(&hostnames.join(", "))
+        );
+    }
+
+    let collapsed = collapsed_states(report, &host.ports);
+    let listed: Vec<_> = host
+        .ports
+        .iter()
+        .filter(|port| !collapsed.contains(&port.state))
+        .collect();
+
+    if listed.is_empty() {
+        html.push_str("<p class=\"muted\">No ports to display</p>\n");
+    } else {
+        html.push_str("<table class=\"sortable\"><thead><tr><th>Port</th><th>Protocol</th><th>State</th><th>Reason</th><th>Service</th><th>Version</th></tr></thead><tbody>\n");
+        for port in &listed {
+            let (service, version) = match &port.service {
+                Some(service) => (
+                    service.name.clone(),
+                    [&service.product, &service.version, &service.extra_info]
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                None => (String::new(), String::new()),
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td data-sort=\"{}\">{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                port.port,
+                port.port,
+                port.protocol.as_str(),
+                state_class(port.state),
+                port.state.as_str(),
+                port.reason.as_str(),
+                escape(&service),
+                escape(&version)
+            );
+        }
+        html.push_str("</tbody></table>\n");
+    }
+
+    for state in &collapsed {
+        let count = host
+            .ports
+            .iter()
+            .filter(|port| port.state == *state)
+            .count();
+        let _ = writeln!(
+            html,
+            "<p class=\"muted\">Not shown: {} {} ports</p>",
+            count,
+            state.as_str()
+        );
+    }
+
+    let port_scripts: Vec<(String, &ScriptResult)> = host
+        .ports
+        .iter()
+        .flat_map(|port| {
+            port.scripts
+                .iter()
+                .map(move |script| (format!("{}/{}", port.port, port.protocol.as_str()), script))
+        })
+        .collect();
+    if !host.scripts.is_empty() || !port_scripts.is_empty() {
+        html.push_str("<h3>Script findings</h3>\n");
+        for script in &host.scripts {
+            render_script(html, "host", script);
+        }
+        for (location, script) in &port_scripts {
+            render_script(html, location, script);
+        }
+    }
+
+    for web in &host.web {
+        let _ = writeln!(
+            html,
+            "<h3>Web content on {}://{}:{}</h3>",
+            escape(&web.scheme),
+            escape(&web.host),
+            web.port
+        );
+        if web.findings.is_empty() {
+            let _ = writeln!(
+                html,
+                "<p class=\"muted\">No content found ({} requests)</p>",
+                web.requests
+            );
+            continue;
+        }
+        html.push_str("<table class=\"sortable\"><thead><tr><th>Path</th><th>Status</th><th>Size</th><th>Redirect</th></tr></thead><tbody>\n");
+        for finding in &web.findings {
+            let _ = writeln!(
+                html,
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&finding.url),
+                escape(&finding.path),
+                finding.status,
+                finding.size,
+                escape(finding.redirect.as_deref().unwrap_or_default())
+            );
+        }
+        html.push_str("</tbody></table>\n");
+    }
+
+    html.push_str("</section>\n");
+}
+
+fn render_script(html: &mut String, location: &str, script: &ScriptResult) {
+    let mut body = Vec::new();
+    if !script.success {
+        body.push(format!(
+            "ERROR: {}",
+            script.error.as_deref().unwrap_or("script execution failed")
+        ));
+    } else {
+        if !script.output.is_empty() {
+            body.push(script.output.clone());
+        }
+        if script.has_data() {
+            body.extend(OutputHandler::format_data_tree(&script.data, 0));
+        }
+    }
+    if body.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(
+        html,
+        "<p><strong>{}</strong> <span class=\"muted\">{}</span></p><pre>{}</pre>",
+        escape(&script.script_name),
+        escape(location),
+        escape(&body.join("\n"))
+    );
+}
@@ -0,0 +1,633 @@
+use crate::args::Config;
+use crate::core::web::WebEnumResult;
+use crate::core::{PortState, ScriptInfo, ScriptResult, ServiceInfo};
+use crate::diff::{Change, ScanDiff};
+use crate::report::{PortReport, Protocol, ScanReport};
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use std::error::Error;
+use tabled::{Table, Tabled};
+
+mod delimited;
+mod grepable;
+mod html;
+mod ndjson;
+mod progress;
+mod xml;
+
+pub use ndjson::NdjsonWriter;
+pub use progress::ProgressReporter;
+
+/// Closed, filtered and open|filtered ports are summarised instead of listed above this
+/// count, as nmap does
+const COLLAPSE_THRESHOLD: usize = 25;
+
+/// Time in the format nmap uses in its output files
+fn time_string(time: &DateTime<Utc>) -> String {
+    time.format("%a %b %e %H:%M:%S %Y").to_string()
+}
+
+/// Port states that are too common to list port by port
+///
+/// Nothing is collapsed when the user asked for the ports explicitly.
+fn collapsed_states(report: &ScanReport, ports: &[PortReport]) -> Vec<PortState> {
+    if report.metadata.ports_explicitly_specified {
+        return Vec::new();
+    }
+    [
+        PortState::Closed,
+        PortState::Filtered,
+        PortState::OpenFiltered,
+    ]
+    .into_iter()
+    .filter(|state| ports.iter().filter(|port| port.state == *state).count() > COLLAPSE_THRESHOLD)
+    .collect()
+}
+
+#[derive(Tabled)]
+struct PortRow {
+    #[tabled(rename = "PORT")]
+    port: String,
+    #[tabled(rename = "STATE")]
+    state: String,
+    #[tabled(rename = "REASON")]
+    reason: String,
+    #[tabled(rename = "SERVICE")]
+    service: String,
+}
+
+#[derive(Tabled)]
+struct ScriptRow {
+    #[tabled(rename = "NAME")]
+    name: String,
+    #[tabled(rename = "CATEGORIES")]
+    categories: String,
+    #[tabled(rename = "DESCRIPTION")]
+    description: String,
+}
+
+#[derive(Tabled)]
+struct WebRow {
+    #[tabled(rename = "PATH")]
+    path: String,
+    #[tabled(rename = "STATUS")]
+    status: u16,
+    #[tabled(rename = "SIZE")]
+    size: usize,
+    #[tabled(rename = "REDIRECT")]
+    redirect: String,
+}
+
+#[derive(Tabled)]
+struct DiffRow {
+    #[tabled(rename = "HOST")]
+    host: String,
+    #[tabled(rename = "PORT")]
+    port: String,
+    #[tabled(rename = "CHANGE")]
+    change: String,
+    #[tabled(rename = "OLD")]
+    old: String,
+    #[tabled(rename = "NEW")]
+    new: String,
+}
+
+/// Host column of the diff table, the target followed by its address when they differ
+fn diff_host(target: &str, address: &str) -> String {
+    if target == address {
+        target.to_string()
+    } else {
+        format!("{} ({})", target, address)
+    }
+}
+
+/// Service name with product, version and extra info when known
+fn service_string(service: Option<&ServiceInfo>) -> String {
+    let Some(service) = service else {
+        return String::new();
+    };
+    [&service.product, &service.version, &service.extra_info]
+        .into_iter()
+        .flatten()
+        .fold(service.name.clone(), |text, part| text + " " + part)
+}
+
+pub struct OutputHandler;
+
+impl Default for OutputHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputHandler {
+    pub fn new() -> OutputHandler {
+        OutputHandler
+    }
+
+    /// Get the most likely service for a given port
+    pub(crate) fn get_service_for_port(port: &str) -> &'static str {
+        match port {
+            "21" => "ftp",
+            "22" => "ssh",
+            "23" => "telnet",
+            "25" => "smtp",
+            "53" => "dns",
+            "80" => "http",
+            "110" => "pop3",
+            "115" => "sftp",
+            "135" => "rpc",
+            "139" => "netbios",
+            "143" => "imap",
+            "194" => "irc",
+            "443" => "https",
+            "445" => "smb",
+            "993" => "imaps",
+            "995" => "pop3s",
+            "1433" => "mssql",
+            "3306" => "mysql",
+            "3389" => "rdp",
+            "5432" => "postgresql",
+            "5632" => "pcanywhere",
+            "5900" => "vnc",
+            "25565" => "minecraft",
+            "27017" => "mongodb",
+            "6379" => "redis",
+            "5672" => "rabbitmq",
+            "8080" => "http-proxy",
+            "8443" => "https-alt",
+            "9200" => "elasticsearch",
+            "5601" => "kibana",
+            "2181" => "zookeeper",
+            "9092" => "kafka",
+            "11211" => "memcached",
+            "1521" => "oracle",
+            "5984" => "couchdb",
+            "7000" => "cassandra",
+            "8086" => "influxdb",
+            "9000" => "sonarqube",
+            "8888" => "jupyter",
+            "3000" => "grafana",
+            "9090" => "prometheus",
+            "4444" => "selenium",
+            "8081" => "nexus",
+            "8082" => "sonatype",
+            "9999" => "abyss",
+            "10000" => "webmin",
+            _ => "unknown",
+        }
+    }
+
+    pub fn out_results(&self, ports: &[PortReport], protocol: &str, show_all_states: bool) {
+        self.out_results_with_ports_info(ports, protocol, show_all_states);
+    }
+
+    /// Print the port table of one host
+    ///
+    /// # Arguments
+    ///
+    /// * `ports` - Every scanned port of the host
+    /// * `protocol` - Protocol name used in the heading and port column
+    /// * `show_all_states` - List closed and filtered ports too. Otherwise only open and
+    ///   open|filtered ports are listed.
+    pub fn out_results_with_ports_info(
+        &self,
+        ports: &[PortReport],
+        protocol: &str,
+        show_all_states: bool,
+    ) {
+        if ports.is_empty() {
+            println!("No ports found for {} scan", protocol.to_uppercase());
+            return;
+        }
+
+        // Sort ports numerically for better display
+        let mut sorted_ports: Vec<_> = ports.iter().collect();
+        sorted_ports.sort_by_key(|port| port.port);
+
+        let rows: Vec<PortRow> = sorted_ports
+            .iter()
+            .filter(|port| {
+                show_all_states || matches!(port.state, PortState::Open | PortState::OpenFiltered)
+            })
+            .map(|port| PortRow {
+                port: format!("{}/{}", port.port, protocol.to_lowercase()),
+                state: port.state.as_str().to_string(),
+                reason: port.reason.as_str().to_string(),
+                service: match &port.service {
+                    Some(service) => service_string(Some(service)),
+                    None => "unknown".to_string(),
+                },
+            })
+            .collect();
+
+        // Create and display table
+        println!("\n{} Scan Results:", protocol.to_uppercase());
+        let table = Table::new(rows);
+        println!("{}", table);
+
+        let count = |state: PortState| ports.iter().filter(|port| port.state == state).count();
+
+        // Print summary
+        let open_count = count(PortState::Open);
+        let closed_count = count(PortState::Closed);
+        let filtered_count = count(PortState::Filtered);
+        let open_filtered_count = count(PortState::OpenFiltered);
+
+        if open_filtered_count > 0 {
+            println!(
+                "Summary: {} open, {} open|filtered, {} closed, {} filtered",
+                open_count, open_filtered_count, closed_count, filtered_count
+            );
+        } else {
+            println!(
+                "Summary: {} open, {} closed, {} filtered",
+                open_count, closed_count, filtered_count
+            );
+        }
+    }
+
+    /// Print the result tables, script results and web findings of every host
+    pub fn out_report(&self, report: &ScanReport, show_all_states: bool) {
+        for host in &report.hosts {
+            match host.ptr_name() {
+                _ if host.target != host.address() => println!(
+                    "\nrmap scan report for {} ({})",
+                    host.target,
+                    host.address()
+                ),
+                Some(name) => println!("\nrmap scan report for {} ({})", name, host.address()),
+                None => println!("\nrmap scan report for {}", host.target),
+            }
+            // Like nmap, mention the PTR name when it differs from the name scanned
+            if let Some(name) = host.ptr_name()
+                && host.target != host.address()
+                && !name.eq_ignore_ascii_case(&host.target)
+            {
+                println!("rDNS record for {}: {}", host.address(), name);
+            }
+
+            for protocol in [Protocol::Tcp, Protocol::Udp] {
+                let ports: Vec<PortReport> = host
+                    .ports
+                    .iter()
+                    .filter(|port| port.protocol == protocol)
+                    .cloned()
+                    .collect();
+                if !ports.is_empty() {
+                    self.out_results_with_ports_info(&ports, protocol.as_str(), show_all_states);
+                }
+            }
+            if host.ports.is_empty() {
+                println!("No ports to display");
+            }
+
+            for script_result in &host.scripts {
+                println!("\nScript: {}", script_result.script_name);
+                println!("------------------------------------------------------------");
+                self.out_script_result(script_result);
+            }
+            for port in &host.ports {
+                for script_result in &port.scripts {
+                    // Scripts that do not apply to a port stay quiet
+                    if script_result.success
+                        && (!script_result.output.is_empty() || script_result.has_data())
+                    {
+                        println!(
+                            "\nPort {} Script Results ({}):",
+                            port.port, script_result.script_name
+                        );
+                        self.out_script_result(script_result);
+                    }
+                }
+            }
+
+            for web in &host.web {
+                self.out_web_results(web);
+            }
+        }
+
+        if report.metadata.partial {
+            println!("\nScan interrupted, results are partial");
+        }
+    }
+
+    /// Write the scan report as a single JSON document
+    pub fn out_json(
+        &self,
+        report: &ScanReport,
+        file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        report.write_json(file_path)?;
+
+        eprintln!("JSON output written to: {}", file_path);
+        Ok(())
+    }
+
+    /// Write the scan report as nmap-compatible XML
+    pub fn out_xml(
+        &self,
+        report: &ScanReport,
+        file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(file_path, xml::render(report))?;
+
+        eprintln!("XML output written to: {}", file_path);
+        Ok(())
+    }
+
+    /// Write the scan report in nmap's grepable format, one line per host
+    pub fn out_grepable(
+        &self,
+        report: &ScanReport,
+        file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(file_path, grepable::render(report))?;
+
+        eprintln!("Grepable output written to: {}", file_path);
+        Ok(())
+    }
+
+    /// Write one comma-separated row per host and port
+    pub fn out_csv(
+        &self,
+        report: &ScanReport,
+        file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(file_path, delimited::render(report, ','))?;
+
+        eprintln!("CSV output written to: {}", file_path);
+        Ok(())
+    }
+
+    /// Write one tab-separated row per host and port
+    pub fn out_tsv(
+        &self,
+        report: &ScanReport,
+        file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(file_path, delimited::render(report, '\t'))?;
+
+        eprintln!("TSV output written to: {}", file_path);
+        Ok(())
+    }
+
+    /// Write the scan report as a self-contained HTML page
+    pub fn out_html(
+        &self,
+        report: &ScanReport,
+        file_path: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(file_path, html::render(report))?;
+
+        eprintln!("HTML report written to: {}", file_path);
+        Ok(())
+    }
+
+    /// Print the differences between two reports as a table
+    pub fn out_diff(&self, diff: &ScanDiff) {
+        println!(
+            "Comparing scan of {} with scan of {}",
+            diff.old_start_time.format("%Y-%m-%d %H:%M:%S UTC"),
+            diff.new_start_time.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+        if diff.is_empty() {
+            println!("No differences found");
+            return;
+        }
+
+        let rows: Vec<DiffRow> = diff
+            .changes
+            .iter()
+            .map(|change| {
+                let kind = change.kind().to_string();
+                match change {
+                    Change::HostAdded { target, address } => DiffRow {
+                        host: diff_host(target, address),
+                        port: String::new(),
+                        change: kind,
+                        old: "down".to_string(),
+                        new: "up".to_string(),
+                    },
+                    Change::HostRemoved { target, address } => DiffRow {
+                        host: diff_host(target, address),
+                        port: String::new(),
+                        change: kind,
+                        old: "up".to_string(),
+                        new: "down".to_string(),
+                    },
+                    Change::PortOpened {
+                        target,
+                        address,
+                        protocol,
+                        port,
+                        previous,
+                        service,
+                    } => DiffRow {
+                        host: diff_host(target, address),
+                        port: format!("{}/{}", port, protocol.as_str()),
+                        change: kind,
+                        old: previous
+                            .map_or("not scanned", |state| state.as_str())
+                            .to_string(),
+                        new: format!("open {}", service_string(service.as_ref()))
+                            .trim_end()
+                            .to_string(),
+                    },
+                    Change::PortClosed {
+                        target,
+                        address,
+                        protocol,
+                        port,
+                        current,
+                        service,
+                    } => DiffRow {
+                        host: diff_host(target, address),
+                        port: format!("{}/{}", port, protocol.as_str()),
+                        change: kind,
+                        old: format!("open {}", service_string(service.as_ref()))
+                            .trim_end()
+                            .to_string(),
+                        new: current
+                            .map_or("not scanned", |state| state.as_str())
+                            .to_string(),
+                    },
+                    Change::ServiceChanged {
+                        target,
+                        address,
+                        protocol,
+                        port,
+                        previous,
+                        current,
+                    } => DiffRow {
+                        host: diff_host(target, address),
+                        port: format!("{}/{}", port, protocol.as_str()),
+                        change: kind,
+                        old: service_string(previous.as_ref()),
+                        new: service_string(current.as_ref()),
+                    },
+                }
+            })
+            .collect();
+
+        println!("{}", Table::new(rows));
+        match diff.changes.len() {
+            1 => println!("1 difference"),
+            count => println!("{} differences", count),
+        }
+    }
+
+    /// Format a script data value as an indented tree, one line per entry
+    pub(crate) fn format_data_tree(value: &JsonValue, indent: usize) -> Vec<String> {
+        let pad = "  ".repeat(indent);
+        let mut lines = Vec::new();
+        match value {
+            JsonValue::Object(map) if !map.is_empty() => {
+                for (key, child) in map {
+                    match Self::scalar_to_string(child) {
+                        Some(scalar) => lines.push(format!("{}{}: {}", pad, key, scalar)),
+                        None => {
+                            lines.push(format!("{}{}:", pad, key));
+                            lines.extend(Self::format_data_tree(child, indent + 1));
+                        }
+                    }
+                }
+            }
+            JsonValue::Array(items) if !items.is_empty() => {
+                for item in items {
+                    match Self::scalar_to_string(item) {
+                        Some(scalar) => lines.push(format!("{}- {}", pad, scalar)),
+                        None => {
+                            lines.push(format!("{}-", pad));
+                            lines.extend(Self::format_data_tree(item, indent + 1));
+                        }
+                    }
+                }
+            }
+            other => lines.push(format!(
+                "{}{}",
+                pad,
+                Self::scalar_to_string(other).unwrap_or_default()
+            )),
+        }
+        lines
+    }
+
+    /// Render leaf values (and empty containers) on a single line
+    pub(crate) fn scalar_to_string(value: &JsonValue) -> Option<String> {
+        match value {
+            JsonValue::Null => Some("null".to_string()),
+            JsonValue::Bool(b) => Some(b.to_string()),
+            JsonValue::Number(n) => Some(n.to_string()),
+            JsonValue::String(s) => Some(s.clone()),
+            JsonValue::Array(items) if items.is_empty() => Some("[]".to_string()),
+            JsonValue::Object(map) if map.is_empty() => Some("{}".to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn out_script_result(&self, result: &ScriptResult) {
+        if result.success {
+            if !result.output.is_empty() {
+                println!("Output: {}", result.output);
+            }
+
+            if result.has_data() {
+                println!("Data:");
+                for line in Self::format_data_tree(&result.data, 1) {
+                    println!("{}", line);
+                }
+            }
+
+            if result.output.is_empty() && !result.has_data() {
+                println!("Script executed successfully (no output)");
+            }
+        } else {
+            if let Some(error) = &result.error {
+                println!("Script failed: {}", error);
+            } else {
+                println!("Script failed (unknown error)");
+            }
+        }
+    }
+
+    pub fn out_script_list(&self, scripts: &[ScriptInfo]) {
+        if scripts.is_empty() {
+            println!("No scripts found");
+            return;
+        }
+
+        let rows: Vec<ScriptRow> = scripts
+            .iter()
+            .map(|script| ScriptRow {
+                name: script.name.clone(),
+                categories: script.categories.join(", "),
+                description: script.description.clone().unwrap_or_default(),
+            })
+            .collect();
+
+        println!("{}", Table::new(rows));
+    }
+
+    /// Print the configuration as YAML, commenting every key with where its value came from
+    pub fn out_config(&self, config: &Config) -> Result<(), Box<dyn Error>> {
+        let serde_yaml::Value::Mapping(values) = serde_yaml::to_value(config)? else {
+            return Err("configuration is not a mapping".into());
+        };
+
+        let mut lines = Vec::new();
+        for (key, value) in &values {
+            let key = key.as_str().unwrap_or_default();
+            // Lists and maps stay on the line of their key so the comment lines up
+            let value = match value {
+                serde_yaml::Value::Sequence(_) | serde_yaml::Value::Mapping(_) => {
+                    serde_json::to_string(value)?
+                }
+                _ => serde_yaml::to_string(value)?.trim_end().to_string(),
+            };
+            lines.push((
+                format!("{}: {}", key, value),
+                config.source(key).to_string(),
+            ));
+        }
+
+        let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        for (line, source) in lines {
+            println!("{:<width$}  # {}", line, source, width = width);
+        }
+        Ok(())
+    }
+
+    pub fn out_web_results(&self, result: &WebEnumResult) {
+        println!(
+            "\nWeb Content Discovery: {}://{}:{}",
+            result.scheme, result.host, result.port
+        );
+        if result.wildcard {
+            println!("Note: server answers unknown paths with success, soft-404 filtering applied");
+        }
+
+        if result.findings.is_empty() {
+            println!("No content found ({} requests)", result.requests);
+            return;
+        }
+
+        let rows: Vec<WebRow> = result
+            .findings
+            .iter()
+            .map(|finding| WebRow {
+                path: finding.path.clone(),
+                status: finding.status,
+                size: finding.size,
+                redirect: finding.redirect.clone().unwrap_or_default(),
+            })
+            .collect();
+
+        println!("{}", Table::new(rows));
+        println!(
+            "Summary: {} found, {} requests",
+            result.findings.len(),
+            result.requests
+        );
+    }
+}
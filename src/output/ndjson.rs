@@ -0,0 +1,54 @@
+use crate::events::{ScanEvent, ScanObserver, TimedEvent};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+
+/// Streams scan events as newline-delimited JSON while the scan runs
+///
+/// Every event is flushed as soon as it is written, so consumers see results live and a
+/// crashed scan keeps everything emitted so far.
+pub struct NdjsonWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl NdjsonWriter {
+    /// Open an event stream, `-` writes to stdout
+    pub fn create(path: &str) -> Result<NdjsonWriter, Box<dyn Error>> {
+        let out: Box<dyn Write + Send> = if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(NdjsonWriter {
+            out: Mutex::new(out),
+        })
+    }
+
+    /// Write one event line, failures are reported on stderr without stopping the scan
+    pub fn emit(&self, event: ScanEvent) {
+        let line = match serde_json::to_string(&TimedEvent::now(event)) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Error serializing event: {}", e);
+                return;
+            }
+        };
+
+        let mut out = match self.out.lock() {
+            Ok(out) => out,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(e) = writeln!(out, "{}", line).and_then(|_| out.flush()) {
+            eprintln!("Error writing event stream: {}", e);
+        }
+    }
+}
+
+impl ScanObserver for NdjsonWriter {
+    fn on_event(&self, event: &ScanEvent) {
+        if !matches!(event, ScanEvent::Progress { .. }) {
+            self.emit(event.clone());
+        }
+    }
+}
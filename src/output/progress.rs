@@ -0,0 +1,172 @@
+use crate::events::{ScanEvent, ScanObserver};
+use crate::report::Protocol;
+use crate::utils::parse_ports;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Host bars shown at once, hosts beyond these only count towards the overall bar
+const MAX_HOST_BARS: usize = 8;
+
+/// Draws an overall progress bar and one bar per active host and protocol on stderr
+///
+/// Bars are stacked with a [`MultiProgress`] so hosts scanned in parallel do not overwrite
+/// each other. At most [`MAX_HOST_BARS`] hosts have a bar, a host gets one on its next
+/// progress event once another host's bar is cleared. Hosts are forgotten when they finish.
+///
+/// Errors of the scan are printed too, this is the observer of the command line.
+pub struct ProgressReporter {
+    multi: MultiProgress,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Probes of every host, counted from the targets and ports when the scan starts
+    overall: Option<ProgressBar>,
+    hosts: HashMap<(String, String, Protocol), HostBar>,
+}
+
+/// Progress of a host that has not finished yet
+struct HostBar {
+    done: usize,
+    total: usize,
+    /// `None` while every bar is taken or once the host's probes are done
+    bar: Option<ProgressBar>,
+}
+
+impl Default for ProgressReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressReporter {
+    pub fn new() -> ProgressReporter {
+        ProgressReporter {
+            multi: MultiProgress::new(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    fn new_bar(&self, prefix: &str, colors: &str, total: usize) -> ProgressBar {
+        let bar = self.multi.add(ProgressBar::new(total as u64));
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template(&format!(
+                    "{{prefix}} {{percent:>3}}%|{{bar:25.{}}}| {{pos}}/{{len}} [{{elapsed_precise}}<{{eta_precise}}, {{per_sec}}]",
+                    colors
+                ))
+                .unwrap()
+                .progress_chars("█▉▊▋▌▍▎▏ "),
+        );
+        bar.set_prefix(prefix.to_string());
+        bar
+    }
+
+    fn new_host_bar(&self, target: &str, protocol: Protocol, total: usize) -> ProgressBar {
+        let colors = match protocol {
+            Protocol::Tcp => "red/bright_red",
+            Protocol::Udp => "cyan/blue",
+        };
+        self.new_bar(&format!("{} {}", protocol.as_str(), target), colors, total)
+    }
+
+    fn clear(&self, bar: ProgressBar) {
+        bar.finish_and_clear();
+        self.multi.remove(&bar);
+    }
+}
+
+impl ScanObserver for ProgressReporter {
+    fn on_event(&self, event: &ScanEvent) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let state = &mut *state;
+
+        match event {
+            ScanEvent::ScanStarted {
+                targets,
+                ports,
+                protocols,
+                ..
+            } => {
+                let total = targets.len() * parse_ports(ports).len() * protocols.len();
+                state.overall = Some(self.new_bar("all", "green/bright_green", total));
+            }
+            ScanEvent::Progress {
+                target,
+                address,
+                protocol,
+                done,
+                total,
+                ..
+            } => {
+                let visible = state
+                    .hosts
+                    .values()
+                    .filter(|host| host.bar.is_some())
+                    .count();
+                let host = state
+                    .hosts
+                    .entry((target.clone(), address.clone(), *protocol))
+                    .or_insert(HostBar {
+                        done: 0,
+                        total: *total,
+                        bar: None,
+                    });
+                if *done <= host.done {
+                    return;
+                }
+                let advanced = done - host.done;
+                host.done = *done;
+
+                if host.done < host.total && host.bar.is_none() && visible < MAX_HOST_BARS {
+                    host.bar = Some(self.new_host_bar(target, *protocol, host.total));
+                }
+                if let Some(bar) = &host.bar {
+                    bar.set_position(host.done as u64);
+                }
+                if host.done >= host.total
+                    && let Some(bar) = host.bar.take()
+                {
+                    self.clear(bar);
+                }
+
+                if let Some(overall) = &state.overall {
+                    overall.inc(advanced as u64);
+                }
+            }
+            ScanEvent::HostFinished {
+                target,
+                address,
+                protocol,
+                ..
+            } => {
+                if let Some(host) =
+                    state
+                        .hosts
+                        .remove(&(target.clone(), address.clone(), *protocol))
+                    && let Some(bar) = host.bar
+                {
+                    self.clear(bar);
+                }
+            }
+            // Printed above the bars so they are not drawn over
+            ScanEvent::Error { message, .. } => self.multi.suspend(|| eprintln!("{}", message)),
+            ScanEvent::ScanFinished { .. } => {
+                for (_, host) in state.hosts.drain() {
+                    if let Some(bar) = host.bar {
+                        self.clear(bar);
+                    }
+                }
+                if let Some(overall) = state.overall.take() {
+                    self.clear(overall);
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Nmap-compatible XML output
+//!
+//! Follows the element layout of nmap's `nmap.dtd` (XML output version 1.05) so the files
+//! can be read by tools that import nmap XML.
+
+use super::{OutputHandler, collapsed_states, time_string};
+use crate::core::{PortState, ScriptResult};
+use crate::report::{HostReport, HostStatus, PortReport, Protocol, ScanReport};
+use crate::utils::parse_ports;
+use serde_json::Value as JsonValue;
+use std::fmt::Write;
+
+const XML_OUTPUT_VERSION: &str = "1.05";
+
+/// Escape text for use in XML attributes and character data
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#xa;"),
+            '\r' => escaped.push_str("&#xd;"),
+            '\t' => escaped.push_str("&#x9;"),
+            // Control characters are not allowed in XML 1.0
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Render a scan report as an nmap XML document
+pub(super) fn render(report: &ScanReport) -> String {
+    let config = &report.metadata.config;
+    let config_bool = |key: &str| config.get(key).and_then(JsonValue::as_bool);
+    let ports_spec = config
+        .get("ports")
+        .and_then(JsonValue::as_str)
+        .unwrap_or_default();
+    let verbose = config_bool("verbose").unwrap_or(false);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<!DOCTYPE nmaprun>\n");
+    let _ = writeln!(
+        xml,
+        "<nmaprun scanner=\"rmap\" args=\"{}\" start=\"{}\" startstr=\"{}\" version=\"{}\" xmloutputversion=\"{}\">",
+        escape(&report.metadata.command_line.join(" ")),
+        report.metadata.start_time.timestamp(),
+        time_string(&report.metadata.start_time),
+        escape(&report.metadata.version),
+        XML_OUTPUT_VERSION
+    );
+
+    let num_services = parse_ports(ports_spec).len();
+    for (enabled, scan_type, protocol) in [
+        (config_bool("tcp").unwrap_or(true), "connect", "tcp"),
+        (config_bool("udp").unwrap_or(false), "udp", "udp"),
+    ] {
+        if enabled {
+            let _ = writeln!(
+                xml,
+                "<scaninfo type=\"{}\" protocol=\"{}\" numservices=\"{}\" services=\"{}\"/>",
+                scan_type,
+                protocol,
+                num_services,
+                escape(ports_spec)
+            );
+        }
+    }
+    let _ = writeln!(xml, "<verbose level=\"{}\"/>", u8::from(verbose));
+    xml.push_str("<debugging level=\"0\"/>\n");
+
+    for host in &report.hosts {
+        render_host(&mut xml, report, host);
+    }
+
+    let up = report
+        .hosts
+        .iter()
+        .filter(|host| host.status == HostStatus::Up)
+        .count();
+    let total = report.hosts.len();
+    let end_time = &report.metadata.end_time;
+    let elapsed = format!("{:.2}", report.metadata.elapsed_seconds);
+    // nmap reports runs that did not complete with an error exit status
+    let exit = if report.metadata.partial {
+        "exit=\"error\" errormsg=\"Scan interrupted, results are partial\""
+    } else {
+        "exit=\"success\""
+    };
+    xml.push_str("<runstats>");
+    let _ = write!(
+        xml,
+        "<finished time=\"{}\" timestr=\"{}\" summary=\"rmap done at {}; {} IP address{} ({} host{} up) scanned in {} seconds\" elapsed=\"{}\" {}/>",
+        end_time.timestamp(),
+        time_string(end_time),
+        time_string(end_time),
+        total,
+        if total == 1 { "" } else { "es" },
+        up,
+        if up == 1 { "" } else { "s" },
+        elapsed,
+        elapsed,
+        exit
+    );
+    let _ = write!(
+        xml,
+        "<hosts up=\"{}\" down=\"{}\" total=\"{}\"/>",
+        up,
+        total - up,
+        total
+    );
+    xml.push_str("</runstats>\n</nmaprun>\n");
+
+    xml
+}
+
+fn render_host(xml: &mut String, report: &ScanReport, host: &HostReport) {
+    xml.push_str("<host>");
+    let (state, reason) = match host.status {
+        HostStatus::Up => (
+            "up",
+            host.ports
+                .iter()
+                .find(|port| port.reason.is_reply())
+                .map_or("user-set", |port| port.reason.as_str()),
+        ),
+        HostStatus::Unknown => ("unknown", "no-response"),
+    };
+    let _ = writeln!(
+        xml,
+        "<status state=\"{}\" reason=\"{}\" reason_ttl=\"0\"/>",
+        state, reason
+    );
+
+    for address in &host.addresses {
+        let _ = writeln!(
+            xml,
+            "<address addr=\"{}\" addrtype=\"{}\"/>",
+            escape(&address.addr),
+            escape(&address.addr_type)
+        );
+    }
+
+    xml.push_str("<hostnames>\n");
+    for hostname in &host.hostnames {
+        let _ = writeln!(
+            xml,
+            "<hostname name=\"{}\" type=\"{}\"/>",
+            escape(&hostname.name),
+            escape(&hostname.source)
+        );
+    }
+    xml.push_str("</hostnames>\n");
+
+    xml.push_str("<ports>");
+    // Summarise large groups of uninteresting ports like nmap's "Not shown" line
+    let collapsed = collapsed_states(report, &host.ports);
+    for state in &collapsed {
+        render_extraports(xml, host, *state);
+    }
+    for port in host
+        .ports
+        .iter()
+        .filter(|port| !collapsed.contains(&port.state))
+    {
+        render_port(xml, port);
+    }
+    xml.push_str("</ports>\n");
+
+    if !host.scripts.is_empty() {
+        xml.push_str("<hostscript>");
+        for script in &host.scripts {
+            render_script(xml, script);
+        }
+        xml.push_str("</hostscript>\n");
+    }
+
+    xml.push_str("</host>\n");
+}
+
+fn render_extraports(xml: &mut String, host: &HostReport, state: PortState) {
+    let ports: Vec<&PortReport> = host
+        .ports
+        .iter()
+        .filter(|port| port.state == state)
+        .collect();
+    let _ = writeln!(
+        xml,
+        "<extraports state=\"{}\" count=\"{}\">",
+        state.as_str(),
+        ports.len()
+    );
+
+    for protocol in [Protocol::Tcp, Protocol::Udp] {
+        let mut reasons: Vec<&str> = Vec::new();
+        for port in ports.iter().filter(|port| port.protocol == protocol) {
+            if !reasons.contains(&port.reason.as_str()) {
+                reasons.push(port.reason.as_str());
+            }
+        }
+        for reason in reasons {
+            let matching: Vec<String> = ports
+                .iter()
+                .filter(|port| port.protocol == protocol && port.reason.as_str() == reason)
+                .map(|port| port.port.to_string())
+                .collect();
+            let _ = writeln!(
+                xml,
+                "<extrareasons reason=\"{}\" count=\"{}\" proto=\"{}\" ports=\"{}\"/>",
+                reason,
+                matching.len(),
+                protocol.as_str(),
+                matching.join(",")
+            );
+        }
+    }
+
+    xml.push_str("</extraports>\n");
+}
+
+fn render_port(xml: &mut String, port: &PortReport) {
+    let _ = write!(
+        xml,
+        "<port protocol=\"{}\" portid=\"{}\"><state state=\"{}\" reason=\"{}\" reason_ttl=\"0\"/>",
+        port.protocol.as_str(),
+        port.port,
+        port.state.as_str(),
+        port.reason.as_str()
+    );
+
+    if let Some(service) = &port.service {
+        let _ = write!(xml, "<service name=\"{}\"", escape(&service.name));
+        for (attribute, value) in [
+            ("product", &service.product),
+            ("version", &service.version),
+            ("extrainfo", &service.extra_info),
+        ] {
+            if let Some(value) = value {
+                let _ = write!(xml, " {}=\"{}\"", attribute, escape(value));
+            }
+        }
+        // Services named from the port table alone are only a guess
+        match service.confidence {
+            Some(confidence) => {
+                let _ = write!(xml, " method=\"probed\" conf=\"{}\"", confidence);
+            }
+            None => xml.push_str(" method=\"table\" conf=\"3\""),
+        }
+        if service.cpe.is_empty() {
+            xml.push_str("/>");
+        } else {
+            xml.push('>');
+            for cpe in &service.cpe {
+                let _ = write!(xml, "<cpe>{}</cpe>", escape(cpe));
+            }
+            xml.push_str("</service>");
+        }
+    }
+
+    for script in &port.scripts {
+        render_script(xml, script);
+    }
+
+    xml.push_str("</port>\n");
+}
+
+fn render_script(xml: &mut String, script: &ScriptResult) {
+    let output = if !script.success {
+        format!(
+            "ERROR: {}",
+            script.error.as_deref().unwrap_or("script execution failed")
+        )
+    } else if !script.output.is_empty() {
+        script.output.clone()
+    } else if script.has_data() {
+        format!(
+            "\n{}",
+            OutputHandler::format_data_tree(&script.data, 1).join("\n")
+        )
+    } else {
+        String::new()
+    };
+
+    let _ = write!(
+        xml,
+        "<script id=\"{}\" output=\"{}\"",
+        escape(&script.script_name),
+        escape(&output)
+    );
+    if script.success && script.has_data() {
+        xml.push('>');
+        render_script_data(xml, None, &script.data, true);
+        xml.push_str("</script>");
+    } else {
+        xml.push_str("/>");
+    }
+}
+
+/// Render structured script data as nested `<table>` and `<elem>` elements
+///
+/// The top level container is the `<script>` element itself, so it is not wrapped in a table.
+fn render_script_data(xml: &mut String, key: Option<&str>, value: &JsonValue, top_level: bool) {
+    let key_attribute = key
+        .map(|key| format!(" key=\"{}\"", escape(key)))
+        .unwrap_or_default();
+
+    match value {
+        JsonValue::Object(_) | JsonValue::Array(_) => {
+            if !top_level {
+                let _ = write!(xml, "<table{}>", key_attribute);
+            }
+            match value {
+                JsonValue::Object(map) => {
+                    for (child_key, child) in map {
+                        render_script_data(xml, Some(child_key), child, false);
+                    }
+                }
+                JsonValue::Array(items) => {
+                    for item in items {
+                        render_script_data(xml, None, item, false);
+                    }
+                }
+                _ => {}
+            }
+            if !top_level {
+                xml.push_str("</table>");
+            }
+        }
+        scalar => {
+            let _ = write!(
+                xml,
+                "<elem{}>{}</elem>",
+                key_attribute,
+                escape(&OutputHandler::scalar_to_string(scalar).unwrap_or_default())
+            );
+        }
+    }
+}
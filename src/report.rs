@@ -0,0 +1,268 @@
+//! The scan report: every host, port and script result of one run
+//!
+//! The JSON serialization of [`ScanReport`] is a public format documented in
+//! `docs/report-schema.md`. Bump [`SCHEMA_VERSION`] whenever a field is removed, renamed or
+//! changes meaning; adding optional fields does not require a new version.
+
+use crate::args::Config;
+use crate::core::web::WebEnumResult;
+use crate::core::{PortReason, PortState, ScriptResult, ServiceInfo};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
+
+/// Version of the report document layout
+pub const SCHEMA_VERSION: &str = "1.0";
+
+/// A complete scan report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanReport {
+    pub schema_version: String,
+    pub scanner: String,
+    pub metadata: ScanMetadata,
+    pub hosts: Vec<HostReport>,
+}
+
+/// Information about the scan run itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanMetadata {
+    /// Version of rmap that produced the report
+    pub version: String,
+    /// The command line, program name included
+    pub command_line: Vec<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub elapsed_seconds: f64,
+    /// The scan was interrupted and only holds the results gathered until then
+    #[serde(default)]
+    pub partial: bool,
+    /// The ports were given by the user, so every one of them is listed in every state
+    #[serde(default)]
+    pub ports_explicitly_specified: bool,
+    /// The effective configuration after merging the config file and CLI arguments
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostStatus {
+    /// At least one probe was answered
+    Up,
+    /// No probe was answered, the host may be down or fully filtered
+    Unknown,
+}
+
+impl HostStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HostStatus::Up => "up",
+            HostStatus::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostAddress {
+    pub addr: String,
+    /// `ipv4` or `ipv6`
+    pub addr_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hostname {
+    pub name: String,
+    /// `user` for names given as a target, `PTR` for names found by reverse DNS
+    pub source: String,
+}
+
+/// Results for one scanned address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostReport {
+    /// The target as given on the command line or in the config file
+    pub target: String,
+    pub status: HostStatus,
+    pub addresses: Vec<HostAddress>,
+    pub hostnames: Vec<Hostname>,
+    /// Ports of every protocol, ordered by protocol then port number
+    pub ports: Vec<PortReport>,
+    /// Results of scripts run against the host rather than a port
+    pub scripts: Vec<ScriptResult>,
+    pub web: Vec<WebEnumResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+/// The state of one port
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortReport {
+    pub protocol: Protocol,
+    pub port: u16,
+    pub state: PortState,
+    pub reason: PortReason,
+    /// Probes sent before the state was decided, retries included
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    pub service: Option<ServiceInfo>,
+    pub scripts: Vec<ScriptResult>,
+}
+
+/// Reports written before attempts were recorded only sent one probe per port
+fn default_attempts() -> u32 {
+    1
+}
+
+/// Replaces the values of script arguments in the report, they may hold credentials
+const REDACTED: &str = "<redacted>";
+
+/// The configuration as written to reports, with the script argument values hidden
+fn redacted_config(config: &Config) -> serde_json::Value {
+    let mut config = config.clone();
+    for value in config.script_args.values_mut() {
+        *value = REDACTED.to_string();
+    }
+    serde_json::to_value(config).unwrap_or_default()
+}
+
+impl ScanReport {
+    /// Start a report for a scan beginning now
+    pub fn new(config: &Config) -> ScanReport {
+        let now = Utc::now();
+        ScanReport {
+            schema_version: SCHEMA_VERSION.to_string(),
+            scanner: "rmap".to_string(),
+            metadata: ScanMetadata {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                command_line: std::env::args().collect(),
+                start_time: now,
+                end_time: now,
+                elapsed_seconds: 0.0,
+                partial: false,
+                ports_explicitly_specified: config.ports_explicitly_specified,
+                config: redacted_config(config),
+            },
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Add hosts, merging results of other protocols for targets already in the report
+    pub fn add_hosts(&mut self, hosts: Vec<HostReport>) {
+        for host in hosts {
+            match self.hosts.iter_mut().find(|existing| {
+                existing.target == host.target && existing.addresses == host.addresses
+            }) {
+                Some(existing) => existing.merge(host),
+                None => self.hosts.push(host),
+            }
+        }
+    }
+
+    /// Record the end time of the scan
+    pub fn finish(&mut self) {
+        self.metadata.end_time = Utc::now();
+        let elapsed = self.metadata.end_time - self.metadata.start_time;
+        self.metadata.elapsed_seconds = elapsed.num_milliseconds() as f64 / 1000.0;
+    }
+
+    /// Read a report previously written with [`ScanReport::write_json`]
+    pub fn from_file(path: &str) -> Result<ScanReport, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let report: ScanReport = serde_json::from_str(&content)?;
+        Ok(report)
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+impl HostReport {
+    /// Create an empty report for `address`, scanned as `target`
+    pub fn new(target: &str, address: &str) -> HostReport {
+        let addr_type = match address.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => "ipv6",
+            _ => "ipv4",
+        };
+        let mut hostnames = Vec::new();
+        if target != address {
+            hostnames.push(Hostname {
+                name: target.to_string(),
+                source: "user".to_string(),
+            });
+        }
+
+        HostReport {
+            target: target.to_string(),
+            status: HostStatus::Unknown,
+            addresses: vec![HostAddress {
+                addr: address.to_string(),
+                addr_type: addr_type.to_string(),
+            }],
+            hostnames,
+            ports: Vec::new(),
+            scripts: Vec::new(),
+            web: Vec::new(),
+        }
+    }
+
+    /// The primary address of the host
+    pub fn address(&self) -> &str {
+        self.addresses
+            .first()
+            .map(|address| address.addr.as_str())
+            .unwrap_or_default()
+    }
+
+    /// The first name reverse DNS found for the host
+    pub fn ptr_name(&self) -> Option<&str> {
+        self.hostnames
+            .iter()
+            .find(|hostname| hostname.source == "PTR")
+            .map(|hostname| hostname.name.as_str())
+    }
+
+    /// Add a name found for the host, unless it is already known from the same source
+    pub fn add_hostname(&mut self, name: &str, source: &str) {
+        let hostname = Hostname {
+            name: name.to_string(),
+            source: source.to_string(),
+        };
+        if !self.hostnames.contains(&hostname) {
+            self.hostnames.push(hostname);
+        }
+    }
+
+    /// Add port results, keeping ports sorted and the host status current
+    pub fn add_ports(&mut self, ports: Vec<PortReport>) {
+        self.ports.extend(ports);
+        self.ports.sort_by_key(|port| (port.protocol, port.port));
+        if self.ports.iter().any(|port| port.reason.is_reply()) {
+            self.status = HostStatus::Up;
+        }
+    }
+
+    fn merge(&mut self, other: HostReport) {
+        for hostname in other.hostnames {
+            self.add_hostname(&hostname.name, &hostname.source);
+        }
+        self.add_ports(other.ports);
+        self.scripts.extend(other.scripts);
+        self.web.extend(other.web);
+    }
+}
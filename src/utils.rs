@@ -1,5 +1,58 @@
 use std::net::IpAddr;
+use std::ops::RangeInclusive;
 
 pub fn valid_ip(ip: &str) -> bool {
     ip.parse::<IpAddr>().is_ok()
 }
+
+/// Parse a port specification such as `22,80,8000-8100` into port numbers
+///
+/// Invalid entries are skipped.
+pub fn parse_ports(ports_str: &str) -> Vec<u16> {
+    ports_str
+        .split(',')
+        .filter_map(|part| parse_port_entry(part).ok())
+        .flatten()
+        .collect()
+}
+
+/// Check a port specification, naming the first entry that is not a port or a port range
+pub fn check_ports(ports_str: &str) -> Result<(), String> {
+    for part in ports_str.split(',') {
+        parse_port_entry(part)?;
+    }
+    Ok(())
+}
+
+/// Parse one entry of a port specification, a port or a range like `1-1024`
+fn parse_port_entry(part: &str) -> Result<RangeInclusive<u16>, String> {
+    let part = part.trim();
+    let parse = |port: &str| {
+        port.trim()
+            .parse::<u16>()
+            .map_err(|_| format!("'{}' is not a port number between 0 and 65535", port.trim()))
+    };
+    match part.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if start > end {
+                return Err(format!("port range {} starts after it ends", part));
+            }
+            Ok(start..=end)
+        }
+        None => parse(part).map(|port| port..=port),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_parse_like_they_are_checked() {
+        assert_eq!(parse_ports("80 - 82, 443"), [80, 81, 82, 443]);
+        assert_eq!(parse_ports("22,x,90-80,8080"), [22, 8080]);
+        assert!(check_ports("80 - 82, 443").is_ok());
+        assert!(check_ports("90-80").is_err());
+    }
+}